@@ -0,0 +1,231 @@
+// Runs a ROM without any frontend, as fast as possible, and prints its final screen. Meant for
+// regression tests: pipe the output to a file and compare it with a known good run.
+
+use std::env;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::PathBuf;
+use std::process;
+use chip8rs::args::{positive, rom_exit_status, Rng, EXIT_DATA, EXIT_FAULT, EXIT_IO, EXIT_USAGE};
+use chip8rs::dump;
+use chip8rs::movie::Movie;
+use chip8rs::quirks::Quirks;
+use chip8rs::rom::Rom;
+use chip8rs::scheduler::{Scheduler, DEFAULT_IPS};
+use chip8rs::script::Script;
+use chip8rs::trace::{TraceFilter, TraceFormat, Tracer};
+use chip8rs::VM;
+
+const DEFAULT_FRAMES: u64 = 600;
+
+const USAGE: &str = "\
+usage: chip8rs-headless [options] <rom>
+
+Runs <rom> until it halts, spins on a jump to itself, or hits the frame limit, then prints the
+screen as ASCII art followed by its hash.
+
+options:
+  --frames <n>          frame limit (default 600)
+  --input <file>        scripted input, lines like \"frame 120: press 5 for 10 frames\"
+  --movie <file>        replay a movie instead, checking it for desyncs
+  --ips <n>             instructions per second (default 500)
+  --quirks <preset>     chip8rs, vip, chip48, schip or xo-chip (default: per mode and roms.json)
+  --seed <n>            seed for the random number generator (default 0)
+  --rng <rng>           splitmix, or vip:<file> for the COSMAC VIP's algorithm, reading the
+                        512 byte interpreter image in <file> (default splitmix)
+  --pbm <file>          also write the screen as a PBM image
+  --trace <file>        log every instruction with the registers before it, - for stderr
+  --trace-format <f>    text or binary (default text)
+  --trace-pc <a-b>      only trace instructions at addresses a to b, e.g. 0x200-0x2ff
+  --trace-kind <list>   only trace these mnemonics, e.g. DRW,LD
+  --trace-frames <a-b>  only trace frames a to b, counting from 0
+  -h, --help            print this help
+";
+
+struct Options {
+    rom: PathBuf,
+    frames: u64,
+    input: Option<PathBuf>,
+    movie: Option<PathBuf>,
+    ips: u32,
+    quirks: Option<Quirks>,
+    seed: u64,
+    rng: Rng,
+    pbm: Option<PathBuf>,
+    trace: Option<PathBuf>,
+    trace_format: TraceFormat,
+    trace_filter: TraceFilter,
+}
+
+fn parse(args: Vec<String>) -> Result<Option<Options>, String> {
+    let mut options = Options {
+        rom: PathBuf::new(),
+        frames: DEFAULT_FRAMES,
+        input: None,
+        movie: None,
+        ips: DEFAULT_IPS,
+        quirks: None,
+        seed: 0,
+        rng: Rng::SplitMix64,
+        pbm: None,
+        trace: None,
+        trace_format: TraceFormat::Text,
+        trace_filter: TraceFilter::default(),
+    };
+    let mut rom = None;
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or(format!("{} needs a value", arg));
+        let number = |value: String| value.parse::<u64>().map_err(|_| format!("{}: invalid number {:?}", arg, value));
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(None),
+            "--frames" => options.frames = number(value()?)?,
+            "--input" => options.input = Some(PathBuf::from(value()?)),
+            "--movie" => options.movie = Some(PathBuf::from(value()?)),
+            "--ips" => options.ips = positive(&arg, &value()?)?,
+            "--quirks" => {
+                let preset = value()?;
+                options.quirks = Some(Quirks::preset(&preset).ok_or(format!("unknown quirks preset {:?}", preset))?);
+            }
+            "--seed" => options.seed = number(value()?)?,
+            "--rng" => options.rng = Rng::parse(&value()?)?,
+            "--pbm" => options.pbm = Some(PathBuf::from(value()?)),
+            "--trace" => options.trace = Some(PathBuf::from(value()?)),
+            "--trace-format" => {
+                let name = value()?;
+                options.trace_format = TraceFormat::from_name(&name).ok_or(format!("unknown trace format {:?}", name))?;
+            }
+            "--trace-pc" => {
+                let (start, end) = range(&arg, &value()?)?;
+                options.trace_filter.pc = Some(start as usize..=end as usize);
+            }
+            "--trace-kind" => {
+                let kinds = value()?.split(',').map(|kind| kind.trim().to_string()).collect();
+                options.trace_filter.kinds = Some(kinds);
+            }
+            "--trace-frames" => {
+                let (start, end) = range(&arg, &value()?)?;
+                options.trace_filter.frames = Some(start..=end);
+            }
+            _ if arg.starts_with('-') && arg != "-" => return Err(format!("unknown option {}", arg)),
+            _ if rom.is_none() => rom = Some(PathBuf::from(&arg)),
+            _ => return Err(format!("unexpected argument {:?}", arg)),
+        }
+    }
+    options.rom = rom.ok_or("no ROM given")?;
+    Ok(Some(options))
+}
+
+// Parses "a-b", each number decimal or hex with 0x.
+fn range(name: &str, value: &str) -> Result<(u64, u64), String> {
+    let number = |s: &str| match s.trim().strip_prefix("0x") {
+        Some(hex) => u64::from_str_radix(hex, 16).ok(),
+        None => s.trim().parse().ok(),
+    };
+    let mut bounds = value.splitn(2, '-');
+    match (bounds.next().and_then(number), bounds.next().and_then(number)) {
+        (Some(start), Some(end)) if start <= end => Ok((start, end)),
+        _ => Err(format!("{}: expected a range like 10-20, got {:?}", name, value)),
+    }
+}
+
+fn fail(what: &str, err: impl std::fmt::Display, status: i32) -> ! {
+    eprintln!("{}: {}", what, err);
+    process::exit(status);
+}
+
+fn main() {
+    let options = match parse(env::args().skip(1).collect()) {
+        Ok(Some(options)) => options,
+        Ok(None) => {
+            print!("{}", USAGE);
+            return;
+        }
+        Err(err) => fail("chip8rs-headless", format!("{}\n\n{}", err, USAGE), EXIT_USAGE),
+    };
+    let rom_name = options.rom.display().to_string();
+    let rom = Rom::load(&options.rom)
+        .and_then(|rom| {
+            rom.check_size(rom.mode())?;
+            Ok(rom)
+        })
+        .unwrap_or_else(|err| {
+            let status = rom_exit_status(&err);
+            fail(&rom_name, err, status)
+        });
+
+    let tracer = options.trace.as_ref().map(|path| {
+        let out: Box<dyn Write> = match path.to_str() {
+            // stdout is taken by the screen
+            Some("-") => Box::new(io::stderr()),
+            _ => Box::new(BufWriter::new(
+                File::create(path).unwrap_or_else(|err| fail(&path.display().to_string(), err, EXIT_IO)),
+            )),
+        };
+        Tracer::new(out, options.trace_format, options.trace_filter.clone())
+    });
+
+    let mut vm = match options.movie {
+        Some(ref path) => {
+            let movie = Movie::load(path).unwrap_or_else(|err| fail(&path.display().to_string(), err, EXIT_DATA));
+            let mut vm = movie.start(&rom.bytes).unwrap_or_else(|err| fail(&path.display().to_string(), err, EXIT_DATA));
+            if let Some(tracer) = tracer {
+                vm.set_tracer(tracer);
+            }
+            if let Err(err) = movie.replay(&mut vm) {
+                fail(&path.display().to_string(), err, EXIT_FAULT);
+            }
+            eprintln!("replayed {} frames", movie.frames());
+            vm
+        }
+        None => run(&options, &rom, tracer),
+    };
+    if let Some(tracer) = vm.take_tracer() {
+        if let Err(err) = tracer.finish() {
+            fail(&options.trace.unwrap_or_default().display().to_string(), err, EXIT_IO);
+        }
+    }
+
+    let output = vm.output();
+    if let Some(ref path) = options.pbm {
+        if let Err(err) = fs::write(path, dump::pbm(output.vram, output.width, output.height)) {
+            fail(&path.display().to_string(), err, EXIT_IO);
+        }
+    }
+    print!("{}", dump::ascii(output.vram, output.width, output.height));
+    println!("hash {:016x}", vm.frame_hash());
+}
+
+// Runs the ROM with the scripted input, until it's done or the frame limit is reached.
+fn run(options: &Options, rom: &Rom, tracer: Option<Tracer>) -> VM {
+    let script = match options.input {
+        Some(ref path) => fs::read_to_string(path)
+            .map_err(|err| err.to_string())
+            .and_then(|text| Script::parse(&text).map_err(|err| err.to_string()))
+            .unwrap_or_else(|err| fail(&path.display().to_string(), err, EXIT_DATA)),
+        None => Script::default(),
+    };
+    let mode = rom.mode();
+    let listed_path = options.rom.with_file_name(rom.name.file_name().unwrap_or_default());
+    let quirks = options.quirks.unwrap_or_else(|| chip8rs::roms::quirks_for(&listed_path, mode.default_quirks()));
+    let rng = options.rng.open(options.seed).unwrap_or_else(|(err, status)| fail("--rng", err, status));
+    let mut vm = VM::with_rng(mode, quirks, rng);
+    vm.load(&rom.bytes);
+    if let Some(tracer) = tracer {
+        vm.set_tracer(tracer);
+    }
+
+    let mut scheduler = Scheduler::new(options.ips);
+    for frame in 0..options.frames {
+        if let Err(err) = vm.run_frame(script.keypad(frame), scheduler.instructions_this_frame()) {
+            fail(&options.rom.display().to_string(), err, EXIT_FAULT);
+        }
+        scheduler.advance();
+        if vm.finished() {
+            eprintln!("finished at frame {}", frame + 1);
+            return vm;
+        }
+    }
+    eprintln!("stopped at the frame limit");
+    vm
+}
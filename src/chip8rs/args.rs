@@ -0,0 +1,73 @@
+// Shared by the command lines of chip8rs and chip8rs-headless.
+
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use super::random::{CosmacVip, Random, SplitMix64};
+use super::rom::RomError;
+
+// Exit statuses, following sysexits.h where one fits.
+pub const EXIT_FAULT: i32 = 1;  // the ROM crashed the VM
+pub const EXIT_USAGE: i32 = 64;
+pub const EXIT_DATA: i32 = 65;  // the ROM or save state can't be used
+pub const EXIT_NO_INPUT: i32 = 66;  // the ROM doesn't exist
+pub const EXIT_IO: i32 = 74;  // the ROM couldn't be read
+
+// Exit status for a ROM which can't be run.
+pub fn rom_exit_status(err: &RomError) -> i32 {
+    match err {
+        RomError::NotFound => EXIT_NO_INPUT,
+        RomError::Io(_) => EXIT_IO,
+        RomError::TooLarge { .. } | RomError::Archive(_) => EXIT_DATA,
+    }
+}
+
+// Source of Cxkk, as picked with --rng.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Rng {
+    SplitMix64,
+    // The COSMAC VIP's own algorithm, reading the interpreter image in this file
+    CosmacVip(PathBuf),
+}
+
+impl Rng {
+    // Parses "splitmix" or "vip:<file>".
+    pub fn parse(value: &str) -> Result<Rng, String> {
+        match value {
+            "splitmix" => Ok(Rng::SplitMix64),
+            _ => match value.strip_prefix("vip:") {
+                Some(path) if !path.is_empty() => Ok(Rng::CosmacVip(PathBuf::from(path))),
+                _ => Err(format!("unknown random number generator {:?}, expected splitmix or vip:<file>", value)),
+            },
+        }
+    }
+
+    // Creates the source, seeded with `seed`, which the VIP's algorithm cuts to 16 bits.
+    pub fn open(&self, seed: u64) -> Result<Box<dyn Random>, (String, i32)> {
+        let path = match self {
+            Rng::SplitMix64 => return Ok(Box::new(SplitMix64::new(seed))),
+            Rng::CosmacVip(path) => path,
+        };
+        let name = path.display().to_string();
+        let interpreter = fs::read(path).map_err(|err| {
+            let status = if err.kind() == io::ErrorKind::NotFound { EXIT_NO_INPUT } else { EXIT_IO };
+            (format!("{}: {}", name, err), status)
+        })?;
+        match CosmacVip::new(&interpreter, seed as u16) {
+            Some(vip) => Ok(Box::new(vip)),
+            None => Err((format!("{}: expected a 512 byte interpreter image, got {} bytes", name, interpreter.len()), EXIT_DATA)),
+        }
+    }
+}
+
+// Parses the value of option `name` as a number from 1 to u32::MAX.
+pub fn positive(name: &str, value: &str) -> Result<u32, String> {
+    match value.parse() {
+        Ok(n) if n > 0 => Ok(n),
+        _ => Err(format!("{}: expected a positive number, got {:?}", name, value)),
+    }
+}
+
+#[cfg(test)]
+#[path = "./args_test.rs"]
+mod args_test;
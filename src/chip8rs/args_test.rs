@@ -0,0 +1,31 @@
+use super::*;
+use std::io;
+
+
+#[test]
+fn test_positive() {
+    assert_eq!(positive("--ips", "600"), Ok(600));
+    assert_eq!(positive("--ips", "4294967295"), Ok(u32::MAX));
+    for bad in &["0", "-1", "4294967296", "fast"] {
+        assert_eq!(positive("--ips", bad), Err(format!("--ips: expected a positive number, got {:?}", bad)));
+    }
+}
+
+#[test]
+fn test_rom_exit_status() {
+    assert_eq!(rom_exit_status(&RomError::NotFound), EXIT_NO_INPUT);
+    assert_eq!(rom_exit_status(&RomError::Io(io::Error::from(io::ErrorKind::PermissionDenied))), EXIT_IO);
+}
+
+#[test]
+fn test_rng() {
+    assert_eq!(Rng::parse("splitmix"), Ok(Rng::SplitMix64));
+    assert_eq!(Rng::parse("vip:vip.bin"), Ok(Rng::CosmacVip(PathBuf::from("vip.bin"))));
+    assert!(Rng::parse("vip:").is_err());
+    assert!(Rng::parse("vip").is_err());
+
+    let missing = Rng::CosmacVip(PathBuf::from("no/such/interpreter.bin"));
+    assert_eq!(missing.open(0).err().map(|(_, status)| status), Some(EXIT_NO_INPUT));
+    let short = Rng::CosmacVip(PathBuf::from("Cargo.toml"));
+    assert_eq!(short.open(0).err().map(|(_, status)| status), Some(EXIT_DATA));
+}
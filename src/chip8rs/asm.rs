@@ -0,0 +1,645 @@
+use std::collections::HashMap;
+use std::error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use super::instruction::{Addr, Byte, Instruction, Register};
+use super::vm::PROGRAM_START;
+
+// Highest address a ROM can reach, on XO-CHIP.
+const MEMORY_END: i64 = 0x10000;
+const MAX_INCLUDE_DEPTH: usize = 16;
+
+#[derive(Debug)]
+pub enum AsmError {
+    // A source or include file couldn't be read
+    Io(PathBuf, io::Error),
+    // Columns count characters from 1
+    Syntax { path: PathBuf, line: usize, column: usize, message: String },
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AsmError::Io(path, err) => write!(f, "{}: {}", path.display(), err),
+            AsmError::Syntax { path, line, column, message } => {
+                write!(f, "{}:{}:{}: {}", path.display(), line, column, message)
+            }
+        }
+    }
+}
+
+impl error::Error for AsmError {}
+
+// Where an instruction was assembled from, for source level debugging.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct SourceLine {
+    pub addr: usize,
+    pub path: PathBuf,
+    pub line: usize,
+}
+
+// Assembles the file at `path` into a ROM to be loaded at 0x200.
+pub fn assemble_file(path: &Path) -> Result<Vec<u8>, AsmError> {
+    assemble_file_with_lines(path).map(|(rom, _)| rom)
+}
+
+// Like `assemble_file`, also returning the source line of every instruction, by address.
+pub fn assemble_file_with_lines(path: &Path) -> Result<(Vec<u8>, Vec<SourceLine>), AsmError> {
+    let source = fs::read_to_string(path).map_err(|err| AsmError::Io(path.to_path_buf(), err))?;
+    let mut assembler = Assembler::new();
+    assembler.read(&source, path, 0)?;
+    let rom = assembler.emit()?;
+    Ok((rom, assembler.lines()))
+}
+
+// Assembles `source` into a ROM to be loaded at 0x200. `path` names the source in errors, and
+// includes are looked up next to it.
+//
+// Source is one statement per line, `;` starting a comment:
+//
+//     SPEED equ 2           ; constants, which can only use names defined above them
+//     start:                ; labels, optionally followed by a statement
+//         LD I, sprite
+//         ADD V0, SPEED + 1
+//         JP start
+//         org 0x300         ; moves the output address
+//     sprite: db 0x81, "A"  ; bytes and strings
+//         dw 0x1234         ; big endian words
+//         include "lib.s"
+//
+// Mnemonics are those `Instruction` displays, case insensitive. Numbers are decimal, hex with 0x
+// or binary with 0b, and can be added and subtracted.
+pub fn assemble(source: &str, path: &Path) -> Result<Vec<u8>, AsmError> {
+    let mut assembler = Assembler::new();
+    assembler.read(source, path, 0)?;
+    assembler.emit()
+}
+
+#[derive(Clone, PartialEq, Debug)]
+enum Token {
+    Name(String),
+    Number(i64),
+    Str(Vec<u8>),
+    Punct(char),
+}
+
+#[derive(Clone, Debug)]
+struct Lexeme {
+    token: Token,
+    column: usize,
+}
+
+// A sum of numbers and names.
+#[derive(Clone, Debug)]
+struct Expr {
+    terms: Vec<(i64, Term)>,  // sign and term
+    column: usize,
+}
+
+#[derive(Clone, Debug)]
+enum Term {
+    Number(i64),
+    Name(String, usize),
+}
+
+#[derive(Clone, Debug)]
+enum Operand {
+    Register(Register),
+    Range(Register, Register),  // "Vx - Vy"
+    I,
+    IndirectI,  // "[I]"
+    Dt,
+    St,
+    K,
+    F,
+    Hf,
+    B,
+    R,
+    Long(Expr),
+    Value(Expr),
+}
+
+#[derive(Clone, Debug)]
+enum Data {
+    Expr(Expr),
+    Str(Vec<u8>),
+}
+
+#[derive(Debug)]
+enum Kind {
+    Instruction { mnemonic: String, operands: Vec<Operand> },
+    Bytes(Vec<Data>),
+    Words(Vec<Expr>),
+}
+
+impl Kind {
+    // Bytes taken in the ROM.
+    fn size(&self) -> usize {
+        match self {
+            Kind::Instruction { operands, .. } if operands.iter().any(|op| matches!(op, Operand::Long(_))) => 4,
+            Kind::Instruction { .. } => 2,
+            Kind::Bytes(items) => items
+                .iter()
+                .map(|item| match item {
+                    Data::Str(s) => s.len(),
+                    Data::Expr(_) => 1,
+                })
+                .sum(),
+            Kind::Words(items) => items.len() * 2,
+        }
+    }
+}
+
+// A statement placed at its address, waiting for the labels to be known.
+#[derive(Debug)]
+struct Statement {
+    addr: usize,
+    location: Location,
+    kind: Kind,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Location {
+    file: usize,  // index into `Assembler::files`
+    line: usize,
+    column: usize,
+}
+
+// Error at a column of the line being processed.
+type LineError = (usize, String);
+
+struct Assembler {
+    files: Vec<PathBuf>,
+    symbols: HashMap<String, i64>,
+    statements: Vec<Statement>,
+    addr: usize,
+}
+
+impl Assembler {
+    fn new() -> Self {
+        Assembler {
+            files: Vec::new(),
+            symbols: HashMap::new(),
+            statements: Vec::new(),
+            addr: PROGRAM_START,
+        }
+    }
+
+    fn error(&self, location: Location, message: String) -> AsmError {
+        AsmError::Syntax {
+            path: self.files[location.file].clone(),
+            line: location.line,
+            column: location.column,
+            message,
+        }
+    }
+
+    // First pass: places every statement and defines the labels and constants.
+    fn read(&mut self, source: &str, path: &Path, depth: usize) -> Result<(), AsmError> {
+        let file = self.files.len();
+        self.files.push(path.to_path_buf());
+        for (n, line) in source.lines().enumerate() {
+            let location = |column| Location { file, line: n + 1, column };
+            let result = tokenize(line).and_then(|tokens| self.read_line(&tokens, location(1)));
+            match result {
+                Ok(Some((include, column))) => {
+                    if depth >= MAX_INCLUDE_DEPTH {
+                        return Err(self.error(location(column), "includes nested too deep".to_string()));
+                    }
+                    let included = path.parent().unwrap_or_else(|| Path::new("")).join(include);
+                    let source = fs::read_to_string(&included).map_err(|err| AsmError::Io(included.clone(), err))?;
+                    self.read(&source, &included, depth + 1)?;
+                }
+                Ok(None) => {}
+                Err((column, message)) => return Err(self.error(location(column), message)),
+            }
+        }
+        Ok(())
+    }
+
+    // Reads one line, returning the file it includes, if any.
+    fn read_line(&mut self, tokens: &[Lexeme], location: Location) -> Result<Option<(String, usize)>, LineError> {
+        let mut tokens = tokens;
+        if let [Lexeme { token: Token::Name(name), column }, Lexeme { token: Token::Punct(':'), .. }, rest @ ..] = tokens {
+            self.define(name, self.addr as i64, *column)?;
+            tokens = rest;
+        }
+        let (name, column, rest) = match tokens {
+            [] => return Ok(None),
+            [Lexeme { token: Token::Name(name), column }, rest @ ..] => (name, *column, rest),
+            [other, ..] => return Err((other.column, "expected a label, directive or instruction".to_string())),
+        };
+        if let [Lexeme { token: Token::Name(equ), .. }, value @ ..] = rest {
+            if equ.eq_ignore_ascii_case("equ") {
+                let value = self.evaluate(&parse_expr(value, column)?)?;
+                self.define(name, value, column)?;
+                return Ok(None);
+            }
+        }
+
+        let directive = name.to_ascii_lowercase();
+        let kind = match directive.as_str() {
+            "org" => {
+                let expr = parse_expr(rest, column)?;
+                let addr = self.evaluate(&expr)?;
+                if addr < PROGRAM_START as i64 || addr >= MEMORY_END {
+                    return Err((expr.column, format!("org 0x{:X} is outside 0x200 to 0xFFFF", addr)));
+                }
+                self.addr = addr as usize;
+                return Ok(None);
+            }
+            "include" => match rest {
+                [Lexeme { token: Token::Str(file), column }] => {
+                    return Ok(Some((String::from_utf8_lossy(file).into_owned(), *column)));
+                }
+                _ => return Err((column, "include needs a quoted file name".to_string())),
+            },
+            "db" => {
+                let items = split_operands(rest)?
+                    .into_iter()
+                    .map(|group| match group {
+                        [Lexeme { token: Token::Str(s), .. }] => Ok(Data::Str(s.clone())),
+                        _ => Ok(Data::Expr(parse_expr(group, column)?)),
+                    })
+                    .collect::<Result<Vec<_>, LineError>>()?;
+                Kind::Bytes(items)
+            }
+            "dw" => {
+                let items = split_operands(rest)?
+                    .into_iter()
+                    .map(|group| parse_expr(group, column))
+                    .collect::<Result<Vec<_>, LineError>>()?;
+                Kind::Words(items)
+            }
+            _ => {
+                let operands = split_operands(rest)?
+                    .into_iter()
+                    .map(|group| parse_operand(group, column))
+                    .collect::<Result<Vec<_>, LineError>>()?;
+                Kind::Instruction { mnemonic: directive.to_ascii_uppercase(), operands }
+            }
+        };
+        let size = kind.size();
+        if self.addr as i64 + size as i64 > MEMORY_END {
+            return Err((column, "past the end of memory".to_string()));
+        }
+        self.statements.push(Statement {
+            addr: self.addr,
+            location: Location { column, ..location },
+            kind,
+        });
+        self.addr += size;
+        Ok(None)
+    }
+
+    fn define(&mut self, name: &str, value: i64, column: usize) -> Result<(), LineError> {
+        if reserved(name) {
+            return Err((column, format!("{} is reserved", name)));
+        }
+        if self.symbols.insert(name.to_string(), value).is_some() {
+            return Err((column, format!("{} is already defined", name)));
+        }
+        Ok(())
+    }
+
+    fn evaluate(&self, expr: &Expr) -> Result<i64, LineError> {
+        let mut value = 0i64;
+        for (sign, term) in &expr.terms {
+            let term = match term {
+                Term::Number(n) => *n,
+                Term::Name(name, column) => *self
+                    .symbols
+                    .get(name)
+                    .ok_or_else(|| (*column, format!("undefined name {}", name)))?,
+            };
+            value = value.wrapping_add(sign * term);
+        }
+        Ok(value)
+    }
+
+    fn lines(&self) -> Vec<SourceLine> {
+        let mut lines: Vec<SourceLine> = self
+            .statements
+            .iter()
+            .filter(|s| matches!(s.kind, Kind::Instruction { .. }))
+            .map(|s| SourceLine { addr: s.addr, path: self.files[s.location.file].clone(), line: s.location.line })
+            .collect();
+        lines.sort_by_key(|line| line.addr);
+        lines
+    }
+
+    // Second pass: encodes the statements now that every label is known.
+    fn emit(&self) -> Result<Vec<u8>, AsmError> {
+        let end = self.statements.iter().map(|s| s.addr + s.kind.size()).max().unwrap_or(PROGRAM_START);
+        let mut rom = vec![0; end - PROGRAM_START];
+        let mut written = vec![false; rom.len()];
+        for statement in &self.statements {
+            let bytes = self.encode(statement).map_err(|(column, message)| {
+                self.error(Location { column, ..statement.location }, message)
+            })?;
+            let start = statement.addr - PROGRAM_START;
+            if let Some(n) = written[start..start + bytes.len()].iter().position(|&w| w) {
+                let message = format!("overlaps what is already at 0x{:04X}", statement.addr + n);
+                return Err(self.error(statement.location, message));
+            }
+            rom[start..start + bytes.len()].copy_from_slice(&bytes);
+            written[start..start + bytes.len()].iter_mut().for_each(|w| *w = true);
+        }
+        Ok(rom)
+    }
+
+    fn encode(&self, statement: &Statement) -> Result<Vec<u8>, LineError> {
+        match &statement.kind {
+            Kind::Bytes(items) => {
+                let mut bytes = Vec::new();
+                for item in items {
+                    match item {
+                        Data::Str(s) => bytes.extend_from_slice(s),
+                        Data::Expr(expr) => bytes.push(self.byte(expr)?),
+                    }
+                }
+                Ok(bytes)
+            }
+            Kind::Words(items) => {
+                let mut bytes = Vec::new();
+                for expr in items {
+                    bytes.extend_from_slice(&self.ranged(expr, -0x8000, 0xFFFF, "word")?.to_be_bytes()[6..]);
+                }
+                Ok(bytes)
+            }
+            Kind::Instruction { mnemonic, operands } => {
+                let column = statement.location.column;
+                let instruction = self.instruction(mnemonic, operands, column)?;
+                let mut bytes = instruction.encode().to_be_bytes().to_vec();
+                if let Instruction::LoadLongI(addr) = instruction {
+                    bytes.extend_from_slice(&addr.to_be_bytes());
+                }
+                Ok(bytes)
+            }
+        }
+    }
+
+    fn ranged(&self, expr: &Expr, min: i64, max: i64, what: &str) -> Result<i64, LineError> {
+        let value = self.evaluate(expr)?;
+        if value < min || value > max {
+            return Err((expr.column, format!("{} {} doesn't fit in a {}", value, show(value), what)));
+        }
+        Ok(value)
+    }
+
+    // Negative bytes are stored in two's complement.
+    fn byte(&self, expr: &Expr) -> Result<Byte, LineError> {
+        Ok(self.ranged(expr, -0x80, 0xFF, "byte")? as Byte)
+    }
+
+    fn nibble(&self, expr: &Expr) -> Result<Byte, LineError> {
+        Ok(self.ranged(expr, 0, 0xF, "nibble")? as Byte)
+    }
+
+    fn addr(&self, expr: &Expr) -> Result<Addr, LineError> {
+        Ok(self.ranged(expr, 0, 0xFFF, "12 bit address")? as Addr)
+    }
+
+    fn instruction(&self, mnemonic: &str, operands: &[Operand], column: usize) -> Result<Instruction, LineError> {
+        use self::Operand::*;
+        let instruction = match (mnemonic, operands) {
+            ("CLS", []) => Instruction::Clear,
+            ("RET", []) => Instruction::Return,
+            ("SCD", [Value(n)]) => Instruction::ScrollDown(self.nibble(n)?),
+            ("SCR", []) => Instruction::ScrollRight,
+            ("SCL", []) => Instruction::ScrollLeft,
+            ("EXIT", []) => Instruction::Exit,
+            ("LOW", []) => Instruction::LowRes,
+            ("HIGH", []) => Instruction::HighRes,
+            ("SYS", [Value(a)]) => Instruction::Sys(self.addr(a)?),
+            ("JP", [Value(a)]) => Instruction::Jump(self.addr(a)?),
+            ("JP", [Register(0), Value(a)]) => Instruction::LongJump(self.addr(a)?),
+            ("CALL", [Value(a)]) => Instruction::Call(self.addr(a)?),
+            ("SE", [Register(x), Value(k)]) => Instruction::SkipEqualK(*x, self.byte(k)?),
+            ("SE", [Register(x), Register(y)]) => Instruction::SkipEqual(*x, *y),
+            ("SNE", [Register(x), Value(k)]) => Instruction::SkipNotEqualK(*x, self.byte(k)?),
+            ("SNE", [Register(x), Register(y)]) => Instruction::SkipNotEqual(*x, *y),
+            ("SAVE", [Range(x, y)]) => Instruction::StoreRange(*x, *y),
+            ("LOAD", [Range(x, y)]) => Instruction::LoadRange(*x, *y),
+            ("LD", [Register(x), Value(k)]) => Instruction::LoadK(*x, self.byte(k)?),
+            ("LD", [Register(x), Register(y)]) => Instruction::Set(*x, *y),
+            ("LD", [I, Value(a)]) => Instruction::LoadI(self.addr(a)?),
+            ("LD", [I, Long(a)]) => Instruction::LoadLongI(self.ranged(a, 0, 0xFFFF, "16 bit address")? as Addr),
+            ("LD", [Register(x), Dt]) => Instruction::GetTimer(*x),
+            ("LD", [Register(x), K]) => Instruction::WaitKey(*x),
+            ("LD", [Dt, Register(x)]) => Instruction::SetTimer(*x),
+            ("LD", [St, Register(x)]) => Instruction::SetSoundTimer(*x),
+            ("LD", [F, Register(x)]) => Instruction::LoadHexGlyph(*x),
+            ("LD", [Hf, Register(x)]) => Instruction::LoadBigHexGlyph(*x),
+            ("LD", [B, Register(x)]) => Instruction::StoreBCD(*x),
+            ("LD", [IndirectI, Register(x)]) => Instruction::StoreRegisters(*x),
+            ("LD", [Register(x), IndirectI]) => Instruction::LoadRegisters(*x),
+            ("LD", [R, Register(x)]) => Instruction::StoreFlags(*x),
+            ("LD", [Register(x), R]) => Instruction::LoadFlags(*x),
+            ("ADD", [Register(x), Value(k)]) => Instruction::AddK(*x, self.byte(k)?),
+            ("ADD", [Register(x), Register(y)]) => Instruction::Add(*x, *y),
+            ("ADD", [I, Register(x)]) => Instruction::AddI(*x),
+            ("OR", [Register(x), Register(y)]) => Instruction::Or(*x, *y),
+            ("AND", [Register(x), Register(y)]) => Instruction::And(*x, *y),
+            ("XOR", [Register(x), Register(y)]) => Instruction::Xor(*x, *y),
+            ("SUB", [Register(x), Register(y)]) => Instruction::Sub(*x, *y),
+            ("SUBN", [Register(x), Register(y)]) => Instruction::SubInv(*x, *y),
+            // Cowgod writes the shifts with an optional Vy
+            ("SHR", [Register(x)]) => Instruction::ShiftRight(*x, *x),
+            ("SHR", [Register(x), Register(y)]) => Instruction::ShiftRight(*x, *y),
+            ("SHL", [Register(x)]) => Instruction::ShiftLeft(*x, *x),
+            ("SHL", [Register(x), Register(y)]) => Instruction::ShiftLeft(*x, *y),
+            ("RND", [Register(x), Value(k)]) => Instruction::Rand(*x, self.byte(k)?),
+            ("DRW", [Register(x), Register(y), Value(n)]) => Instruction::Draw(*x, *y, self.nibble(n)?),
+            ("SKP", [Register(x)]) => Instruction::SkipPressed(*x),
+            ("SKNP", [Register(x)]) => Instruction::SkipNotPressed(*x),
+            ("PLANE", [Value(n)]) => Instruction::Plane(self.nibble(n)?),
+            ("AUDIO", []) => Instruction::LoadAudio,
+            ("PITCH", [Register(x)]) => Instruction::Pitch(*x),
+            _ if MNEMONICS.contains(&mnemonic) => return Err((column, format!("invalid operands for {}", mnemonic))),
+            _ => return Err((column, format!("unknown instruction {}", mnemonic))),
+        };
+        Ok(instruction)
+    }
+}
+
+const MNEMONICS: &[&str] = &[
+    "CLS", "RET", "SCD", "SCR", "SCL", "EXIT", "LOW", "HIGH", "SYS", "JP", "CALL", "SE", "SNE", "SAVE", "LOAD",
+    "LD", "ADD", "OR", "AND", "XOR", "SUB", "SUBN", "SHR", "SHL", "RND", "DRW", "SKP", "SKNP", "PLANE",
+    "AUDIO", "PITCH",
+];
+
+// Names that mean something in operands, and can't be labels or constants.
+fn reserved(name: &str) -> bool {
+    let upper = name.to_ascii_uppercase();
+    register(name).is_some() || ["I", "DT", "ST", "K", "F", "HF", "B", "R", "LONG", "EQU"].contains(&upper.as_str())
+}
+
+fn register(name: &str) -> Option<Register> {
+    let mut chars = name.chars();
+    match (chars.next(), chars.next(), chars.next()) {
+        (Some('V'), Some(x), None) | (Some('v'), Some(x), None) => x.to_digit(16).map(|x| x as Register),
+        _ => None,
+    }
+}
+
+fn show(value: i64) -> String {
+    if value < 0 {
+        format!("(-0x{:X})", value.unsigned_abs())
+    } else {
+        format!("(0x{:X})", value)
+    }
+}
+
+fn tokenize(line: &str) -> Result<Vec<Lexeme>, LineError> {
+    let chars: Vec<char> = line.chars().collect();
+    let mut tokens = Vec::new();
+    let mut n = 0;
+    while n < chars.len() {
+        let c = chars[n];
+        let column = n + 1;
+        let start = n;
+        let token = match c {
+            ';' => break,
+            _ if c.is_whitespace() => {
+                n += 1;
+                continue;
+            }
+            '"' => {
+                n += 1;
+                while n < chars.len() && chars[n] != '"' {
+                    n += 1;
+                }
+                if n == chars.len() {
+                    return Err((column, "unterminated string".to_string()));
+                }
+                n += 1;
+                Token::Str(chars[start + 1..n - 1].iter().collect::<String>().into_bytes())
+            }
+            _ if c.is_ascii_digit() => {
+                while n < chars.len() && (chars[n].is_ascii_alphanumeric() || chars[n] == '_') {
+                    n += 1;
+                }
+                let text: String = chars[start..n].iter().filter(|&&c| c != '_').collect();
+                let lower = text.to_ascii_lowercase();
+                let parsed = match (lower.strip_prefix("0x"), lower.strip_prefix("0b")) {
+                    (Some(hex), _) => i64::from_str_radix(hex, 16),
+                    (_, Some(bin)) => i64::from_str_radix(bin, 2),
+                    _ => lower.parse(),
+                };
+                Token::Number(parsed.map_err(|_| (column, format!("invalid number {}", text)))?)
+            }
+            _ if c.is_alphabetic() || c == '_' || c == '.' => {
+                while n < chars.len() && (chars[n].is_alphanumeric() || chars[n] == '_' || chars[n] == '.') {
+                    n += 1;
+                }
+                Token::Name(chars[start..n].iter().collect())
+            }
+            ',' | ':' | '+' | '-' | '[' | ']' => {
+                n += 1;
+                Token::Punct(c)
+            }
+            _ => return Err((column, format!("unexpected character {:?}", c))),
+        };
+        tokens.push(Lexeme { token, column });
+    }
+    Ok(tokens)
+}
+
+// Splits the operands at commas.
+fn split_operands(tokens: &[Lexeme]) -> Result<Vec<&[Lexeme]>, LineError> {
+    let mut groups = Vec::new();
+    let mut start = 0;
+    for (n, lexeme) in tokens.iter().enumerate() {
+        if lexeme.token == Token::Punct(',') {
+            if n == start {
+                return Err((lexeme.column, "missing operand".to_string()));
+            }
+            groups.push(&tokens[start..n]);
+            start = n + 1;
+        }
+    }
+    match tokens.last() {
+        None => {}
+        Some(last) if start == tokens.len() => return Err((last.column + 1, "missing operand".to_string())),
+        Some(_) => groups.push(&tokens[start..]),
+    }
+    Ok(groups)
+}
+
+fn parse_operand(tokens: &[Lexeme], column: usize) -> Result<Operand, LineError> {
+    let name = |l: &Lexeme| match &l.token {
+        Token::Name(name) => Some(name.to_ascii_uppercase()),
+        _ => None,
+    };
+    let operand = match tokens {
+        [single] => match name(single).as_deref() {
+            Some("I") => Operand::I,
+            Some("DT") => Operand::Dt,
+            Some("ST") => Operand::St,
+            Some("K") => Operand::K,
+            Some("F") => Operand::F,
+            Some("HF") => Operand::Hf,
+            Some("B") => Operand::B,
+            Some("R") => Operand::R,
+            Some(n) => match register(n) {
+                Some(x) => Operand::Register(x),
+                None => Operand::Value(parse_expr(tokens, column)?),
+            },
+            None => Operand::Value(parse_expr(tokens, column)?),
+        },
+        [open, i, close] if open.token == Token::Punct('[') && close.token == Token::Punct(']') => {
+            match name(i).as_deref() {
+                Some("I") => Operand::IndirectI,
+                _ => return Err((i.column, "expected [I]".to_string())),
+            }
+        }
+        [x, dash, y] if dash.token == Token::Punct('-') && name(x).and_then(|n| register(&n)).is_some() => {
+            match (name(x).and_then(|n| register(&n)), name(y).and_then(|n| register(&n))) {
+                (Some(x), Some(y)) => Operand::Range(x, y),
+                _ => return Err((y.column, "expected a register".to_string())),
+            }
+        }
+        [long, rest @ ..] if name(long).as_deref() == Some("LONG") => Operand::Long(parse_expr(rest, long.column)?),
+        _ => Operand::Value(parse_expr(tokens, column)?),
+    };
+    Ok(operand)
+}
+
+fn parse_expr(tokens: &[Lexeme], column: usize) -> Result<Expr, LineError> {
+    let start = tokens.first().map(|l| l.column).unwrap_or(column);
+    let mut terms = Vec::new();
+    let mut sign = 1;
+    let mut expect_term = true;
+    for lexeme in tokens {
+        match (&lexeme.token, expect_term) {
+            (Token::Punct('-'), true) => sign = -sign,
+            (Token::Punct('+'), true) => {}
+            (Token::Number(n), true) => terms.push((sign, Term::Number(*n))),
+            (Token::Name(name), true) if reserved(name) => {
+                return Err((lexeme.column, format!("{} can't be used here", name)));
+            }
+            (Token::Name(name), true) => terms.push((sign, Term::Name(name.clone(), lexeme.column))),
+            (Token::Punct('+'), false) => sign = 1,
+            (Token::Punct('-'), false) => sign = -1,
+            _ => return Err((lexeme.column, "expected a number or a name".to_string())),
+        }
+        if let Token::Punct(_) = lexeme.token {
+            expect_term = true;
+        } else {
+            expect_term = false;
+            sign = 1;
+        }
+    }
+    if expect_term {
+        let column = tokens.last().map(|l| l.column + 1).unwrap_or(start);
+        return Err((column, "expected a number or a name".to_string()));
+    }
+    Ok(Expr { terms, column: start })
+}
+
+#[cfg(test)]
+#[path = "./asm_test.rs"]
+mod asm_test;
@@ -0,0 +1,131 @@
+use super::*;
+use std::env;
+
+
+fn asm(source: &str) -> Result<Vec<u8>, AsmError> {
+    assemble(source, Path::new("test.s"))
+}
+
+// The line, column and message of an assembly error.
+fn error(source: &str) -> (usize, usize, String) {
+    match asm(source) {
+        Err(AsmError::Syntax { line, column, message, .. }) => (line, column, message),
+        other => panic!("expected a syntax error, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_instructions() {
+    let source = "\
+        CLS
+        ld v3, 0x1F
+        LD I, LONG 0x1234
+        DRW V0, V1, 5
+        JP V0, 0x300
+        SHR VA
+        SAVE V1 - V2
+        LD [I], VF
+        LD VA, [I]
+        ADD I, V2
+        LD HF, V0
+        PLANE 3
+    ";
+    assert_eq!(asm(source).unwrap(), vec![
+        0x00, 0xE0,
+        0x63, 0x1F,
+        0xF0, 0x00, 0x12, 0x34,
+        0xD0, 0x15,
+        0xB3, 0x00,
+        0x8A, 0xA6,
+        0x51, 0x22,
+        0xFF, 0x55,
+        0xFA, 0x65,
+        0xF2, 0x1E,
+        0xF0, 0x30,
+        0xF3, 0x01,
+    ]);
+}
+
+#[test]
+fn test_labels_and_constants() {
+    let source = "\
+SPEED equ 2
+start:  ADD V0, SPEED + 1   ; forward reference below
+        JP end
+loop:   JP loop
+end:    CALL start
+        LD V1, -1
+    ";
+    assert_eq!(asm(source).unwrap(), vec![0x70, 0x03, 0x12, 0x06, 0x12, 0x04, 0x22, 0x00, 0x61, 0xFF]);
+}
+
+#[test]
+fn test_data_and_org() {
+    let source = "\
+        LD I, sprite
+        org 0x208
+sprite: db 0b10000001, \"AB\", 0xFF
+        dw 0x1234, sprite
+    ";
+    assert_eq!(asm(source).unwrap(), vec![
+        0xA2, 0x08,
+        0, 0, 0, 0, 0, 0,
+        0x81, 0x41, 0x42, 0xFF,
+        0x12, 0x34, 0x02, 0x08,
+    ]);
+}
+
+#[test]
+fn test_include() {
+    let dir = env::temp_dir().join(format!("chip8rs-asm-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("lib.s"), "sub: RET\n").unwrap();
+    fs::write(dir.join("main.s"), "CALL sub\ninclude \"lib.s\"\nJP sub\n").unwrap();
+    let rom = assemble_file_with_lines(&dir.join("main.s"));
+    fs::write(dir.join("bad.s"), "include \"broken.s\"\n").unwrap();
+    fs::write(dir.join("broken.s"), "\n  JP nowhere\n").unwrap();
+    let broken = assemble_file(&dir.join("bad.s"));
+    fs::remove_dir_all(&dir).unwrap();
+
+    let (rom, lines) = rom.unwrap();
+    assert_eq!(rom, vec![0x22, 0x02, 0x00, 0xEE, 0x12, 0x02]);
+    assert_eq!(lines, vec![
+        SourceLine { addr: 0x200, path: dir.join("main.s"), line: 1 },
+        SourceLine { addr: 0x202, path: dir.join("lib.s"), line: 1 },
+        SourceLine { addr: 0x204, path: dir.join("main.s"), line: 3 },
+    ]);
+    match broken {
+        Err(AsmError::Syntax { path, line: 2, column: 6, .. }) => assert_eq!(path, dir.join("broken.s")),
+        other => panic!("unexpected {:?}", other),
+    }
+}
+
+#[test]
+fn test_errors() {
+    assert_eq!(error("  FOO V1"), (1, 3, "unknown instruction FOO".to_string()));
+    assert_eq!(error("CLS\n  LD V1, K, V2"), (2, 3, "invalid operands for LD".to_string()));
+    assert_eq!(error("LD V1, 256"), (1, 8, "256 (0x100) doesn't fit in a byte".to_string()));
+    assert_eq!(error("JP 0x1000"), (1, 4, "4096 (0x1000) doesn't fit in a 12 bit address".to_string()));
+    let (_, _, message) = error("LD V1, -0x7FFFFFFFFFFFFFFF - 1");
+    assert_eq!(message, "-9223372036854775808 (-0x8000000000000000) doesn't fit in a byte");
+    assert_eq!(error("  JP missing"), (1, 6, "undefined name missing".to_string()));
+    assert_eq!(error("a: CLS\na: CLS"), (2, 1, "a is already defined".to_string()));
+    assert_eq!(error("DRW V0, , 1"), (1, 9, "missing operand".to_string()));
+    assert_eq!(error("db \"open"), (1, 4, "unterminated string".to_string()));
+    assert_eq!(error("org 0x100"), (1, 5, "org 0x100 is outside 0x200 to 0xFFFF".to_string()));
+    assert_eq!(error("org 0x202\nCLS\norg 0x200\nLD I, LONG 0"), (4, 1, "overlaps what is already at 0x0202".to_string()));
+    assert_eq!(error("v1: CLS"), (1, 1, "v1 is reserved".to_string()));
+}
+
+#[test]
+fn test_round_trips_disassembly() {
+    // Everything `Instruction` displays assembles back to the same opcode
+    for opcode in 0..=0xFFFFu16 {
+        let instruction = Instruction::decode(opcode);
+        if instruction == Instruction::LoadLongI(0) {
+            continue;
+        }
+        let rom = asm(&instruction.to_string()).unwrap();
+        assert_eq!(rom, opcode.to_be_bytes(), "{}", instruction);
+    }
+}
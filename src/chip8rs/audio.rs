@@ -0,0 +1,143 @@
+use std::f64::consts::PI;
+
+pub const AUDIO_PATTERN_SIZE: usize = 16;
+const DEFAULT_PITCH: u8 = 64;
+pub const DEFAULT_FREQUENCY: f64 = 440.0;
+pub const DEFAULT_VOLUME: f32 = 0.25;
+const FADE_SECONDS: f64 = 0.005;  // long enough to avoid clicks, short enough to keep beeps crisp
+
+// XO-CHIP audio: a 128 bit pattern played one bit at a time, looping while the sound timer runs.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct AudioPattern {
+    pub pattern: [u8; AUDIO_PATTERN_SIZE],
+    pub pitch: u8,
+}
+
+impl AudioPattern {
+    // Playback rate in bits per second.
+    pub fn rate(&self) -> f64 {
+        4000.0 * 2f64.powf((self.pitch as f64 - 64.0) / 48.0)
+    }
+
+    // Whether bit `n` of the pattern, counting from the most significant bit of the first byte, is set.
+    pub fn bit(&self, n: usize) -> bool {
+        let n = n % (AUDIO_PATTERN_SIZE * 8);
+        self.pattern[n / 8] & (0x80 >> (n % 8)) != 0
+    }
+}
+
+impl Default for AudioPattern {
+    fn default() -> Self {
+        AudioPattern {
+            pattern: [0; AUDIO_PATTERN_SIZE],
+            pitch: DEFAULT_PITCH,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Waveform {
+    Square,
+    Triangle,
+    Sine,
+}
+
+impl Waveform {
+    pub const NAMES: &'static [&'static str] = &["square", "triangle", "sine"];
+
+    pub fn from_name(name: &str) -> Option<Waveform> {
+        match name {
+            "square" => Some(Waveform::Square),
+            "triangle" => Some(Waveform::Triangle),
+            "sine" => Some(Waveform::Sine),
+            _ => None,
+        }
+    }
+
+    // The wave at `phase`, in periods from 0 to 1, between -1 and 1.
+    pub fn sample(self, phase: f64) -> f32 {
+        let s = match self {
+            Waveform::Square => if phase < 0.5 { 1.0 } else { -1.0 },
+            Waveform::Triangle => 1.0 - 4.0 * (phase - 0.5).abs(),
+            Waveform::Sine => (2.0 * PI * phase).sin(),
+        };
+        s as f32
+    }
+}
+
+// The sound played while the sound timer runs, unless an XO-CHIP pattern replaces it.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Tone {
+    pub waveform: Waveform,
+    pub frequency: f64,  // Hz
+    pub volume: f32,  // 0 to 1, also used for XO-CHIP patterns and MegaChip sounds
+}
+
+impl Default for Tone {
+    fn default() -> Self {
+        Tone {
+            waveform: Waveform::Square,
+            frequency: DEFAULT_FREQUENCY,
+            volume: DEFAULT_VOLUME,
+        }
+    }
+}
+
+// Generates the beep samples: the tone, or the XO-CHIP pattern when one is loaded, faded in when
+// the sound timer starts and out when it stops. Frontends only switch it on and off once per frame,
+// so the sound follows the 60 Hz timer whatever the instruction rate.
+pub struct Beeper {
+    tone: Tone,
+    sample_rate: f64,
+    beep: bool,
+    pattern: Option<AudioPattern>,
+    phase: f64,  // in tone periods
+    position: f64,  // in pattern bits
+    gain: f64,
+}
+
+impl Beeper {
+    pub fn new(tone: Tone, sample_rate: f64) -> Self {
+        Beeper {
+            tone,
+            sample_rate,
+            beep: false,
+            pattern: None,
+            phase: 0.0,
+            position: 0.0,
+            gain: 0.0,
+        }
+    }
+
+    pub fn set(&mut self, beep: bool, pattern: Option<AudioPattern>) {
+        self.beep = beep;
+        self.pattern = pattern;
+    }
+
+    pub fn next_sample(&mut self) -> f32 {
+        let target = if self.beep { 1.0 } else { 0.0 };
+        let step = 1.0 / (FADE_SECONDS * self.sample_rate);
+        self.gain = if self.gain < target { (self.gain + step).min(target) } else { (self.gain - step).max(target) };
+        if self.gain == 0.0 {
+            return 0.0;
+        }
+
+        let wave = match self.pattern {
+            Some(pattern) => {
+                let bit = pattern.bit(self.position as usize);
+                self.position = (self.position + pattern.rate() / self.sample_rate) % (AUDIO_PATTERN_SIZE * 8) as f64;
+                if bit { 1.0 } else { -1.0 }
+            }
+            None => {
+                let sample = self.tone.waveform.sample(self.phase);
+                self.phase = (self.phase + self.tone.frequency / self.sample_rate).fract();
+                sample
+            }
+        };
+        wave * self.gain as f32 * self.tone.volume
+    }
+}
+
+#[cfg(test)]
+#[path = "./audio_test.rs"]
+mod audio_test;
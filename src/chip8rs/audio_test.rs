@@ -0,0 +1,39 @@
+use super::*;
+
+
+#[test]
+fn test_waveforms() {
+    for &(phase, square, triangle, sine) in &[(0.0, 1.0, -1.0, 0.0), (0.25, 1.0, 0.0, 1.0), (0.5, -1.0, 1.0, 0.0), (0.75, -1.0, 0.0, -1.0)] {
+        assert_eq!(Waveform::Square.sample(phase), square);
+        assert!((Waveform::Triangle.sample(phase) - triangle).abs() < 1e-6);
+        assert!((Waveform::Sine.sample(phase) - sine).abs() < 1e-6);
+    }
+}
+
+#[test]
+fn test_beeper_fades() {
+    let tone = Tone { waveform: Waveform::Square, frequency: 100.0, volume: 1.0 };
+    let mut beeper = Beeper::new(tone, 4000.0);  // 20 samples of fade
+    assert_eq!(beeper.next_sample(), 0.0);
+
+    beeper.set(true, None);
+    let on: Vec<f32> = (0..40).map(|_| beeper.next_sample()).collect();
+    assert!(on[0] > 0.0 && on[0] < 0.1);
+    assert!(on.windows(2).take(19).all(|w| w[1] > w[0]));
+    assert_eq!(on[39].abs(), 1.0);
+
+    beeper.set(false, None);
+    let off: Vec<f32> = (0..40).map(|_| beeper.next_sample()).collect();
+    assert!(off[0].abs() > 0.9);
+    assert!(off[21..].iter().all(|&s| s == 0.0));
+}
+
+#[test]
+fn test_beeper_pattern() {
+    let tone = Tone { volume: 1.0, ..Tone::default() };
+    let mut beeper = Beeper::new(tone, 4000.0);
+    let pattern = AudioPattern { pattern: [0xff; AUDIO_PATTERN_SIZE], pitch: 64 };
+    beeper.set(true, Some(pattern));
+    let samples: Vec<f32> = (0..40).map(|_| beeper.next_sample()).collect();
+    assert!(samples.iter().all(|&s| s > 0.0));
+}
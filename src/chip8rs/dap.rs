@@ -0,0 +1,775 @@
+use std::collections::{BTreeSet, HashMap};
+use std::convert::TryFrom;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, TryRecvError};
+use std::thread;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use super::args::Rng;
+use super::asm::{self, SourceLine};
+use super::flow::Op;
+use super::instruction::Instruction;
+use super::quirks::Quirks;
+use super::rom::Rom;
+use super::scheduler::{Scheduler, DEFAULT_IPS};
+use super::vm::{Registers, VM};
+
+// The VM is the only thread.
+const THREAD_ID: u64 = 1;
+
+// `variablesReference`s of the scopes, the same for every stack frame.
+const REGISTERS: u64 = 1;
+const TIMERS: u64 = 2;
+const STACK: u64 = 3;
+
+// Programs with these extensions are assembled, which also gives source line breakpoints.
+const SOURCE_EXTENSIONS: &[&str] = &["s", "asm"];
+
+// Shown when a step finds Fx0A waiting, as the editor has no keypad of its own.
+const WAITING_FOR_KEY: &str = "Fx0A is waiting for a key: press one with setKeys, then release it";
+
+// Reads one message: "Content-Length" and other headers, a blank line, then that many bytes of
+// JSON. `None` at the end of the input.
+pub fn read_message<R: BufRead>(input: &mut R) -> io::Result<Option<Value>> {
+    let mut length = None;
+    loop {
+        let mut header = String::new();
+        if input.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some(value) = header.strip_prefix("Content-Length:") {
+            length = value.trim().parse::<usize>().ok();
+        }
+    }
+    let length = length.ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "missing Content-Length"))?;
+    let mut body = vec![0; length];
+    input.read_exact(&mut body)?;
+    serde_json::from_slice(&body).map(Some).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+}
+
+pub fn write_message<W: Write>(output: &mut W, message: &Value) -> io::Result<()> {
+    let body = message.to_string();
+    write!(output, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    output.flush()
+}
+
+// Serves one debugging session: requests are read on a thread of their own so they can pause a
+// running VM, which runs in real time with nothing pressed on the keypad.
+pub fn serve<R, W>(input: R, mut output: W) -> io::Result<()>
+where
+    R: Read + Send + 'static,
+    W: Write,
+{
+    let (sender, messages) = mpsc::channel();
+    thread::spawn(move || {
+        let mut input = BufReader::new(input);
+        while let Ok(Some(message)) = read_message(&mut input) {
+            if sender.send(message).is_err() {
+                break;
+            }
+        }
+    });
+
+    let mut adapter = Adapter::new();
+    let mut scheduler = Scheduler::new(adapter.ips());
+    let mut ips = adapter.ips();
+    while !adapter.done() {
+        let message = if adapter.running() {
+            match messages.try_recv() {
+                Ok(message) => Some(message),
+                Err(TryRecvError::Empty) => None,
+                Err(TryRecvError::Disconnected) => break,
+            }
+        } else {
+            match messages.recv() {
+                Ok(message) => Some(message),
+                Err(_) => break,
+            }
+        };
+        let replies = match message {
+            Some(message) => {
+                let replies = adapter.handle(&message);
+                if adapter.ips() != ips {
+                    ips = adapter.ips();
+                    scheduler = Scheduler::new(ips);
+                }
+                replies
+            }
+            None => {
+                let events = adapter.run_frame(scheduler.instructions_this_frame());
+                scheduler.wait_next_frame();
+                events
+            }
+        };
+        for reply in &replies {
+            write_message(&mut output, reply)?;
+        }
+    }
+    Ok(())
+}
+
+#[derive(Deserialize)]
+struct Request {
+    seq: u64,
+    command: String,
+    #[serde(default)]
+    arguments: Value,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct LaunchArguments {
+    program: PathBuf,
+    #[serde(default)]
+    stop_on_entry: bool,
+    ips: Option<u32>,
+    quirks: Option<String>,
+    seed: Option<u64>,
+    rng: Option<String>,  // as for --rng
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Run {
+    Stopped,
+    Continue,
+    // Until the stack is back to `depth` entries, after stepping over a call
+    StepOver { depth: usize },
+    // Until the stack has fewer than `depth` entries
+    StepOut { depth: usize },
+}
+
+enum Stop {
+    Breakpoint,
+    Step,
+    Halted,
+    Fault(String),
+}
+
+// A breakpoint on a line of source, moved to an instruction once the program is assembled.
+struct SourceBreakpoint {
+    id: u64,
+    line: usize,
+}
+
+// The protocol state of a session, without any I/O: each request gives its response followed by
+// any events, and `run_frame` advances a running VM. Until "launch" there is no VM.
+pub struct Adapter {
+    seq: u64,
+    vm: Option<VM>,
+    ips: u32,
+    stop_on_entry: bool,
+    lines: Vec<SourceLine>,
+    source_breakpoints: HashMap<PathBuf, Vec<SourceBreakpoint>>,  // as requested, which may be before launch
+    next_breakpoint_id: u64,
+    instruction_breakpoints: Vec<usize>,
+    breakpoints: BTreeSet<usize>,  // all of the above
+    run: Run,
+    resumed: bool,  // don't stop on the breakpoint the VM is sitting on
+    executed: u32,  // instructions of the current frame already run
+    keys: [bool; 16],  // held down, as set by the custom "setKeys" request
+    done: bool,
+}
+
+impl Default for Adapter {
+    fn default() -> Self {
+        Adapter::new()
+    }
+}
+
+impl Adapter {
+    pub fn new() -> Self {
+        Adapter {
+            seq: 0,
+            vm: None,
+            ips: DEFAULT_IPS,
+            stop_on_entry: false,
+            lines: Vec::new(),
+            source_breakpoints: HashMap::new(),
+            next_breakpoint_id: 1,
+            instruction_breakpoints: Vec::new(),
+            breakpoints: BTreeSet::new(),
+            run: Run::Stopped,
+            resumed: false,
+            executed: 0,
+            keys: [false; 16],
+            done: false,
+        }
+    }
+
+    pub fn vm(&self) -> Option<&VM> {
+        self.vm.as_ref()
+    }
+
+    // Whether the VM is running, and `run_frame` should be called every frame.
+    pub fn running(&self) -> bool {
+        self.vm.is_some() && self.run != Run::Stopped
+    }
+
+    // Whether the client disconnected.
+    pub fn done(&self) -> bool {
+        self.done
+    }
+
+    pub fn ips(&self) -> u32 {
+        self.ips
+    }
+
+    // Handles one request, returning its response followed by any events.
+    pub fn handle(&mut self, message: &Value) -> Vec<Value> {
+        let request: Request = match serde_json::from_value(message.clone()) {
+            Ok(request) => request,
+            Err(err) => return vec![self.event("output", json!({ "category": "stderr", "output": format!("{}\n", err) }))],
+        };
+        let mut events = Vec::new();
+        let result = self.execute(&request.command, &request.arguments, &mut events);
+        let seq = self.next_seq();
+        let mut response = json!({
+            "seq": seq,
+            "type": "response",
+            "request_seq": request.seq,
+            "command": request.command,
+            "success": result.is_ok(),
+        });
+        match result {
+            Ok(body) => response["body"] = body,
+            Err(message) => response["message"] = Value::String(message),
+        }
+        let mut replies = vec![response];
+        for (event, body) in events {
+            replies.push(self.event(event, body));
+        }
+        replies
+    }
+
+    // Runs the rest of the current frame, or less when a breakpoint or a step ends it. Returns the
+    // events telling why the VM stopped, if it did.
+    pub fn run_frame(&mut self, instructions: u32) -> Vec<Value> {
+        let stop = match self.run_instructions(instructions) {
+            Some(stop) => stop,
+            None => return Vec::new(),
+        };
+        self.run = Run::Stopped;
+        match stop {
+            Stop::Breakpoint => vec![self.stopped("breakpoint", None)],
+            Stop::Step => vec![self.stopped("step", None)],
+            Stop::Fault(err) => vec![self.stopped("exception", Some(err))],
+            Stop::Halted => {
+                self.done = true;
+                vec![
+                    self.event("exited", json!({ "exitCode": 0 })),
+                    self.event("terminated", json!({})),
+                ]
+            }
+        }
+    }
+
+    fn run_instructions(&mut self, instructions: u32) -> Option<Stop> {
+        let vm = self.vm.as_mut()?;
+        while self.executed < instructions {
+            let output = vm.output();
+            if output.halted {
+                return Some(Stop::Halted);
+            }
+            let pc = vm.registers().pc;
+            if !self.resumed && !output.waiting && self.breakpoints.contains(&pc) {
+                return Some(Stop::Breakpoint);
+            }
+            self.resumed = false;
+            if let Err(err) = vm.step(self.keys) {
+                return Some(Stop::Fault(err.to_string()));
+            }
+            self.executed += 1;
+            let sp = vm.registers().sp;
+            match self.run {
+                Run::StepOver { depth } if sp <= depth => return Some(Stop::Step),
+                Run::StepOut { depth } if sp < depth => return Some(Stop::Step),
+                _ => {}
+            }
+        }
+        vm.tick_timers();
+        self.executed = 0;
+        None
+    }
+
+    fn execute(&mut self, command: &str, arguments: &Value, events: &mut Vec<(&'static str, Value)>) -> Result<Value, String> {
+        match command {
+            "initialize" => {
+                events.push(("initialized", json!({})));
+                return Ok(json!({
+                    "supportsConfigurationDoneRequest": true,
+                    "supportsInstructionBreakpoints": true,
+                    "supportsReadMemoryRequest": true,
+                    "supportsDisassembleRequest": true,
+                    "supportsSetVariable": true,
+                    "supportsTerminateRequest": true,
+                }));
+            }
+            "launch" => {
+                let arguments: LaunchArguments = serde_json::from_value(arguments.clone()).map_err(|err| err.to_string())?;
+                self.launch(arguments)?;
+                // Source breakpoints set before launch only resolve now
+                for breakpoint in self.source_breakpoint_states() {
+                    events.push(("breakpoint", json!({ "reason": "changed", "breakpoint": breakpoint })));
+                }
+                return Ok(json!({}));
+            }
+            "setBreakpoints" => return Ok(self.set_breakpoints(arguments)),
+            "setInstructionBreakpoints" => return Ok(self.set_instruction_breakpoints(arguments)),
+            "setExceptionBreakpoints" => return Ok(json!({ "breakpoints": [] })),
+            "threads" => return Ok(json!({ "threads": [{ "id": THREAD_ID, "name": "CHIP-8" }] })),
+            // Not part of the protocol: `{ "keys": [5] }` holds down key 5 until the next setKeys
+            "setKeys" => {
+                let mut keys = [false; 16];
+                for key in arguments["keys"].as_array().cloned().unwrap_or_default() {
+                    match key.as_u64() {
+                        Some(key) if key < 16 => keys[key as usize] = true,
+                        _ => return Err(format!("{} is not a key, expected 0 to 15", key)),
+                    }
+                }
+                self.keys = keys;
+                return Ok(json!({}));
+            }
+            "disconnect" => {
+                self.done = true;
+                return Ok(json!({}));
+            }
+            "terminate" => {
+                self.done = true;
+                events.push(("terminated", json!({})));
+                return Ok(json!({}));
+            }
+            _ => {}
+        }
+
+        let vm = self.vm.as_mut().ok_or("no program launched")?;
+        let body = match command {
+            "configurationDone" => {
+                if self.stop_on_entry {
+                    events.push(stopped("entry", None));
+                } else {
+                    self.resume(Run::Continue);
+                }
+                json!({})
+            }
+            "continue" => {
+                self.resume(Run::Continue);
+                json!({ "allThreadsContinued": true })
+            }
+            "next" => {
+                let r = vm.registers();
+                match vm.instruction_at(r.pc) {
+                    Op::Chip(Instruction::Call(_)) => self.resume(Run::StepOver { depth: r.sp }),
+                    _ => events.push(self.step_instruction()),
+                }
+                json!({})
+            }
+            "stepIn" => {
+                events.push(self.step_instruction());
+                json!({})
+            }
+            "stepOut" => {
+                let depth = vm.registers().sp;
+                self.resume(Run::StepOut { depth });
+                json!({})
+            }
+            "pause" => {
+                if self.run != Run::Stopped {
+                    self.run = Run::Stopped;
+                    events.push(stopped("pause", None));
+                }
+                json!({})
+            }
+            "stackTrace" => self.stack_trace(),
+            "scopes" => json!({ "scopes": [
+                { "name": "Registers", "variablesReference": REGISTERS, "expensive": false },
+                { "name": "Timers", "variablesReference": TIMERS, "expensive": false },
+                { "name": "Stack", "variablesReference": STACK, "expensive": false },
+            ] }),
+            "variables" => {
+                let reference = arguments["variablesReference"].as_u64().unwrap_or_default();
+                json!({ "variables": variables(&vm.registers(), reference) })
+            }
+            "setVariable" => {
+                let reference = arguments["variablesReference"].as_u64().unwrap_or_default();
+                let name = arguments["name"].as_str().unwrap_or_default();
+                let value = arguments["value"].as_str().and_then(number).ok_or("expected a number like 12 or 0x0C")?;
+                let mut r = vm.registers();
+                set_variable(&mut r, reference, name, value)?;
+                vm.set_registers(&r);
+                let value = variables(&vm.registers(), reference)
+                    .into_iter()
+                    .find(|variable| variable["name"] == name)
+                    .map(|variable| variable["value"].clone())
+                    .unwrap_or_default();
+                json!({ "value": value })
+            }
+            "readMemory" => {
+                let address = memory_reference(arguments)?;
+                let count = arguments["count"].as_u64().unwrap_or_default() as usize;
+                let memory = vm.memory();
+                let start = address.min(memory.len());
+                let end = address.saturating_add(count).min(memory.len());
+                json!({
+                    "address": format!("0x{:04X}", address),
+                    "data": base64(&memory[start..end]),
+                    "unreadableBytes": count - (end - start),
+                })
+            }
+            "disassemble" => {
+                let address = memory_reference(arguments)?;
+                let offset = arguments["instructionOffset"].as_i64().unwrap_or_default();
+                let count = arguments["instructionCount"].as_u64().unwrap_or_default() as usize;
+                json!({ "instructions": self.disassemble(address as i64 + offset * 2, count) })
+            }
+            _ => return Err(format!("unsupported request {}", command)),
+        };
+        Ok(body)
+    }
+
+    fn launch(&mut self, arguments: LaunchArguments) -> Result<(), String> {
+        let program = &arguments.program;
+        let source = program
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .is_some_and(|ext| SOURCE_EXTENSIONS.iter().any(|e| ext.eq_ignore_ascii_case(e)));
+        let rom = if source {
+            // Canonical paths, to match the ones clients give for breakpoints
+            let path = program.canonicalize().map_err(|err| format!("{}: {}", program.display(), err))?;
+            let (bytes, lines) = asm::assemble_file_with_lines(&path).map_err(|err| err.to_string())?;
+            self.lines = lines;
+            // "pong.sc8.s" runs as SUPER-CHIP
+            Rom::from_bytes(bytes, &path.with_extension("")).map_err(|err| err.to_string())?
+        } else {
+            Rom::load(program).map_err(|err| format!("{}: {}", program.display(), err))?
+        };
+        let mode = rom.mode();
+        rom.check_size(mode).map_err(|err| format!("{}: {}", program.display(), err))?;
+        let quirks = match arguments.quirks {
+            Some(ref preset) => Quirks::preset(preset).ok_or(format!("unknown quirks preset {:?}", preset))?,
+            None => {
+                let listed_path = program.with_file_name(rom.name.file_name().unwrap_or_default());
+                super::roms::quirks_for(&listed_path, mode.default_quirks())
+            }
+        };
+
+        let rng = match arguments.rng {
+            Some(ref rng) => Rng::parse(rng)?,
+            None => Rng::SplitMix64,
+        };
+        let rng = rng.open(arguments.seed.unwrap_or_else(rand::random)).map_err(|(err, _)| err)?;
+        let mut vm = VM::with_rng(mode, quirks, rng);
+        vm.load(&rom.bytes);
+        self.vm = Some(vm);
+        self.ips = arguments.ips.unwrap_or(DEFAULT_IPS).max(1);
+        self.stop_on_entry = arguments.stop_on_entry;
+        self.run = Run::Stopped;
+        self.executed = 0;
+        self.update_breakpoints();
+        Ok(())
+    }
+
+    // Source breakpoints move to the first instruction at or after their line. They can be set
+    // before launch, and are resolved again once the program is assembled.
+    fn set_breakpoints(&mut self, arguments: &Value) -> Value {
+        let path = PathBuf::from(arguments["source"]["path"].as_str().unwrap_or_default());
+        let path = path.canonicalize().unwrap_or(path);
+        let requested = arguments["breakpoints"].as_array().cloned().unwrap_or_default();
+        let mut breakpoints = Vec::new();
+        for breakpoint in &requested {
+            let line = breakpoint["line"].as_u64().unwrap_or_default() as usize;
+            breakpoints.push(SourceBreakpoint { id: self.next_breakpoint_id, line });
+            self.next_breakpoint_id += 1;
+        }
+        let states = breakpoints.iter().map(|breakpoint| self.source_breakpoint_state(&path, breakpoint)).collect::<Vec<_>>();
+        self.source_breakpoints.insert(path, breakpoints);
+        self.update_breakpoints();
+        json!({ "breakpoints": states })
+    }
+
+    // The instruction a source breakpoint stops on, if the program has one at or after its line.
+    fn resolve(&self, path: &Path, breakpoint: &SourceBreakpoint) -> Option<&SourceLine> {
+        self.lines
+            .iter()
+            .filter(|l| l.path == path && l.line >= breakpoint.line)
+            .min_by_key(|l| (l.line, l.addr))
+    }
+
+    fn source_breakpoint_state(&self, path: &Path, breakpoint: &SourceBreakpoint) -> Value {
+        match self.resolve(path, breakpoint) {
+            Some(found) => json!({
+                "id": breakpoint.id,
+                "verified": true,
+                "line": found.line,
+                "instructionReference": format!("0x{:04X}", found.addr),
+            }),
+            None => {
+                let message = match self.vm {
+                    Some(_) => "no instruction at or after this line",
+                    None => "not launched yet",
+                };
+                json!({ "id": breakpoint.id, "verified": false, "line": breakpoint.line, "message": message })
+            }
+        }
+    }
+
+    fn source_breakpoint_states(&self) -> Vec<Value> {
+        self.source_breakpoints
+            .iter()
+            .flat_map(|(path, breakpoints)| breakpoints.iter().map(move |breakpoint| self.source_breakpoint_state(path, breakpoint)))
+            .collect()
+    }
+
+    fn set_instruction_breakpoints(&mut self, arguments: &Value) -> Value {
+        let requested = arguments["breakpoints"].as_array().cloned().unwrap_or_default();
+        let memory_size = self.vm.as_ref().map_or(usize::MAX, |vm| vm.memory().len());
+        self.instruction_breakpoints.clear();
+        let breakpoints: Vec<Value> = requested
+            .iter()
+            .map(|breakpoint| {
+                let addr = breakpoint["instructionReference"]
+                    .as_str()
+                    .and_then(number)
+                    .map(|addr| addr as i64 + breakpoint["offset"].as_i64().unwrap_or_default());
+                match addr.and_then(|addr| usize::try_from(addr).ok()).filter(|&addr| addr < memory_size) {
+                    Some(addr) => {
+                        self.instruction_breakpoints.push(addr);
+                        json!({ "verified": true, "instructionReference": format!("0x{:04X}", addr) })
+                    }
+                    None => json!({ "verified": false, "message": "not an address in memory" }),
+                }
+            })
+            .collect();
+        self.update_breakpoints();
+        json!({ "breakpoints": breakpoints })
+    }
+
+    fn update_breakpoints(&mut self) {
+        let mut breakpoints: BTreeSet<usize> = self.instruction_breakpoints.iter().cloned().collect();
+        for (path, requested) in &self.source_breakpoints {
+            breakpoints.extend(requested.iter().filter_map(|breakpoint| self.resolve(path, breakpoint)).map(|line| line.addr));
+        }
+        self.breakpoints = breakpoints;
+    }
+
+    fn resume(&mut self, run: Run) {
+        self.run = run;
+        self.resumed = true;
+    }
+
+    // Executes the instruction at pc, and tells why the VM stopped afterwards. While Fx0A waits,
+    // a step only reads the keys, and stops saying so until a key set by setKeys is released.
+    fn step_instruction(&mut self) -> (&'static str, Value) {
+        let vm = match self.vm.as_mut() {
+            Some(vm) => vm,
+            None => return stopped("step", None),
+        };
+        match vm.step(self.keys) {
+            Ok(output) if output.waiting => stopped("waiting for key", Some(WAITING_FOR_KEY.to_string())),
+            Ok(_) => stopped("step", None),
+            Err(err) => stopped("exception", Some(err.to_string())),
+        }
+    }
+
+    // The current instruction, then the calls which led to it, innermost first.
+    fn stack_trace(&self) -> Value {
+        let vm = match self.vm {
+            Some(ref vm) => vm,
+            None => return json!({ "stackFrames": [], "totalFrames": 0 }),
+        };
+        let r = vm.registers();
+        // Return addresses point past their call
+        let callers = r.stack[..r.sp].iter().rev().map(|ret| ret.saturating_sub(2));
+        let frames: Vec<Value> = std::iter::once(r.pc)
+            .chain(callers)
+            .enumerate()
+            .map(|(id, addr)| {
+                let instruction = vm.instruction_at(addr);
+                let mut frame = json!({
+                    "id": id,
+                    "name": format!("0x{:04X} {}", addr, instruction),
+                    "line": 0,
+                    "column": 0,
+                    "instructionPointerReference": format!("0x{:04X}", addr),
+                });
+                if let Some(line) = self.source_line(addr) {
+                    frame["source"] = source(&line.path);
+                    frame["line"] = json!(line.line);
+                    frame["column"] = json!(1);
+                }
+                frame
+            })
+            .collect();
+        json!({ "totalFrames": frames.len(), "stackFrames": frames })
+    }
+
+    fn source_line(&self, addr: usize) -> Option<&SourceLine> {
+        self.lines
+            .binary_search_by_key(&addr, |line| line.addr)
+            .ok()
+            .map(|i| &self.lines[i])
+    }
+
+    // `count` instructions from `start`, which may be outside memory when the client scrolls
+    // past either end; those get placeholders as the protocol wants exactly `count` of them.
+    fn disassemble(&self, start: i64, count: usize) -> Vec<Value> {
+        let vm = match self.vm {
+            Some(ref vm) => vm,
+            None => return Vec::new(),
+        };
+        let memory = vm.memory();
+        let mut addr = start;
+        (0..count)
+            .map(|_| match usize::try_from(addr).ok().filter(|&addr| addr + 1 < memory.len()) {
+                Some(at) => {
+                    let op = vm.instruction_at(at);
+                    let bytes: Vec<String> = memory[at..(at + op.size()).min(memory.len())].iter().map(|b| format!("{:02X}", b)).collect();
+                    let mut instruction = json!({
+                        "address": format!("0x{:04X}", at),
+                        "instructionBytes": bytes.join(" "),
+                        "instruction": op.to_string(),
+                    });
+                    if let Some(line) = self.source_line(at) {
+                        instruction["location"] = source(&line.path);
+                        instruction["line"] = json!(line.line);
+                    }
+                    addr += op.size() as i64;
+                    instruction
+                }
+                None => {
+                    let instruction = json!({ "address": format!("0x{:04X}", addr.max(0)), "instruction": "??" });
+                    addr += 2;
+                    instruction
+                }
+            })
+            .collect()
+    }
+
+    fn next_seq(&mut self) -> u64 {
+        self.seq += 1;
+        self.seq
+    }
+
+    fn event(&mut self, event: &str, body: Value) -> Value {
+        json!({ "seq": self.next_seq(), "type": "event", "event": event, "body": body })
+    }
+
+    fn stopped(&mut self, reason: &str, text: Option<String>) -> Value {
+        let (event, body) = stopped(reason, text);
+        self.event(event, body)
+    }
+}
+
+fn stopped(reason: &str, text: Option<String>) -> (&'static str, Value) {
+    let mut body = json!({ "reason": reason, "threadId": THREAD_ID, "allThreadsStopped": true });
+    if let Some(text) = text {
+        body["text"] = Value::String(text);
+    }
+    ("stopped", body)
+}
+
+fn source(path: &Path) -> Value {
+    json!({
+        "name": path.file_name().map(|name| name.to_string_lossy()).unwrap_or_default(),
+        "path": path.display().to_string(),
+    })
+}
+
+// Decimal, or hex with 0x.
+fn number(s: &str) -> Option<u64> {
+    match s.trim().strip_prefix("0x").or_else(|| s.trim().strip_prefix("0X")) {
+        Some(hex) => u64::from_str_radix(hex, 16).ok(),
+        None => s.trim().parse().ok(),
+    }
+}
+
+// The address a "memoryReference" and "offset" point at.
+fn memory_reference(arguments: &Value) -> Result<usize, String> {
+    let reference = arguments["memoryReference"].as_str().and_then(number).ok_or("invalid memoryReference")?;
+    let addr = reference as i64 + arguments["offset"].as_i64().unwrap_or_default();
+    usize::try_from(addr).map_err(|_| format!("address {} is before the start of memory", addr))
+}
+
+fn variable(name: &str, value: String, memory_reference: bool) -> Value {
+    let mut variable = json!({ "name": name, "value": value, "variablesReference": 0 });
+    if memory_reference {
+        variable["memoryReference"] = variable["value"].clone();
+    }
+    variable
+}
+
+fn variables(r: &Registers, reference: u64) -> Vec<Value> {
+    match reference {
+        REGISTERS => {
+            let mut variables: Vec<Value> = r
+                .v
+                .iter()
+                .enumerate()
+                .map(|(x, v)| variable(&format!("V{:X}", x), format!("0x{:02X}", v), false))
+                .collect();
+            variables.push(variable("I", format!("0x{:04X}", r.i), true));
+            variables.push(variable("PC", format!("0x{:04X}", r.pc), true));
+            variables.push(variable("SP", r.sp.to_string(), false));
+            variables
+        }
+        TIMERS => vec![
+            variable("DT", r.delay_timer.to_string(), false),
+            variable("ST", r.sound_timer.to_string(), false),
+        ],
+        STACK => r.stack[..r.sp]
+            .iter()
+            .enumerate()
+            .map(|(n, ret)| variable(&n.to_string(), format!("0x{:04X}", ret), true))
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+fn set_variable(r: &mut Registers, reference: u64, name: &str, value: u64) -> Result<(), String> {
+    let fits = |max: u64| if value <= max { Ok(value) } else { Err(format!("{} is too large for {}", value, name)) };
+    match (reference, name) {
+        (REGISTERS, "I") => r.i = fits(u32::MAX as u64)? as u32,
+        (REGISTERS, "PC") => r.pc = fits(0xFFFFFF)? as usize,
+        (REGISTERS, "SP") => r.sp = fits(r.stack.len() as u64)? as usize,
+        (REGISTERS, _) => {
+            let x = name
+                .strip_prefix('V')
+                .filter(|x| x.len() == 1)
+                .and_then(|x| usize::from_str_radix(x, 16).ok())
+                .ok_or(format!("no register {}", name))?;
+            r.v[x] = fits(0xFF)? as u8;
+        }
+        (TIMERS, "DT") => r.delay_timer = fits(0xFF)? as u8,
+        (TIMERS, "ST") => r.sound_timer = fits(0xFF)? as u8,
+        (STACK, _) => {
+            let n = name.parse::<usize>().ok().filter(|&n| n < r.sp).ok_or(format!("no stack entry {}", name))?;
+            r.stack[n] = fits(0xFFFFFF)? as usize;
+        }
+        _ => return Err(format!("no variable {}", name)),
+    }
+    Ok(())
+}
+
+fn base64(data: &[u8]) -> String {
+    const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut encoded = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let bits = chunk.iter().enumerate().fold(0u32, |bits, (n, &b)| bits | (b as u32) << (16 - 8 * n));
+        for n in 0..4 {
+            if n <= chunk.len() {
+                encoded.push(ALPHABET[(bits >> (18 - 6 * n) & 0x3F) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+#[cfg(test)]
+#[path = "./dap_test.rs"]
+mod dap_test;
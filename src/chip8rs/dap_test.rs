@@ -0,0 +1,301 @@
+use super::*;
+use std::env;
+use std::fs;
+
+
+// CALL 0x206; LD V1, 1; JP 0x204; LD V0, 5; RET
+const ROM: &[u8] = &[0x22, 0x06, 0x61, 0x01, 0x12, 0x04, 0x60, 0x05, 0x00, 0xEE];
+
+const SOURCE: &str = "\
+        CALL sub
+        LD V1, 1
+loop:   JP loop
+
+sub:    LD V0, 5
+        RET
+";
+
+struct Session {
+    adapter: Adapter,
+    seq: u64,
+}
+
+impl Session {
+    // Launches `program`, which is deleted again once loaded.
+    fn launch(name: &str, data: &[u8]) -> Session {
+        let dir = env::temp_dir().join(format!("chip8rs-dap-{}-{}", std::process::id(), name));
+        fs::create_dir_all(&dir).unwrap();
+        let program = dir.join(name);
+        fs::write(&program, data).unwrap();
+
+        let mut session = Session { adapter: Adapter::new(), seq: 0 };
+        session.request("initialize", json!({ "adapterID": "chip8rs" }));
+        let launched = session.request("launch", json!({ "program": program, "stopOnEntry": true, "seed": 1 }));
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(launched[0]["success"], true, "{}", launched[0]);
+        session
+    }
+
+    fn request(&mut self, command: &str, arguments: Value) -> Vec<Value> {
+        self.seq += 1;
+        self.adapter.handle(&json!({ "seq": self.seq, "type": "request", "command": command, "arguments": arguments }))
+    }
+
+    // The body of a successful response.
+    fn body(&mut self, command: &str, arguments: Value) -> Value {
+        let replies = self.request(command, arguments);
+        assert_eq!(replies[0]["success"], true, "{}", replies[0]);
+        replies[0]["body"].clone()
+    }
+
+    // Runs frames until the VM stops, returning the reason.
+    fn wait_stop(&mut self) -> String {
+        for _ in 0..10 {
+            let events = self.adapter.run_frame(10);
+            if let Some(event) = events.first() {
+                return event["body"]["reason"].as_str().unwrap_or_else(|| event["event"].as_str().unwrap()).to_string();
+            }
+        }
+        panic!("still running");
+    }
+
+    fn pc(&self) -> usize {
+        self.adapter.vm().unwrap().registers().pc
+    }
+}
+
+fn events(replies: &[Value]) -> Vec<String> {
+    replies[1..].iter().map(|e| e["event"].as_str().unwrap().to_string()).collect()
+}
+
+#[test]
+fn test_messages() {
+    let mut data = Vec::new();
+    write_message(&mut data, &json!({ "seq": 1 })).unwrap();
+    write_message(&mut data, &json!({ "seq": 2 })).unwrap();
+    assert!(data.starts_with(b"Content-Length: 9\r\n\r\n{\"seq\":1}"));
+
+    let mut input = io::Cursor::new(data);
+    assert_eq!(read_message(&mut input).unwrap(), Some(json!({ "seq": 1 })));
+    assert_eq!(read_message(&mut input).unwrap(), Some(json!({ "seq": 2 })));
+    assert_eq!(read_message(&mut input).unwrap(), None);
+}
+
+#[test]
+fn test_serve() {
+    let mut input = Vec::new();
+    write_message(&mut input, &json!({ "seq": 1, "type": "request", "command": "initialize" })).unwrap();
+    write_message(&mut input, &json!({ "seq": 2, "type": "request", "command": "disconnect" })).unwrap();
+    let mut output = Vec::new();
+    serve(io::Cursor::new(input), &mut output).unwrap();
+
+    let mut output = io::Cursor::new(output);
+    let mut replies = Vec::new();
+    while let Some(message) = read_message(&mut output).unwrap() {
+        replies.push(format!("{} {}", message["type"].as_str().unwrap(), message["command"].as_str().or(message["event"].as_str()).unwrap()));
+    }
+    assert_eq!(replies, vec!["response initialize", "event initialized", "response disconnect"]);
+}
+
+#[test]
+fn test_initialize() {
+    let mut session = Session { adapter: Adapter::new(), seq: 0 };
+    let replies = session.request("initialize", json!({}));
+    assert_eq!(replies[0]["body"]["supportsInstructionBreakpoints"], true);
+    assert_eq!(events(&replies), vec!["initialized"]);
+
+    let replies = session.request("stackTrace", json!({ "threadId": 1 }));
+    assert_eq!(replies[0]["success"], false);
+    assert_eq!(replies[0]["message"], "no program launched");
+    assert_eq!(session.request("frobnicate", json!({}))[0]["success"], false);
+}
+
+#[test]
+fn test_breakpoints_and_stepping() {
+    let mut session = Session::launch("rom.ch8", ROM);
+    assert_eq!(events(&session.request("configurationDone", json!({}))), vec!["stopped"]);
+    assert!(!session.adapter.running());
+
+    let body = session.body("setInstructionBreakpoints", json!({ "breakpoints": [{ "instructionReference": "0x206" }] }));
+    assert_eq!(body["breakpoints"][0]["verified"], true);
+    session.request("continue", json!({}));
+    assert_eq!(session.wait_stop(), "breakpoint");
+    assert_eq!(session.pc(), 0x206);
+
+    let trace = session.body("stackTrace", json!({ "threadId": 1 }));
+    assert_eq!(trace["totalFrames"], 2);
+    assert_eq!(trace["stackFrames"][0]["name"], "0x0206 LD V0, 0x05");
+    assert_eq!(trace["stackFrames"][1]["instructionPointerReference"], "0x0200");
+
+    assert_eq!(events(&session.request("stepIn", json!({}))), vec!["stopped"]);
+    assert_eq!(session.pc(), 0x208);
+    session.request("stepOut", json!({}));
+    assert_eq!(session.wait_stop(), "step");
+    assert_eq!(session.pc(), 0x202);
+
+    // Stepping over a call runs the whole subroutine
+    session.body("setInstructionBreakpoints", json!({ "breakpoints": [] }));
+    let vm = session.adapter.vm.as_mut().unwrap();
+    vm.set_registers(&Registers { pc: 0x200, ..vm.registers() });
+    session.request("next", json!({}));
+    assert!(session.adapter.running());
+    assert_eq!(session.wait_stop(), "step");
+    assert_eq!(session.pc(), 0x202);
+    assert_eq!(session.adapter.vm().unwrap().registers().v[0], 5);
+
+    session.request("continue", json!({}));
+    assert_eq!(session.adapter.run_frame(10), Vec::<Value>::new());
+    let replies = session.request("pause", json!({}));
+    assert_eq!(replies[1]["body"]["reason"], "pause");
+    assert_eq!(session.pc(), 0x204);
+}
+
+#[test]
+fn test_variables_and_memory() {
+    let mut session = Session::launch("rom.ch8", ROM);
+    session.request("stepIn", json!({}));
+
+    let registers = session.body("variables", json!({ "variablesReference": REGISTERS }));
+    assert_eq!(registers["variables"][0], json!({ "name": "V0", "value": "0x00", "variablesReference": 0 }));
+    assert_eq!(registers["variables"][17]["memoryReference"], "0x0206");
+    let stack = session.body("variables", json!({ "variablesReference": STACK }));
+    assert_eq!(stack["variables"], json!([{ "name": "0", "value": "0x0202", "variablesReference": 0, "memoryReference": "0x0202" }]));
+
+    let set = session.body("setVariable", json!({ "variablesReference": REGISTERS, "name": "VA", "value": "0x1f" }));
+    assert_eq!(set["value"], "0x1F");
+    assert_eq!(session.adapter.vm().unwrap().registers().v[0xA], 0x1F);
+    session.body("setVariable", json!({ "variablesReference": TIMERS, "name": "DT", "value": "30" }));
+    assert_eq!(session.adapter.vm().unwrap().registers().delay_timer, 30);
+    let replies = session.request("setVariable", json!({ "variablesReference": REGISTERS, "name": "V1", "value": "256" }));
+    assert_eq!(replies[0]["message"], "256 is too large for V1");
+
+    let memory = session.body("readMemory", json!({ "memoryReference": "0x200", "offset": 2, "count": 4 }));
+    assert_eq!(memory, json!({ "address": "0x0202", "data": "YQESBA==", "unreadableBytes": 0 }));
+    let end = session.body("readMemory", json!({ "memoryReference": "0xFFE", "count": 4 }));
+    assert_eq!(end["unreadableBytes"], 2);
+
+    let listing = session.body("disassemble", json!({ "memoryReference": "0x202", "instructionOffset": -1, "instructionCount": 2 }));
+    assert_eq!(listing["instructions"][0]["instruction"], "CALL 0x206");
+    assert_eq!(listing["instructions"][1]["instructionBytes"], "61 01");
+}
+
+#[test]
+fn test_decoding_follows_mode() {
+    // LD I, 0x0300; LD V0, 1 under XO-CHIP
+    let mut session = Session::launch("rom.xo8", &[0xF0, 0x00, 0x03, 0x00, 0x60, 0x01]);
+    let trace = session.body("stackTrace", json!({ "threadId": THREAD_ID }));
+    assert!(trace["stackFrames"][0]["name"].as_str().unwrap().contains("0x0300"), "{}", trace);
+
+    let listing = session.body("disassemble", json!({ "memoryReference": "0x200", "instructionCount": 2 }));
+    assert_eq!(listing["instructions"][0]["instructionBytes"], "F0 00 03 00");
+    assert_eq!(listing["instructions"][1]["address"], "0x0204");
+    assert_eq!(listing["instructions"][1]["instruction"], "LD V0, 0x01");
+}
+
+#[test]
+fn test_keys() {
+    // LD V0, K; JP 0x202
+    let mut session = Session::launch("rom.ch8", &[0xF0, 0x0A, 0x12, 0x02]);
+    let replies = session.request("stepIn", json!({}));
+    assert_eq!(replies[1]["body"]["reason"], "waiting for key");
+    let replies = session.request("setKeys", json!({ "keys": [16] }));
+    assert_eq!(replies[0]["success"], false);
+
+    session.body("setKeys", json!({ "keys": [7] }));
+    let replies = session.request("stepIn", json!({}));
+    assert_eq!(replies[1]["body"]["reason"], "waiting for key");
+    session.body("setKeys", json!({ "keys": [] }));
+    let replies = session.request("stepIn", json!({}));
+    assert_eq!(replies[1]["body"]["reason"], "step");
+    assert_eq!(session.pc(), 0x202);
+    assert_eq!(session.adapter.vm().unwrap().registers().v[0], 7);
+
+    // While running, keys reach the VM too
+    session.body("setVariable", json!({ "variablesReference": REGISTERS, "name": "PC", "value": "0x200" }));
+    session.body("setInstructionBreakpoints", json!({ "breakpoints": [{ "instructionReference": "0x202" }] }));
+    session.request("continue", json!({}));
+    session.adapter.run_frame(10);
+    assert!(session.adapter.running());
+    assert!(session.adapter.vm().unwrap().output().waiting);
+    session.body("setKeys", json!({ "keys": [3] }));
+    session.adapter.run_frame(10);
+    session.body("setKeys", json!({ "keys": [] }));
+    assert_eq!(session.wait_stop(), "breakpoint");
+    assert_eq!(session.adapter.vm().unwrap().registers().v[0], 3);
+}
+
+#[test]
+fn test_launch_rng() {
+    let dir = env::temp_dir().join(format!("chip8rs-dap-{}-rng", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let program = dir.join("rom.ch8");
+    fs::write(&program, ROM).unwrap();
+    let interpreter = dir.join("vip.bin");
+    fs::write(&interpreter, [0; 0x200]).unwrap();
+
+    let mut session = Session { adapter: Adapter::new(), seq: 0 };
+    let launch = |rng: String| json!({ "program": program, "rng": rng });
+    let replies = session.request("launch", launch("vip".to_string()));
+    assert!(replies[0]["message"].as_str().unwrap().starts_with("unknown random number generator"), "{}", replies[0]);
+    let replies = session.request("launch", launch(format!("vip:{}", dir.join("missing.bin").display())));
+    assert_eq!(replies[0]["success"], false);
+    let replies = session.request("launch", launch(format!("vip:{}", interpreter.display())));
+    fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(replies[0]["success"], true, "{}", replies[0]);
+}
+
+#[test]
+fn test_source_breakpoints() {
+    let mut session = Session::launch("prog.s", SOURCE.as_bytes());
+    let path = session.adapter.lines[0].path.clone();
+    let body = session.body("setBreakpoints", json!({
+        "source": { "path": path },
+        "breakpoints": [{ "line": 4 }, { "line": 9 }],
+    }));
+    assert_eq!(body["breakpoints"][0]["line"], 5);
+    assert_eq!(body["breakpoints"][0]["verified"], true);
+    assert_eq!(body["breakpoints"][1]["verified"], false);
+
+    session.request("continue", json!({}));
+    assert_eq!(session.wait_stop(), "breakpoint");
+    assert_eq!(session.pc(), 0x206);
+    let trace = session.body("stackTrace", json!({ "threadId": 1 }));
+    assert_eq!(trace["stackFrames"][0]["line"], 5);
+    assert_eq!(trace["stackFrames"][0]["source"]["name"], "prog.s");
+    assert_eq!(trace["stackFrames"][1]["line"], 1);
+}
+
+#[test]
+fn test_breakpoints_before_launch() {
+    let dir = env::temp_dir().join(format!("chip8rs-dap-{}-early", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let program = dir.join("prog.s");
+    fs::write(&program, SOURCE).unwrap();
+
+    // As VS Code does, right after the initialized event
+    let mut session = Session { adapter: Adapter::new(), seq: 0 };
+    session.request("initialize", json!({}));
+    let body = session.body("setBreakpoints", json!({ "source": { "path": program }, "breakpoints": [{ "line": 4 }] }));
+    assert_eq!(body["breakpoints"][0]["verified"], false);
+    let id = body["breakpoints"][0]["id"].clone();
+
+    let replies = session.request("launch", json!({ "program": program }));
+    fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(events(&replies), vec!["breakpoint"]);
+    assert_eq!(replies[1]["body"]["reason"], "changed");
+    assert_eq!(replies[1]["body"]["breakpoint"]["id"], id);
+    assert_eq!(replies[1]["body"]["breakpoint"]["verified"], true);
+    assert_eq!(replies[1]["body"]["breakpoint"]["line"], 5);
+
+    session.request("configurationDone", json!({}));
+    assert_eq!(session.wait_stop(), "breakpoint");
+    assert_eq!(session.pc(), 0x206);
+}
+
+#[test]
+fn test_base64() {
+    assert_eq!(base64(b""), "");
+    assert_eq!(base64(b"f"), "Zg==");
+    assert_eq!(base64(b"fo"), "Zm8=");
+    assert_eq!(base64(b"foobar"), "Zm9vYmFy");
+}
@@ -0,0 +1,518 @@
+use std::collections::BTreeSet;
+use std::convert::TryFrom;
+use std::io::{self, BufRead, Write};
+use super::emulator::present;
+use super::error::VmError;
+use super::frontend::{Audio, Clock, Command, Display, Input};
+use super::vm::VM;
+use super::watch::{WatchHit, WatchKind, Watchpoint};
+
+pub const HELP: &str = "\
+commands:
+  step [n]              run n instructions (default 1), without ticking timers or reading keys
+  continue              run in real time until a breakpoint, or P in the window
+  run-to-frame <n>      run in real time until frame n
+  break [addr]          set a breakpoint on pc, or list them
+  clear [addr]          remove a breakpoint, or all of them
+  watch <addr>[-<end>] [r|w|rw] [log]
+                        stop after an instruction writes (default) or reads that memory, or
+                        only log it; without arguments, list the watchpoints
+  unwatch [addr]        remove the watchpoints on addr, or all of them
+  print                 show the registers, stack and timers
+  hexdump <addr> [len]  dump memory (default 64 bytes)
+  poke <target> <n>     set V0-VF, I, pc, sp, dt, st or a memory address
+  disasm [addr] [n]     disassemble n instructions (default 10) around pc or from addr
+  help                  print this help
+  quit                  exit
+Numbers are decimal, or hex with 0x. An empty line repeats the last command.
+";
+
+const DEFAULT_HEXDUMP_LEN: usize = 64;
+const DEFAULT_DISASM_COUNT: usize = 10;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum DebugCommand {
+    Step(u32),
+    Continue,
+    RunToFrame(u64),
+    Break(Option<usize>),
+    Clear(Option<usize>),
+    Watch(Option<Watch>),
+    Unwatch(Option<usize>),
+    Print,
+    Hexdump { addr: usize, len: usize },
+    Poke(Target, u32),
+    Disasm { addr: Option<usize>, count: usize },
+    Help,
+    Quit,
+}
+
+// A watchpoint set by `watch`, which either stops the debugger or only logs its hits.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Watch {
+    pub watchpoint: Watchpoint,
+    pub log: bool,
+}
+
+impl Watch {
+    fn stops_on(&self, hit: &WatchHit) -> bool {
+        !self.log && self.watchpoint.kind.matches(hit.access) && self.watchpoint.overlaps(hit.addr, hit.new.len())
+    }
+}
+
+// What `poke` writes to.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Target {
+    V(usize),
+    I,
+    Pc,
+    Sp,
+    DelayTimer,
+    SoundTimer,
+    Memory(usize),
+}
+
+impl DebugCommand {
+    // Parses one REPL line. Returns `None` for an empty line.
+    pub fn parse(line: &str) -> Result<Option<DebugCommand>, String> {
+        let mut words = line.split_whitespace();
+        let name = match words.next() {
+            Some(name) => name,
+            None => return Ok(None),
+        };
+        let args: Vec<&str> = words.collect();
+        let arg = |n: usize| args.get(n).map(|a| number(a)).transpose();
+        let required = |n: usize, what: &str| arg(n)?.ok_or(format!("{} needs {}", name, what));
+        let command = match name {
+            "s" | "step" => DebugCommand::Step(arg(0)?.unwrap_or(1).max(1) as u32),
+            "c" | "continue" => DebugCommand::Continue,
+            "run-to-frame" => DebugCommand::RunToFrame(required(0, "a frame")? as u64),
+            "b" | "break" => DebugCommand::Break(arg(0)?),
+            "clear" => DebugCommand::Clear(arg(0)?),
+            "w" | "watch" if args.is_empty() => DebugCommand::Watch(None),
+            "w" | "watch" => DebugCommand::Watch(Some(parse_watch(&args)?)),
+            "unwatch" => DebugCommand::Unwatch(arg(0)?),
+            "p" | "print" => DebugCommand::Print,
+            "x" | "hexdump" => DebugCommand::Hexdump {
+                addr: required(0, "an address")?,
+                len: arg(1)?.unwrap_or(DEFAULT_HEXDUMP_LEN),
+            },
+            "poke" => {
+                let target = args.first().ok_or("poke needs a target and a value")?;
+                let value = required(1, "a value")?;
+                DebugCommand::Poke(parse_target(target)?, value as u32)
+            }
+            "d" | "disasm" => DebugCommand::Disasm {
+                addr: arg(0)?,
+                count: arg(1)?.unwrap_or(DEFAULT_DISASM_COUNT),
+            },
+            "h" | "help" => DebugCommand::Help,
+            "q" | "quit" => DebugCommand::Quit,
+            _ => return Err(format!("unknown command {:?}, try help", name)),
+        };
+        Ok(Some(command))
+    }
+}
+
+fn number(s: &str) -> Result<usize, String> {
+    let parsed = match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        Some(hex) => usize::from_str_radix(hex, 16),
+        None => s.parse(),
+    };
+    parsed.map_err(|_| format!("invalid number {:?}", s))
+}
+
+// "0x300-0x302 rw log": an address or an inclusive range, then optionally the kind and "log".
+fn parse_watch(args: &[&str]) -> Result<Watch, String> {
+    let (start, end) = match args[0].split_once('-') {
+        Some((start, end)) => (number(start)?, number(end)?),
+        None => (number(args[0])?, number(args[0])?),
+    };
+    if start > end {
+        return Err(format!("invalid range {:?}", args[0]));
+    }
+    let mut watch = Watch { watchpoint: Watchpoint { start, end, kind: WatchKind::Write }, log: false };
+    for &arg in &args[1..] {
+        match arg {
+            "log" => watch.log = true,
+            _ => watch.watchpoint.kind = WatchKind::from_name(arg).ok_or(format!("expected r, w, rw or log, got {:?}", arg))?,
+        }
+    }
+    Ok(watch)
+}
+
+fn parse_target(s: &str) -> Result<Target, String> {
+    let lower = s.to_ascii_lowercase();
+    let target = match lower.as_str() {
+        "i" => Target::I,
+        "pc" => Target::Pc,
+        "sp" => Target::Sp,
+        "dt" => Target::DelayTimer,
+        "st" => Target::SoundTimer,
+        _ if lower.len() == 2 && lower.starts_with('v') => {
+            let x = usize::from_str_radix(&lower[1..], 16).map_err(|_| format!("invalid register {:?}", s))?;
+            Target::V(x)
+        }
+        _ => Target::Memory(number(s)?),
+    };
+    Ok(target)
+}
+
+// Why `continue` or `run-to-frame` gave the prompt back.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Stop {
+    Breakpoint(usize),
+    Watchpoint,
+    Frame,
+    Halted,
+    Paused,
+    Quit,
+}
+
+// An interactive debugger around a VM. Frames only advance while running with `continue` or
+// `run-to-frame`; `step` executes bare instructions. A frame interrupted by a breakpoint is
+// finished by the next run.
+pub struct Debugger {
+    vm: VM,
+    breakpoints: BTreeSet<usize>,
+    watches: Vec<Watch>,
+    hits: Vec<WatchHit>,  // not printed yet
+    frame: u64,
+    executed: u32,  // instructions of the current frame already run
+    last: Option<DebugCommand>,
+}
+
+impl Debugger {
+    pub fn new(vm: VM) -> Self {
+        Debugger {
+            vm,
+            breakpoints: BTreeSet::new(),
+            watches: Vec::new(),
+            hits: Vec::new(),
+            frame: 0,
+            executed: 0,
+            last: None,
+        }
+    }
+
+    pub fn vm(&self) -> &VM {
+        &self.vm
+    }
+
+    pub fn vm_mut(&mut self) -> &mut VM {
+        &mut self.vm
+    }
+
+    pub fn frame(&self) -> u64 {
+        self.frame
+    }
+
+    // Reads commands from `input` until "quit", the end of the input or the window is closed.
+    pub fn repl<R, W, F, C>(&mut self, input: R, output: &mut W, frontend: &mut F, clock: &mut C) -> io::Result<()>
+    where
+        R: BufRead,
+        W: Write,
+        F: Display + Input + Audio,
+        C: Clock,
+    {
+        self.show_output(frontend);
+        let mut lines = input.lines();
+        loop {
+            write!(output, "(chip8rs) ")?;
+            output.flush()?;
+            let line = match lines.next() {
+                Some(line) => line?,
+                None => return Ok(()),
+            };
+            let command = match DebugCommand::parse(&line) {
+                Ok(Some(command)) => command,
+                Ok(None) => match self.last {
+                    Some(command) => command,
+                    None => continue,
+                },
+                Err(err) => {
+                    writeln!(output, "{}", err)?;
+                    continue;
+                }
+            };
+            self.last = Some(command);
+            if !self.execute(command, output, frontend, clock)? {
+                return Ok(());
+            }
+        }
+    }
+
+    // Runs one command. Returns `false` once the user wants to quit.
+    pub fn execute<W, F, C>(&mut self, command: DebugCommand, output: &mut W, frontend: &mut F, clock: &mut C) -> io::Result<bool>
+    where
+        W: Write,
+        F: Display + Input + Audio,
+        C: Clock,
+    {
+        match command {
+            DebugCommand::Step(n) => {
+                for _ in 0..n {
+                    if self.vm.output().waiting {
+                        writeln!(output, "waiting for a key, continue and press one in the window")?;
+                        break;
+                    }
+                    if let Err(err) = self.vm.step_instruction() {
+                        writeln!(output, "{}", err)?;
+                        break;
+                    }
+                    if self.take_hits() {
+                        break;
+                    }
+                }
+                self.print_hits(output)?;
+                self.show_output(frontend);
+                self.disassemble(output, self.vm.registers().pc, 1)?;
+            }
+            DebugCommand::Continue => return self.run(u64::MAX, output, frontend, clock),
+            DebugCommand::RunToFrame(frame) => {
+                if frame <= self.frame {
+                    writeln!(output, "already at frame {}", self.frame)?;
+                } else {
+                    return self.run(frame, output, frontend, clock);
+                }
+            }
+            DebugCommand::Break(None) => {
+                for addr in &self.breakpoints {
+                    writeln!(output, "breakpoint at 0x{:04x}", addr)?;
+                }
+            }
+            DebugCommand::Break(Some(addr)) => {
+                self.breakpoints.insert(addr);
+            }
+            DebugCommand::Clear(None) => self.breakpoints.clear(),
+            DebugCommand::Clear(Some(addr)) => {
+                if !self.breakpoints.remove(&addr) {
+                    writeln!(output, "no breakpoint at 0x{:04x}", addr)?;
+                }
+            }
+            DebugCommand::Watch(None) => {
+                for watch in &self.watches {
+                    let w = watch.watchpoint;
+                    let kind = match w.kind {
+                        WatchKind::Read => "r",
+                        WatchKind::Write => "w",
+                        WatchKind::ReadWrite => "rw",
+                    };
+                    let log = if watch.log { " log" } else { "" };
+                    writeln!(output, "watchpoint on 0x{:04x}-0x{:04x} {}{}", w.start, w.end, kind, log)?;
+                }
+            }
+            DebugCommand::Watch(Some(watch)) => {
+                self.watches.push(watch);
+                self.update_watchpoints();
+            }
+            DebugCommand::Unwatch(None) => {
+                self.watches.clear();
+                self.update_watchpoints();
+            }
+            DebugCommand::Unwatch(Some(addr)) => {
+                let before = self.watches.len();
+                self.watches.retain(|watch| !watch.watchpoint.overlaps(addr, 1));
+                if self.watches.len() == before {
+                    writeln!(output, "no watchpoint on 0x{:04x}", addr)?;
+                }
+                self.update_watchpoints();
+            }
+            DebugCommand::Print => self.print_registers(output)?,
+            DebugCommand::Hexdump { addr, len } => self.hexdump(output, addr, len)?,
+            DebugCommand::Poke(target, value) => {
+                if let Err(err) = self.poke(target, value) {
+                    writeln!(output, "{}", err)?;
+                }
+            }
+            DebugCommand::Disasm { addr: Some(addr), count } => self.disassemble(output, addr, count)?,
+            DebugCommand::Disasm { addr: None, count } => {
+                // Start a few instructions back so the pc is in the middle
+                let start = self.vm.registers().pc.saturating_sub(count / 2 * 2);
+                self.disassemble(output, start, count)?;
+            }
+            DebugCommand::Help => write!(output, "{}", HELP)?,
+            DebugCommand::Quit => return Ok(false),
+        }
+        Ok(true)
+    }
+
+    // Runs frames in real time until `frame`, a breakpoint, a fault or the user pauses.
+    fn run<W, F, C>(&mut self, frame: u64, output: &mut W, frontend: &mut F, clock: &mut C) -> io::Result<bool>
+    where
+        W: Write,
+        F: Display + Input + Audio,
+        C: Clock,
+    {
+        // Don't stop on the breakpoint we are sitting on
+        let mut resumed = true;
+        let stop = loop {
+            let keypad = match frontend.poll() {
+                Some(keypad) => keypad,
+                None => break Ok(Stop::Quit),
+            };
+            if frontend.commands().contains(&Command::TogglePause) {
+                break Ok(Stop::Paused);
+            }
+            let stop = self.run_frame(keypad, clock.instructions_this_frame(), resumed);
+            self.print_hits(output)?;
+            match stop {
+                Ok(Stop::Frame) if self.frame >= frame => break Ok(Stop::Frame),
+                Ok(Stop::Frame) => {}
+                other => break other,
+            }
+            resumed = false;
+            self.show_output(frontend);
+            clock.wait_next_frame();
+        };
+        frontend.play(false, None);
+        self.show_output(frontend);
+
+        match stop {
+            Ok(Stop::Quit) => return Ok(false),
+            Ok(Stop::Breakpoint(pc)) => writeln!(output, "breakpoint at 0x{:04x}, frame {}", pc, self.frame)?,
+            Ok(Stop::Watchpoint) => writeln!(output, "watchpoint, frame {}", self.frame)?,
+            Ok(Stop::Frame) | Ok(Stop::Paused) => writeln!(output, "frame {}", self.frame)?,
+            Ok(Stop::Halted) => writeln!(output, "halted, frame {}", self.frame)?,
+            Err(err) => writeln!(output, "{}", err)?,
+        }
+        self.disassemble(output, self.vm.registers().pc, 1)?;
+        Ok(true)
+    }
+
+    // Runs the rest of the current frame, checking for breakpoints before each instruction.
+    fn run_frame(&mut self, keypad: [bool; 16], instructions: u32, resumed: bool) -> Result<Stop, VmError> {
+        let mut check = !resumed;
+        while self.executed < instructions {
+            let output = self.vm.output();
+            if output.halted {
+                return Ok(Stop::Halted);
+            }
+            let pc = self.vm.registers().pc;
+            if check && !output.waiting && self.breakpoints.contains(&pc) {
+                return Ok(Stop::Breakpoint(pc));
+            }
+            check = true;
+            self.vm.step(keypad)?;
+            self.executed += 1;
+            if self.take_hits() {
+                return Ok(Stop::Watchpoint);
+            }
+        }
+        self.vm.tick_timers();
+        self.executed = 0;
+        self.frame += 1;
+        Ok(Stop::Frame)
+    }
+
+    fn update_watchpoints(&mut self) {
+        self.vm.set_watchpoints(self.watches.iter().map(|watch| watch.watchpoint).collect());
+    }
+
+    // Collects the watched accesses of the last instruction, returning whether one of them should
+    // stop the debugger.
+    fn take_hits(&mut self) -> bool {
+        let hits = self.vm.take_watch_hits();
+        let stop = hits.iter().any(|hit| self.watches.iter().any(|watch| watch.stops_on(hit)));
+        self.hits.extend(hits);
+        stop
+    }
+
+    fn print_hits<W: Write>(&mut self, output: &mut W) -> io::Result<()> {
+        for hit in self.hits.drain(..) {
+            writeln!(output, "{}", hit)?;
+        }
+        Ok(())
+    }
+
+    fn show_output<F: Display + Audio>(&self, frontend: &mut F) {
+        let mut output = self.vm.output();
+        output.vram_changed = true;
+        present(frontend, &output, false);
+    }
+
+    fn print_registers<W: Write>(&self, output: &mut W) -> io::Result<()> {
+        let r = self.vm.registers();
+        writeln!(
+            output,
+            "pc 0x{:04x}  I 0x{:04x}  sp {}  dt {}  st {}  frame {}",
+            r.pc, r.i, r.sp, r.delay_timer, r.sound_timer, self.frame
+        )?;
+        for row in r.v.chunks(8).enumerate() {
+            let (n, values) = row;
+            let line: Vec<String> = values
+                .iter()
+                .enumerate()
+                .map(|(x, v)| format!("V{:X} {:02x}", n * 8 + x, v))
+                .collect();
+            writeln!(output, "{}", line.join("  "))?;
+        }
+        let stack: Vec<String> = r.stack[..r.sp].iter().map(|addr| format!("0x{:04x}", addr)).collect();
+        writeln!(output, "stack [{}]", stack.join(" "))
+    }
+
+    fn hexdump<W: Write>(&self, output: &mut W, addr: usize, len: usize) -> io::Result<()> {
+        let memory = self.vm.memory();
+        if addr >= memory.len() {
+            return writeln!(output, "0x{:04x} is outside memory", addr);
+        }
+        let end = addr.saturating_add(len).min(memory.len());
+        for start in (addr..end).step_by(16) {
+            let row = &memory[start..end.min(start + 16)];
+            let hex: Vec<String> = row.iter().map(|b| format!("{:02x}", b)).collect();
+            let text: String = row
+                .iter()
+                .map(|&b| if b.is_ascii_graphic() || b == b' ' { b as char } else { '.' })
+                .collect();
+            writeln!(output, "{:04x}  {:<47}  {}", start, hex.join(" "), text)?;
+        }
+        Ok(())
+    }
+
+    fn poke(&mut self, target: Target, value: u32) -> Result<(), String> {
+        let byte = || u8::try_from(value).map_err(|_| format!("{} doesn't fit in a byte", value));
+        let mut r = self.vm.registers();
+        match target {
+            Target::V(x) if x < r.v.len() => r.v[x] = byte()?,
+            Target::V(x) => return Err(format!("no register V{:X}", x)),
+            Target::I => r.i = value,
+            Target::Pc => r.pc = value as usize,
+            Target::Sp if (value as usize) <= r.stack.len() => r.sp = value as usize,
+            Target::Sp => return Err(format!("the stack only has {} entries", r.stack.len())),
+            Target::DelayTimer => r.delay_timer = byte()?,
+            Target::SoundTimer => r.sound_timer = byte()?,
+            Target::Memory(addr) => {
+                let value = byte()?;
+                let memory = self.vm.memory_mut();
+                let len = memory.len();
+                *memory.get_mut(addr).ok_or(format!("0x{:04x} is outside memory of {} bytes", addr, len))? = value;
+                return Ok(());
+            }
+        }
+        self.vm.set_registers(&r);
+        Ok(())
+    }
+
+    // Lists `count` instructions from `addr`, marking the pc with ">" and breakpoints with "*".
+    fn disassemble<W: Write>(&self, output: &mut W, addr: usize, count: usize) -> io::Result<()> {
+        let memory = self.vm.memory();
+        let pc = self.vm.registers().pc;
+        let mut addr = addr;
+        for _ in 0..count {
+            if addr + 1 >= memory.len() {
+                break;
+            }
+            let op = self.vm.instruction_at(addr);
+            let raw: String = memory[addr..(addr + op.size()).min(memory.len())].iter().map(|b| format!("{:02x}", b)).collect();
+            let marker = if addr == pc { '>' } else { ' ' };
+            let breakpoint = if self.breakpoints.contains(&addr) { '*' } else { ' ' };
+            writeln!(output, "{}{} {:04x}  {:<8}  {}", marker, breakpoint, addr, raw, op)?;
+            addr += op.size();
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+#[path = "./debugger_test.rs"]
+mod debugger_test;
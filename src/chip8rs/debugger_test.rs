@@ -0,0 +1,177 @@
+use super::*;
+use std::io::Cursor;
+use super::super::mode::Mode;
+use super::super::quirks::Quirks;
+use super::super::test_support::{Clock, Frontend};
+
+
+// LD V0, 1; ADD V1, V0; LD DT, V1; JP 0x202
+const ROM: &[u8] = &[0x60, 0x01, 0x71, 0x01, 0xF1, 0x15, 0x12, 0x02];
+
+fn debugger() -> Debugger {
+    let mut vm = VM::new();
+    vm.load(ROM);
+    Debugger::new(vm)
+}
+
+// Runs `script` through the REPL and returns what it printed.
+fn repl(debugger: &mut Debugger, script: &str) -> String {
+    let mut frontend = Frontend::new(1000);
+    let mut output = Vec::new();
+    debugger.repl(Cursor::new(script), &mut output, &mut frontend, &mut Clock).unwrap();
+    String::from_utf8(output).unwrap()
+}
+
+#[test]
+fn test_parse() {
+    assert_eq!(DebugCommand::parse("  "), Ok(None));
+    assert_eq!(DebugCommand::parse("step"), Ok(Some(DebugCommand::Step(1))));
+    assert_eq!(DebugCommand::parse("s 0x10"), Ok(Some(DebugCommand::Step(16))));
+    assert_eq!(DebugCommand::parse("run-to-frame 60"), Ok(Some(DebugCommand::RunToFrame(60))));
+    assert_eq!(DebugCommand::parse("break 0x204"), Ok(Some(DebugCommand::Break(Some(0x204)))));
+    assert_eq!(DebugCommand::parse("clear"), Ok(Some(DebugCommand::Clear(None))));
+    assert_eq!(
+        DebugCommand::parse("hexdump 0x200"),
+        Ok(Some(DebugCommand::Hexdump { addr: 0x200, len: DEFAULT_HEXDUMP_LEN }))
+    );
+    assert_eq!(DebugCommand::parse("poke VA 3"), Ok(Some(DebugCommand::Poke(Target::V(10), 3))));
+    assert_eq!(DebugCommand::parse("poke 0x300 0xff"), Ok(Some(DebugCommand::Poke(Target::Memory(0x300), 0xff))));
+    assert!(DebugCommand::parse("run-to-frame").is_err());
+    assert!(DebugCommand::parse("poke vz 1").is_err());
+    assert!(DebugCommand::parse("frobnicate").is_err());
+}
+
+#[test]
+fn test_step_skips_timers() {
+    let mut debugger = debugger();
+    let output = repl(&mut debugger, "step 3\n");
+    assert!(output.contains(">  0206"), "{}", output);
+    let registers = debugger.vm().registers();
+    assert_eq!(registers.v[1], 1);
+    assert_eq!(registers.delay_timer, 1);
+    assert_eq!(debugger.frame(), 0);
+}
+
+#[test]
+fn test_step_through_wait_key() {
+    let mut vm = VM::new();
+    vm.load(&[0xF5, 0x0A]);
+    let mut debugger = Debugger::new(vm);
+    let output = repl(&mut debugger, "step\nstep\n");
+    assert!(output.contains("waiting for a key, continue and press one in the window"), "{}", output);
+    assert_eq!(debugger.vm().registers().pc, 0x202);
+
+    // Running reads the keys
+    let mut frontend = Frontend::new(1000);
+    let mut output = Vec::new();
+    frontend.keys[7] = true;
+    debugger.execute(DebugCommand::RunToFrame(1), &mut output, &mut frontend, &mut Clock).unwrap();
+    frontend.keys[7] = false;
+    debugger.execute(DebugCommand::RunToFrame(2), &mut output, &mut frontend, &mut Clock).unwrap();
+    assert_eq!(debugger.vm().registers().v[5], 7);
+}
+
+#[test]
+fn test_empty_line_repeats() {
+    let mut debugger = debugger();
+    repl(&mut debugger, "step\n\n\n");
+    assert_eq!(debugger.vm().registers().pc, 0x206);
+}
+
+#[test]
+fn test_breakpoint() {
+    let mut debugger = debugger();
+    let output = repl(&mut debugger, "break 0x204\ncontinue\ncontinue\n");
+    assert_eq!(output.matches("breakpoint at 0x0204, frame 0").count(), 2, "{}", output);
+    // The second continue went once more around the loop, without stopping where it started
+    assert_eq!(debugger.vm().registers().v[1], 2);
+}
+
+#[test]
+fn test_run_to_frame() {
+    let mut debugger = debugger();
+    let output = repl(&mut debugger, "run-to-frame 3\n");
+    assert!(output.contains("frame 3"), "{}", output);
+    assert_eq!(debugger.frame(), 3);
+}
+
+#[test]
+fn test_pause_stops_running() {
+    let mut debugger = debugger();
+    let mut frontend = Frontend::new(1000);
+    frontend.commands.push(Command::TogglePause);
+    let mut output = Vec::new();
+    let more = debugger.execute(DebugCommand::Continue, &mut output, &mut frontend, &mut Clock).unwrap();
+    assert!(more);
+    assert_eq!(debugger.frame(), 0);
+}
+
+#[test]
+fn test_window_closed() {
+    let mut debugger = debugger();
+    let mut frontend = Frontend::new(2);
+    let mut output = Vec::new();
+    let more = debugger.execute(DebugCommand::Continue, &mut output, &mut frontend, &mut Clock).unwrap();
+    assert!(!more);
+    assert_eq!(debugger.frame(), 2);
+}
+
+#[test]
+fn test_poke_and_print() {
+    let mut debugger = debugger();
+    let output = repl(&mut debugger, "poke v3 0x1f\npoke i 0x300\npoke 0x300 0xab\npoke v0 256\nprint\nhexdump 0x300 2\n");
+    assert!(output.contains("256 doesn't fit in a byte"), "{}", output);
+    assert!(output.contains("I 0x0300"), "{}", output);
+    assert!(output.contains("V3 1f"), "{}", output);
+    assert!(output.contains("0300  ab 00"), "{}", output);
+    assert_eq!(debugger.vm().memory()[0x300], 0xab);
+}
+
+#[test]
+fn test_watchpoints() {
+    // LD I, 0x300; LD B, V1; ADD V1, 1; JP 0x202
+    let mut vm = VM::new();
+    vm.load(&[0xA3, 0x00, 0xF1, 0x33, 0x71, 0x01, 0x12, 0x02]);
+    let mut debugger = Debugger::new(vm);
+    let output = repl(&mut debugger, "watch 0x302\nwatch 0x300-0x301 rw log\nwatch\ncontinue\ncontinue\n");
+    assert!(output.contains("watchpoint on 0x0302-0x0302 w\nwatchpoint on 0x0300-0x0301 rw log\n"), "{}", output);
+    assert!(output.contains("0x0202 LD B, V1 wrote 0x0300: 00 00 00 -> 00 00 00\nwatchpoint, frame 0"), "{}", output);
+    assert!(output.contains("0x0202 LD B, V1 wrote 0x0300: 00 00 00 -> 00 00 01\nwatchpoint, frame 0"), "{}", output);
+    assert_eq!(debugger.vm().registers().pc, 0x204);
+
+    // Only logged, so running carries on to the frame
+    let output = repl(&mut debugger, "unwatch 0x302\nrun-to-frame 2\nunwatch 0x302\n");
+    assert_eq!(output.matches(" wrote 0x0300").count(), 5, "{}", output);
+    assert!(output.contains("frame 2"), "{}", output);
+    assert!(output.contains("no watchpoint on 0x0302"), "{}", output);
+
+    assert!(DebugCommand::parse("watch 0x302-0x300").is_err());
+    assert!(DebugCommand::parse("watch 0x300 x").is_err());
+}
+
+#[test]
+fn test_disasm_marks_pc_and_breakpoints() {
+    let mut debugger = debugger();
+    let output = repl(&mut debugger, "break 0x202\ndisasm 0x200 2\n");
+    assert!(output.contains(">  0200  6001"), "{}", output);
+    assert!(output.contains(" * 0202  7101"), "{}", output);
+}
+
+#[test]
+fn test_disasm_follows_mode() {
+    // LD I, 0x0300; LD V0, 1 under XO-CHIP, where F000 takes the next word as its address.
+    let mut vm = VM::with_mode(Mode::XoChip, Quirks::default());
+    vm.load(&[0xF0, 0x00, 0x03, 0x00, 0x60, 0x01]);
+    let mut debugger = Debugger::new(vm);
+    let output = repl(&mut debugger, "disasm 0x200 2\n");
+    assert!(output.contains(">  0200  f0000300"), "{}", output);
+    assert!(output.contains("   0204  6001"), "{}", output);
+
+    // Plain CHIP-8 has no F000, so the same bytes list as two words.
+    let mut vm = VM::new();
+    vm.load(&[0xF0, 0x00, 0x03, 0x00, 0x60, 0x01]);
+    let mut debugger = Debugger::new(vm);
+    let output = repl(&mut debugger, "disasm 0x200 2\n");
+    assert!(output.contains(">  0200  f000"), "{}", output);
+    assert!(output.contains("   0202  0300"), "{}", output);
+}
@@ -0,0 +1,186 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use super::flow::{self, ControlFlow, EdgeKind, Op};
+use super::instruction::Instruction;
+use super::mode::Mode;
+use super::vm::PROGRAM_START;
+
+// Instructions after an `LD I` searched for a `DRW` using it.
+const SPRITE_LOOKAHEAD: usize = 8;
+// Data bytes per `DB` line, outside of sprites.
+const DATA_PER_LINE: usize = 8;
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+enum Label {
+    Data,
+    Sprite,
+    Jump,
+    Call,
+}
+
+// Disassembles a ROM loaded at 0x200 into a listing of address, raw opcode and mnemonic.
+//
+// Code is found by following the control flow from 0x200, see `flow::analyze`, so bytes never
+// reached are listed as data. Jump and call targets get labels, and data that an `LD I` points at
+// shortly before a `DRW` is marked as a sprite, with its pixels drawn in a comment.
+pub fn disassemble(rom: &[u8], mode: Mode) -> String {
+    let mut listing = Listing::new(rom, mode);
+    listing.label_targets();
+    listing.find_data();
+    listing.render()
+}
+
+struct Listing<'a> {
+    rom: &'a [u8],
+    flow: ControlFlow,
+    labels: BTreeMap<usize, Label>,
+}
+
+impl<'a> Listing<'a> {
+    fn new(rom: &'a [u8], mode: Mode) -> Self {
+        Listing {
+            rom,
+            flow: flow::analyze(rom, mode),
+            labels: BTreeMap::new(),
+        }
+    }
+
+    fn contains(&self, addr: usize) -> bool {
+        addr >= PROGRAM_START && addr < PROGRAM_START + self.rom.len()
+    }
+
+    fn label(&mut self, addr: usize, label: Label) {
+        let entry = self.labels.entry(addr).or_insert(label);
+        *entry = (*entry).max(label);
+    }
+
+    fn label_targets(&mut self) {
+        let mut found = Vec::new();
+        for block in self.flow.blocks.values() {
+            for edge in &block.successors {
+                match edge.kind {
+                    EdgeKind::Jump | EdgeKind::Indirect => found.push((edge.to, Label::Jump)),
+                    EdgeKind::Call => found.push((edge.to, Label::Call)),
+                    EdgeKind::Fallthrough | EdgeKind::Skip => {}
+                }
+            }
+        }
+        for (addr, label) in found {
+            self.label(addr, label);
+        }
+    }
+
+    // Labels the data `I` is pointed at, as sprites when a `DRW` follows.
+    fn find_data(&mut self) {
+        let mut found = Vec::new();
+        let ops = &self.flow.instructions;
+        let addrs: Vec<usize> = ops.keys().cloned().collect();
+        for (n, &addr) in addrs.iter().enumerate() {
+            let target = match ops[&addr] {
+                Op::Chip(Instruction::LoadI(target)) => target as usize,
+                Op::Chip(Instruction::LoadLongI(target)) => target as usize,
+                _ => continue,
+            };
+            if !self.contains(target) || self.flow.is_code(target) {
+                continue;
+            }
+            let mut drawn = false;
+            for following in addrs[n + 1..].iter().take(SPRITE_LOOKAHEAD) {
+                match ops[following] {
+                    Op::Chip(Instruction::Draw(..)) => {
+                        drawn = true;
+                        break;
+                    }
+                    Op::Chip(Instruction::LoadI(_))
+                    | Op::Chip(Instruction::LoadLongI(_))
+                    | Op::Chip(Instruction::Jump(_))
+                    | Op::Chip(Instruction::Return) => break,
+                    _ => {}
+                }
+            }
+            found.push((target, if drawn { Label::Sprite } else { Label::Data }));
+        }
+        for (addr, label) in found {
+            self.label(addr, label);
+        }
+    }
+
+    fn label_name(&self, addr: usize) -> Option<String> {
+        let prefix = match self.labels.get(&addr)? {
+            Label::Jump => "label",
+            Label::Call => "sub",
+            Label::Sprite => "sprite",
+            Label::Data => "data",
+        };
+        if !self.contains(addr) {
+            return None;
+        }
+        Some(format!("{}_{:03X}", prefix, addr))
+    }
+
+    // The instruction's mnemonic, with its target address replaced by a label where there is one.
+    fn mnemonic(&self, op: &Op) -> String {
+        let (instruction, target) = match op {
+            Op::Mega(instruction) => return instruction.to_string(),
+            Op::Chip(instruction) => match *instruction {
+                Instruction::Jump(addr) => ("JP", addr),
+                Instruction::Call(addr) => ("CALL", addr),
+                Instruction::LongJump(addr) => ("JP V0,", addr),
+                Instruction::LoadI(addr) => ("LD I,", addr),
+                Instruction::LoadLongI(addr) => ("LD I, LONG", addr),
+                _ => return instruction.to_string(),
+            },
+        };
+        match self.label_name(target as usize) {
+            Some(label) => format!("{} {}", instruction, label),
+            None => op.to_string(),
+        }
+    }
+
+    fn render(&self) -> String {
+        let mut out = String::new();
+        let end = PROGRAM_START + self.rom.len();
+        let mut sprite = false;
+        let mut addr = PROGRAM_START;
+        while addr < end {
+            if let Some(label) = self.label_name(addr) {
+                sprite = self.labels[&addr] == Label::Sprite;
+                writeln!(out, "{}:", label).unwrap();
+            }
+            if let Some(op) = self.flow.instructions.get(&addr) {
+                let raw: String = self.rom[addr - PROGRAM_START..addr - PROGRAM_START + op.size()]
+                    .chunks(2)
+                    .map(|word| word.iter().map(|b| format!("{:02X}", b)).collect::<String>())
+                    .collect();
+                writeln!(out, "{:04X}  {:<8}  {}", addr, raw, self.mnemonic(op)).unwrap();
+                sprite = false;
+                addr += op.size();
+                continue;
+            }
+
+            // Data runs until the next instruction or label
+            let mut len = 1;
+            let per_line = if sprite { 1 } else { DATA_PER_LINE };
+            while len < per_line && addr + len < end && !self.flow.instructions.contains_key(&(addr + len))
+                && !self.labels.contains_key(&(addr + len))
+            {
+                len += 1;
+            }
+            let bytes = &self.rom[addr - PROGRAM_START..addr - PROGRAM_START + len];
+            let raw: String = bytes.iter().map(|b| format!("{:02X}", b)).collect();
+            let values: Vec<String> = bytes.iter().map(|b| format!("0x{:02X}", b)).collect();
+            write!(out, "{:04X}  {:<8}  DB {}", addr, raw, values.join(", ")).unwrap();
+            if sprite {
+                let pixels: String = (0..8).map(|bit| if bytes[0] & (0x80 >> bit) != 0 { '#' } else { '.' }).collect();
+                write!(out, "  ; {}", pixels).unwrap();
+            }
+            out.push('\n');
+            addr += len;
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+#[path = "./disasm_test.rs"]
+mod disasm_test;
@@ -0,0 +1,49 @@
+use super::*;
+
+
+#[test]
+fn test_labels_and_sprites() {
+    let rom = [
+        0x00, 0xE0,  // 200: CLS
+        0x22, 0x08,  // 202: CALL sub_208
+        0x12, 0x02,  // 204: JP label_202
+        0xFF, 0xFF,  // 206: never reached
+        0xA2, 0x0E,  // 208: LD I, sprite_20E
+        0xD0, 0x11,  // 20A: DRW V0, V1, 1
+        0x00, 0xEE,  // 20C: RET
+        0x81,        // 20E: sprite
+    ];
+    let listing = disassemble(&rom, Mode::Chip8);
+    let expected = "\
+0200  00E0      CLS
+label_202:
+0202  2208      CALL sub_208
+0204  1202      JP label_202
+0206  FFFF      DB 0xFF, 0xFF
+sub_208:
+0208  A20E      LD I, sprite_20E
+020A  D011      DRW V0, V1, 1
+020C  00EE      RET
+sprite_20E:
+020E  81        DB 0x81  ; #......#
+";
+    assert_eq!(listing, expected);
+}
+
+#[test]
+fn test_skips_follow_both_paths() {
+    // SE V0, 0; JP 0x208; LD I, LONG 0x020A; JP 0x208
+    let rom = [0x30, 0x00, 0x12, 0x08, 0xF0, 0x00, 0x02, 0x0A, 0x12, 0x08, 0xAB, 0xCD];
+    let listing = disassemble(&rom, Mode::XoChip);
+    assert!(listing.contains("0204  F000020A  LD I, LONG data_20A\n"), "{}", listing);
+    assert!(listing.contains("data_20A:\n020A  ABCD      DB 0xAB, 0xCD\n"), "{}", listing);
+}
+
+#[test]
+fn test_mode_limits_instructions() {
+    // SCR is data on plain CHIP-8
+    let listing = disassemble(&[0x00, 0xFB], Mode::Chip8);
+    assert_eq!(listing, "0200  00FB      DB 0x00, 0xFB\n");
+    let listing = disassemble(&[0x00, 0xFB], Mode::SuperChip);
+    assert_eq!(listing, "0200  00FB      SCR\n");
+}
@@ -0,0 +1,28 @@
+use super::vm::{SCHIP_HEIGHT, SCHIP_WIDTH};
+
+// Text renderings of the CHIP-8 screen, for comparing runs.
+
+// One character per pixel, "#" when lit on any plane.
+pub fn ascii(vram: &[[u8; SCHIP_WIDTH]; SCHIP_HEIGHT], width: usize, height: usize) -> String {
+    let mut out = String::new();
+    for row in &vram[..height] {
+        out.extend(row[..width].iter().map(|&p| if p != 0 { '#' } else { '.' }));
+        out.push('\n');
+    }
+    out
+}
+
+// Plain PBM (P1), 1 for lit pixels.
+pub fn pbm(vram: &[[u8; SCHIP_WIDTH]; SCHIP_HEIGHT], width: usize, height: usize) -> String {
+    let mut out = format!("P1\n{} {}\n", width, height);
+    for row in &vram[..height] {
+        let pixels: Vec<&str> = row[..width].iter().map(|&p| if p != 0 { "1" } else { "0" }).collect();
+        out.push_str(&pixels.join(" "));
+        out.push('\n');
+    }
+    out
+}
+
+#[cfg(test)]
+#[path = "./dump_test.rs"]
+mod dump_test;
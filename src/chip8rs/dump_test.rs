@@ -0,0 +1,11 @@
+use super::*;
+
+
+#[test]
+fn test_dumps() {
+    let mut vram = [[0; SCHIP_WIDTH]; SCHIP_HEIGHT];
+    vram[0][1] = 1;
+    vram[1][0] = 3;
+    assert_eq!(ascii(&vram, 3, 2), ".#.\n#..\n");
+    assert_eq!(pbm(&vram, 3, 2), "P1\n3 2\n0 1 0\n1 0 0\n");
+}
@@ -0,0 +1,205 @@
+use std::fmt;
+use std::mem;
+use super::error::VmError;
+use super::frontend::{Audio, Clock, Command, Display, Input};
+use super::movie::{movie_path, FrameInput, Movie, MovieError};
+use super::rewind::{Rewind, DEFAULT_BUDGET, DEFAULT_LENGTH};
+use super::rom::Rom;
+use super::snapshot::{slot_path, Snapshot};
+use super::vm::{OutputState, VM};
+
+// Where the keypad comes from, and whether it is being recorded.
+enum Tape {
+    Off,
+    Recording(Movie),
+    Playing { movie: Movie, inputs: std::vec::IntoIter<FrameInput>, frame: u64 },
+}
+
+impl Tape {
+    // The next frame of the movie being played, if any. Stops the playback at the end.
+    fn next_input(&mut self) -> Option<FrameInput> {
+        if let Tape::Playing { inputs, .. } = self {
+            let input = inputs.next();
+            if input.is_none() {
+                *self = Tape::Off;
+            }
+            return input;
+        }
+        None
+    }
+
+    // Records, or checks, the frame `vm` has just run.
+    fn after_frame(&mut self, input: FrameInput, vm: &VM) -> Result<(), MovieError> {
+        match self {
+            Tape::Off => Ok(()),
+            Tape::Recording(movie) => {
+                movie.record(input, vm);
+                Ok(())
+            }
+            Tape::Playing { movie, frame, .. } => {
+                *frame += 1;
+                movie.check(*frame, vm)
+            }
+        }
+    }
+}
+
+// Runs a VM against any frontend: feeds it input and the clock's instruction budget every frame,
+// presents its output, and carries out the frontend's commands (save states, movies, rewind and
+// pause). Errors from commands are reported on stderr, they don't stop emulation.
+pub struct Emulator {
+    vm: VM,
+    rom: Rom,
+    rewind: Rewind,
+    tape: Tape,
+    paused: bool,
+}
+
+impl Emulator {
+    // Loads `rom` into `vm`.
+    pub fn new(mut vm: VM, rom: Rom) -> Self {
+        vm.load(&rom.bytes);
+        Emulator {
+            vm,
+            rom,
+            rewind: Rewind::new(DEFAULT_LENGTH, DEFAULT_BUDGET),
+            tape: Tape::Off,
+            paused: false,
+        }
+    }
+
+    pub fn vm(&self) -> &VM {
+        &self.vm
+    }
+
+    pub fn vm_mut(&mut self) -> &mut VM {
+        &mut self.vm
+    }
+
+    pub fn into_vm(self) -> VM {
+        self.vm
+    }
+
+    pub fn set_paused(&mut self, paused: bool) {
+        self.paused = paused;
+    }
+
+    pub fn set_rewind(&mut self, rewind: Rewind) {
+        self.rewind = rewind;
+    }
+
+    // Runs frames until the user quits, or the ROM faults.
+    pub fn run<F, C>(&mut self, frontend: &mut F, clock: &mut C) -> Result<(), VmError>
+    where
+        F: Display + Input + Audio,
+        C: Clock,
+    {
+        while self.run_frame(frontend, clock)? {
+            clock.wait_next_frame();
+        }
+        Ok(())
+    }
+
+    // Runs and presents one frame, without waiting for the next one. Returns `false` once the user
+    // wants to quit.
+    pub fn run_frame<F, C>(&mut self, frontend: &mut F, clock: &C) -> Result<bool, VmError>
+    where
+        F: Display + Input + Audio,
+        C: Clock,
+    {
+        let keypad = match frontend.poll() {
+            Some(keypad) => keypad,
+            None => return Ok(false),
+        };
+        for command in frontend.commands() {
+            self.run_command(command);
+        }
+
+        if self.paused {
+            // Keep showing the current frame
+        } else if frontend.rewinding() && matches!(self.tape, Tape::Off) {
+            if let Some(snapshot) = self.rewind.pop() {
+                let restored = self.vm.restore(&snapshot);
+                self.report("rewind", restored);
+            }
+        } else {
+            let input = self.tape.next_input().unwrap_or(FrameInput {
+                keypad,
+                instructions: clock.instructions_this_frame(),
+            });
+            self.rewind.push(self.vm.snapshot());
+            self.vm.run_frame(input.keypad, input.instructions)?;
+            if let Err(err) = self.tape.after_frame(input, &self.vm) {
+                self.report("movie", Err(err));
+                self.tape = Tape::Off;
+            }
+        }
+
+        present(frontend, &self.vm.output(), self.paused);
+        Ok(true)
+    }
+
+    pub fn run_command(&mut self, command: Command) {
+        let rom_path = self.rom.path.clone();
+        let result = match command {
+            Command::SaveState(slot) => self.vm.snapshot().save(&slot_path(&rom_path, slot)).map_err(|e| e.to_string()),
+            Command::LoadState(_) if !matches!(self.tape, Tape::Off) => {
+                Err("not while a movie is recording or playing".to_string())
+            }
+            Command::LoadState(slot) => Snapshot::load(&slot_path(&rom_path, slot))
+                .and_then(|s| self.vm.restore(&s))
+                .map_err(|e| e.to_string()),
+            Command::ToggleRecording => match mem::replace(&mut self.tape, Tape::Off) {
+                Tape::Recording(movie) => movie.save(&movie_path(&rom_path)).map_err(|e| e.to_string()),
+                _ => {
+                    // Recordings carry the state they started from, so the game carries on
+                    self.tape = Tape::Recording(Movie::new(&self.vm));
+                    Ok(())
+                }
+            },
+            Command::PlayMovie => Movie::load(&movie_path(&rom_path))
+                .and_then(|movie| {
+                    self.vm = movie.start(&self.rom.bytes)?;
+                    self.rewind.clear();
+                    let inputs = movie.inputs().collect::<Vec<_>>().into_iter();
+                    self.tape = Tape::Playing { movie, inputs, frame: 0 };
+                    Ok(())
+                })
+                .map_err(|e| e.to_string()),
+            Command::TogglePause => {
+                self.paused = !self.paused;
+                Ok(())
+            }
+        };
+        self.report(&format!("{:?}", command), result);
+    }
+
+    fn report<E: fmt::Display>(&self, what: &str, result: Result<(), E>) {
+        if let Err(err) = result {
+            eprintln!("{}: {}: {}", self.rom.path.display(), what, err);
+        }
+    }
+}
+
+// Draws the screen if it changed, and plays the VM's sounds unless `muted`.
+pub fn present<F: Display + Audio>(frontend: &mut F, output: &OutputState, muted: bool) {
+    match output.mega {
+        Some(ref frame) => {
+            if output.vram_changed {
+                frontend.draw_mega(frame);
+            }
+            frontend.play_sound(frame.sound);
+        }
+        None => {
+            if output.vram_changed {
+                frontend.draw(output.vram, output.width, output.height);
+            }
+            frontend.play_sound(None);
+        }
+    }
+    frontend.play(output.beep && !muted, output.audio);
+}
+
+#[cfg(test)]
+#[path = "./emulator_test.rs"]
+mod emulator_test;
@@ -0,0 +1,44 @@
+use super::*;
+use std::path::Path;
+use super::super::mode::Mode;
+use super::super::quirks::Quirks;
+use super::super::test_support::{Clock, Frontend};
+
+
+fn emulator(rom: &[u8]) -> Emulator {
+    let rom = Rom::from_bytes(rom.to_vec(), Path::new("test.ch8")).unwrap();
+    Emulator::new(VM::with_mode(Mode::Chip8, Quirks::default()), rom)
+}
+
+#[test]
+fn test_run_until_quit() {
+    // LD V0, 1; ADD V1, V0; CLS; JP 0x202
+    let mut emulator = emulator(&[0x60, 0x01, 0x71, 0x01, 0x00, 0xE0, 0x12, 0x02]);
+    let mut frontend = Frontend::new(3);
+    emulator.run(&mut frontend, &mut Clock).unwrap();
+    assert_eq!(frontend.draws, 3);
+    assert_eq!(emulator.vm().snapshot().state, {
+        let mut vm = VM::new();
+        vm.load(&[0x60, 0x01, 0x71, 0x01, 0x00, 0xE0, 0x12, 0x02]);
+        for _ in 0..3 {
+            vm.run_frame([false; 16], 10).unwrap();
+        }
+        vm.snapshot().state
+    });
+}
+
+#[test]
+fn test_pause() {
+    let mut emulator = emulator(&[0x71, 0x01, 0x12, 0x00]);
+    let mut frontend = Frontend::new(5);
+    frontend.commands.push(Command::TogglePause);
+    let before = emulator.vm().snapshot();
+    emulator.run(&mut frontend, &mut Clock).unwrap();
+    assert_eq!(emulator.vm().snapshot(), before);
+}
+
+#[test]
+fn test_fault() {
+    let mut emulator = emulator(&[0x00, 0xEE]);
+    assert!(matches!(emulator.run(&mut Frontend::new(1), &mut Clock), Err(VmError::StackUnderflow { .. })));
+}
@@ -0,0 +1,55 @@
+use std::error;
+use std::fmt;
+
+// Faults raised while executing a ROM. Every variant carries the `pc` of the faulting instruction
+// and its `opcode`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum VmError {
+    // 2nnn with all 16 stack slots in use
+    StackOverflow { pc: usize, opcode: u16 },
+    // 00EE with an empty stack
+    StackUnderflow { pc: usize, opcode: u16 },
+    // The program counter left the addressable memory. `opcode` holds whatever could still be read.
+    PcOutOfBounds { pc: usize, opcode: u16 },
+    // A read or write through `I` touched `addr`, which is past the end of memory
+    MemoryOutOfBounds { pc: usize, opcode: u16, addr: usize },
+    // The opcode doesn't decode to any known instruction
+    UnknownOpcode { pc: usize, opcode: u16 },
+}
+
+impl VmError {
+    pub fn pc(&self) -> usize {
+        match *self {
+            VmError::StackOverflow { pc, .. } => pc,
+            VmError::StackUnderflow { pc, .. } => pc,
+            VmError::PcOutOfBounds { pc, .. } => pc,
+            VmError::MemoryOutOfBounds { pc, .. } => pc,
+            VmError::UnknownOpcode { pc, .. } => pc,
+        }
+    }
+
+    pub fn opcode(&self) -> u16 {
+        match *self {
+            VmError::StackOverflow { opcode, .. } => opcode,
+            VmError::StackUnderflow { opcode, .. } => opcode,
+            VmError::PcOutOfBounds { opcode, .. } => opcode,
+            VmError::MemoryOutOfBounds { opcode, .. } => opcode,
+            VmError::UnknownOpcode { opcode, .. } => opcode,
+        }
+    }
+}
+
+impl fmt::Display for VmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            VmError::StackOverflow { .. } => write!(f, "stack overflow")?,
+            VmError::StackUnderflow { .. } => write!(f, "stack underflow")?,
+            VmError::PcOutOfBounds { .. } => write!(f, "program counter out of bounds")?,
+            VmError::MemoryOutOfBounds { addr, .. } => write!(f, "memory access out of bounds at {:#05x}", addr)?,
+            VmError::UnknownOpcode { .. } => write!(f, "unknown opcode")?,
+        }
+        write!(f, " (pc {:#05x}, opcode {:#06x})", self.pc(), self.opcode())
+    }
+}
+
+impl error::Error for VmError {}
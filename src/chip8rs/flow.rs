@@ -0,0 +1,352 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::{self, Write};
+use std::ops::Range;
+use super::instruction::Instruction;
+use super::mega_instruction::MegaInstruction;
+use super::mode::Mode;
+use super::vm::PROGRAM_START;
+
+// Most entries followed in a jump table of `JP` instructions, one per value of V0 / 2.
+const JUMP_TABLE_ENTRIES: usize = 128;
+
+// An instruction of the ROM, from either instruction set.
+#[derive(PartialEq, Eq, Debug)]
+pub enum Op {
+    Chip(Instruction),
+    Mega(MegaInstruction),
+}
+
+impl Op {
+    pub fn size(&self) -> usize {
+        match self {
+            Op::Chip(instruction) => instruction.size(),
+            Op::Mega(instruction) => instruction.size(),
+        }
+    }
+}
+
+impl fmt::Display for Op {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Op::Chip(instruction) => instruction.fmt(f),
+            Op::Mega(instruction) => instruction.fmt(f),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum EdgeKind {
+    // To the next instruction, including the return from a call
+    Fallthrough,
+    Jump,
+    // To the instruction after the next, when a skip is taken
+    Skip,
+    Call,
+    // A guessed target of `JP V0, nnn`
+    Indirect,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub struct Edge {
+    pub to: usize,
+    pub kind: EdgeKind,
+}
+
+// Instructions from `start` to `end` (exclusive) which run one after the other, entered only at
+// `start` and left only after the last one.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Block {
+    pub start: usize,
+    pub end: usize,
+    pub successors: Vec<Edge>,
+}
+
+// The blocks reachable from a call target, or from 0x200, without following calls. Blocks shared
+// by several functions, such as common tails jumped to, belong to each of them.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Function {
+    pub entry: usize,
+    pub blocks: BTreeSet<usize>,
+    pub calls: BTreeSet<usize>,
+}
+
+// The control flow of a ROM loaded at 0x200, recovered statically by following every path from
+// 0x200. Skips fork to both the next instruction and the one after, and `JP V0, nnn` is followed
+// to the `nnn + V0` V0 was just loaded with, or else to every entry of a table of jumps at `nnn`.
+// Bytes never reached are data.
+pub struct ControlFlow {
+    rom_len: usize,
+    code: Vec<bool>,  // per ROM byte, whether it belongs to an instruction
+    pub instructions: BTreeMap<usize, Op>,
+    pub blocks: BTreeMap<usize, Block>,
+    pub functions: BTreeMap<usize, Function>,
+}
+
+pub fn analyze(rom: &[u8], mode: Mode) -> ControlFlow {
+    let mut tracer = Tracer {
+        rom,
+        mode,
+        code: vec![false; rom.len()],
+        instructions: BTreeMap::new(),
+        successors: BTreeMap::new(),
+    };
+    tracer.trace();
+    let blocks = blocks(&tracer.instructions, &tracer.successors);
+    let functions = functions(&blocks);
+    ControlFlow {
+        rom_len: rom.len(),
+        code: tracer.code,
+        instructions: tracer.instructions,
+        blocks,
+        functions,
+    }
+}
+
+impl ControlFlow {
+    pub fn is_code(&self, addr: usize) -> bool {
+        addr >= PROGRAM_START && self.code.get(addr - PROGRAM_START) == Some(&true)
+    }
+
+    // The runs of bytes which are not code, by address.
+    pub fn data(&self) -> Vec<Range<usize>> {
+        let mut ranges: Vec<Range<usize>> = Vec::new();
+        for addr in (PROGRAM_START..PROGRAM_START + self.rom_len).filter(|&addr| !self.is_code(addr)) {
+            match ranges.last_mut() {
+                Some(range) if range.end == addr => range.end += 1,
+                _ => ranges.push(addr..addr + 1),
+            }
+        }
+        ranges
+    }
+
+    // The graph in Graphviz's DOT language, with a cluster per function. A block shared by
+    // several functions is drawn in the first of them.
+    pub fn to_dot(&self) -> String {
+        let mut out = String::new();
+        out.push_str("digraph rom {\n    node [shape=box, fontname=\"monospace\"];\n");
+        let mut drawn = BTreeSet::new();
+        for function in self.functions.values() {
+            let name = if function.entry == PROGRAM_START { "main".to_string() } else { format!("sub_{:03X}", function.entry) };
+            writeln!(out, "    subgraph cluster_{} {{\n        label=\"{}\";", name, name).unwrap();
+            for start in &function.blocks {
+                if drawn.insert(*start) {
+                    writeln!(out, "        {}", self.dot_node(&self.blocks[start])).unwrap();
+                }
+            }
+            out.push_str("    }\n");
+        }
+        for block in self.blocks.values() {
+            for edge in &block.successors {
+                let style = match edge.kind {
+                    EdgeKind::Fallthrough | EdgeKind::Jump => "",
+                    EdgeKind::Skip => " [label=\"skip\"]",
+                    EdgeKind::Call => " [style=dashed]",
+                    EdgeKind::Indirect => " [style=dotted, label=\"V0\"]",
+                };
+                writeln!(out, "    b_{:03X} -> b_{:03X}{};", block.start, edge.to, style).unwrap();
+            }
+        }
+        out.push_str("}\n");
+        out
+    }
+
+    fn dot_node(&self, block: &Block) -> String {
+        let lines: String = self
+            .instructions
+            .range(block.start..block.end)
+            .map(|(addr, op)| format!("{:04X}  {}\\l", addr, op.to_string().replace('\\', "\\\\").replace('"', "\\\"")))
+            .collect();
+        format!("b_{:03X} [label=\"{}\"];", block.start, lines)
+    }
+}
+
+struct Tracer<'a> {
+    rom: &'a [u8],
+    mode: Mode,
+    code: Vec<bool>,
+    instructions: BTreeMap<usize, Op>,
+    successors: BTreeMap<usize, Vec<Edge>>,  // by instruction, to other instructions
+}
+
+impl<'a> Tracer<'a> {
+    fn contains(&self, addr: usize) -> bool {
+        addr >= PROGRAM_START && addr < PROGRAM_START + self.rom.len()
+    }
+
+    fn word(&self, addr: usize) -> Option<u16> {
+        let offset = addr.checked_sub(PROGRAM_START)?;
+        match (self.rom.get(offset), self.rom.get(offset + 1)) {
+            (Some(&hi), Some(&lo)) => Some((hi as u16) << 8 | lo as u16),
+            _ => None,
+        }
+    }
+
+    // Decodes the instruction at `addr`, if it is a valid one for the mode.
+    fn decode(&self, addr: usize) -> Option<Op> {
+        let opcode = self.word(addr)?;
+        if self.mode == Mode::MegaChip {
+            if let Some(instruction) = MegaInstruction::decode(opcode) {
+                if instruction.size() > 2 {
+                    return Some(Op::Mega(MegaInstruction::decode_long(opcode, self.word(addr + 2)?)?));
+                }
+                return Some(Op::Mega(instruction));
+            }
+        }
+        let mut instruction = Instruction::decode(opcode);
+        if matches!(instruction, Instruction::Unknown(_)) || !self.mode.supports(&instruction) {
+            return None;
+        }
+        if instruction.size() > 2 {
+            instruction = Instruction::decode_long(opcode, self.word(addr + 2)?);
+        }
+        Some(Op::Chip(instruction))
+    }
+
+    fn size_at(&self, addr: usize) -> usize {
+        self.decode(addr).map(|op| op.size()).unwrap_or(2)
+    }
+
+    // Where the instruction at `addr` may go next.
+    fn edges(&self, addr: usize, op: &Op) -> Vec<Edge> {
+        let next = addr + op.size();
+        let edge = |to: usize, kind: EdgeKind| Edge { to, kind };
+        match *op {
+            Op::Chip(Instruction::Jump(target)) => vec![edge(target as usize, EdgeKind::Jump)],
+            Op::Chip(Instruction::LongJump(base)) => self
+                .jump_targets(addr, base as usize)
+                .into_iter()
+                .map(|to| edge(to, EdgeKind::Indirect))
+                .collect(),
+            Op::Chip(Instruction::Call(target)) => vec![edge(target as usize, EdgeKind::Call), edge(next, EdgeKind::Fallthrough)],
+            Op::Chip(Instruction::Return) | Op::Chip(Instruction::Exit) => Vec::new(),
+            Op::Chip(Instruction::SkipEqualK(..))
+            | Op::Chip(Instruction::SkipNotEqualK(..))
+            | Op::Chip(Instruction::SkipEqual(..))
+            | Op::Chip(Instruction::SkipNotEqual(..))
+            | Op::Chip(Instruction::SkipPressed(_))
+            | Op::Chip(Instruction::SkipNotPressed(_)) => {
+                vec![edge(next, EdgeKind::Fallthrough), edge(next + self.size_at(next), EdgeKind::Skip)]
+            }
+            _ => vec![edge(next, EdgeKind::Fallthrough)],
+        }
+    }
+
+    // The guessed targets of `JP V0, base` at `addr`: exact when the instruction before it loads
+    // V0 with a constant, otherwise the run of `JP` instructions at `base`, which is how jump
+    // tables are usually written.
+    fn jump_targets(&self, addr: usize, base: usize) -> Vec<usize> {
+        if let Some(Op::Chip(Instruction::LoadK(0, k))) = addr.checked_sub(2).and_then(|prev| self.decode(prev)) {
+            return vec![base + k as usize];
+        }
+        let mut targets = vec![base];
+        targets.extend(
+            (1..JUMP_TABLE_ENTRIES)
+                .map(|n| base + n * 2)
+                .take_while(|&entry| matches!(self.decode(entry), Some(Op::Chip(Instruction::Jump(_))))),
+        );
+        targets
+    }
+
+    // Follows every path from 0x200, recording the instructions found and where each goes.
+    fn trace(&mut self) {
+        let mut pending = vec![PROGRAM_START];
+        while let Some(addr) = pending.pop() {
+            if !self.contains(addr) || self.code[addr - PROGRAM_START] {
+                continue;
+            }
+            let op = match self.decode(addr) {
+                Some(op) => op,
+                None => continue,
+            };
+            let next = addr + op.size();
+            let offsets = addr - PROGRAM_START..(next - PROGRAM_START).min(self.rom.len());
+            if self.code[offsets.clone()].iter().any(|&c| c) {
+                continue;
+            }
+            self.code[offsets].iter_mut().for_each(|c| *c = true);
+
+            let edges = self.edges(addr, &op);
+            // Visit the fallthrough first, so the listing is traced in order where it can be
+            pending.extend(edges.iter().rev().map(|edge| edge.to));
+            self.successors.insert(addr, edges);
+            self.instructions.insert(addr, op);
+        }
+        // Paths into data or the middle of another instruction lead nowhere
+        let instructions = &self.instructions;
+        for edges in self.successors.values_mut() {
+            edges.retain(|edge| instructions.contains_key(&edge.to));
+        }
+    }
+}
+
+// Splits the instructions into blocks, which start where an edge other than a fallthrough leads
+// and end after any instruction that doesn't simply go on to the next.
+fn blocks(instructions: &BTreeMap<usize, Op>, successors: &BTreeMap<usize, Vec<Edge>>) -> BTreeMap<usize, Block> {
+    let leaders: BTreeSet<usize> = successors
+        .values()
+        .flatten()
+        .filter(|edge| edge.kind != EdgeKind::Fallthrough)
+        .map(|edge| edge.to)
+        .chain(std::iter::once(PROGRAM_START))
+        .collect();
+    let mut blocks = BTreeMap::new();
+    let mut current: Option<Block> = None;
+    for (&addr, op) in instructions {
+        let next = addr + op.size();
+        let mut block = match current.take() {
+            Some(block) if block.end == addr && !leaders.contains(&addr) => block,
+            Some(block) => {
+                blocks.insert(block.start, block);
+                Block { start: addr, end: addr, successors: Vec::new() }
+            }
+            None => Block { start: addr, end: addr, successors: Vec::new() },
+        };
+        block.end = next;
+        block.successors = successors[&addr].clone();
+        let straight = matches!(block.successors[..], [Edge { to, kind: EdgeKind::Fallthrough }] if to == next);
+        if straight {
+            current = Some(block);
+        } else {
+            blocks.insert(block.start, block);
+        }
+    }
+    if let Some(block) = current {
+        blocks.insert(block.start, block);
+    }
+    blocks
+}
+
+fn functions(blocks: &BTreeMap<usize, Block>) -> BTreeMap<usize, Function> {
+    let entries: BTreeSet<usize> = blocks
+        .values()
+        .flat_map(|block| &block.successors)
+        .filter(|edge| edge.kind == EdgeKind::Call)
+        .map(|edge| edge.to)
+        .chain(blocks.keys().next().filter(|&&start| start == PROGRAM_START).cloned())
+        .collect();
+    entries
+        .into_iter()
+        .map(|entry| {
+            let mut function = Function { entry, blocks: BTreeSet::new(), calls: BTreeSet::new() };
+            let mut pending = vec![entry];
+            while let Some(start) = pending.pop() {
+                if !function.blocks.insert(start) {
+                    continue;
+                }
+                for edge in &blocks[&start].successors {
+                    match edge.kind {
+                        EdgeKind::Call => {
+                            function.calls.insert(edge.to);
+                        }
+                        _ => pending.push(edge.to),
+                    }
+                }
+            }
+            (entry, function)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+#[path = "./flow_test.rs"]
+mod flow_test;
@@ -0,0 +1,72 @@
+use super::*;
+
+
+fn edge(to: usize, kind: EdgeKind) -> Edge {
+    Edge { to, kind }
+}
+
+const ROM: &[u8] = &[
+    0x00, 0xE0,  // 200: CLS
+    0x22, 0x0A,  // 202: CALL 0x20A
+    0x30, 0x00,  // 204: SE V0, 0
+    0x12, 0x02,  // 206: JP 0x202
+    0x12, 0x08,  // 208: JP 0x208
+    0x60, 0x01,  // 20A: LD V0, 1
+    0x00, 0xEE,  // 20C: RET
+    0xFF,        // 20E: data
+];
+
+#[test]
+fn test_blocks() {
+    let flow = analyze(ROM, Mode::Chip8);
+    let blocks: Vec<(usize, usize, Vec<Edge>)> = flow.blocks.values().map(|b| (b.start, b.end, b.successors.clone())).collect();
+    assert_eq!(blocks, vec![
+        (0x200, 0x202, vec![edge(0x202, EdgeKind::Fallthrough)]),
+        (0x202, 0x204, vec![edge(0x20A, EdgeKind::Call), edge(0x204, EdgeKind::Fallthrough)]),
+        (0x204, 0x206, vec![edge(0x206, EdgeKind::Fallthrough), edge(0x208, EdgeKind::Skip)]),
+        (0x206, 0x208, vec![edge(0x202, EdgeKind::Jump)]),
+        (0x208, 0x20A, vec![edge(0x208, EdgeKind::Jump)]),
+        (0x20A, 0x20E, vec![]),
+    ]);
+    assert_eq!(flow.data(), vec![0x20E..0x20F]);
+    assert!(flow.is_code(0x20D));
+    assert!(!flow.is_code(0x20E));
+}
+
+#[test]
+fn test_functions() {
+    let flow = analyze(ROM, Mode::Chip8);
+    let main = &flow.functions[&0x200];
+    assert_eq!(main.blocks, [0x200, 0x202, 0x204, 0x206, 0x208].iter().cloned().collect());
+    assert_eq!(main.calls, [0x20A].iter().cloned().collect());
+    assert_eq!(flow.functions[&0x20A].blocks, [0x20A].iter().cloned().collect());
+    assert_eq!(flow.functions.len(), 2);
+}
+
+#[test]
+fn test_jump_tables() {
+    // RND V0, 2; JP V0, 0x206; then a table of two jumps, ended by data
+    let table = [0xC0, 0x02, 0xB2, 0x06, 0xFF, 0xFF, 0x12, 0x0C, 0x12, 0x0E, 0xFF, 0xFF, 0x12, 0x0C, 0x12, 0x0E];
+    let flow = analyze(&table, Mode::Chip8);
+    assert_eq!(flow.blocks[&0x200].successors, vec![edge(0x206, EdgeKind::Indirect), edge(0x208, EdgeKind::Indirect)]);
+    assert!(flow.is_code(0x20C) && flow.is_code(0x20E));
+    assert_eq!(flow.data(), vec![0x204..0x206, 0x20A..0x20C]);
+
+    // LD V0, 2 before the jump picks the entry
+    let mut exact = table;
+    exact[0] = 0x60;
+    let flow = analyze(&exact, Mode::Chip8);
+    assert_eq!(flow.blocks[&0x200].successors, vec![edge(0x208, EdgeKind::Indirect)]);
+    assert!(!flow.is_code(0x206) && !flow.is_code(0x20C));
+}
+
+#[test]
+fn test_dot() {
+    let dot = analyze(ROM, Mode::Chip8).to_dot();
+    assert!(dot.starts_with("digraph rom {\n"), "{}", dot);
+    assert!(dot.contains("    subgraph cluster_sub_20A {\n        label=\"sub_20A\";\n        b_20A [label=\"020A  LD V0, 0x01\\l020C  RET\\l\"];\n    }\n"), "{}", dot);
+    assert!(dot.contains("    b_202 -> b_20A [style=dashed];\n"), "{}", dot);
+    assert!(dot.contains("    b_204 -> b_208 [label=\"skip\"];\n"), "{}", dot);
+    assert!(dot.contains("    b_206 -> b_202;\n"), "{}", dot);
+    assert!(dot.ends_with("}\n"));
+}
@@ -79,4 +79,168 @@ pub const FONT_SET: [u8; 80] = [
     0xF0,
     0x80,
     0x80,
-];
\ No newline at end of file
+];
+
+// SUPER-CHIP 8x10 glyphs for the hexadecimal digits, loaded after FONT_SET.
+pub const BIG_FONT_SET: [u8; 160] = [
+    0xFF,
+    0xFF,
+    0xC3,
+    0xC3,
+    0xC3,
+    0xC3,
+    0xC3,
+    0xC3,
+    0xFF,
+    0xFF,
+    0x18,
+    0x78,
+    0x78,
+    0x18,
+    0x18,
+    0x18,
+    0x18,
+    0x18,
+    0xFF,
+    0xFF,
+    0xFF,
+    0xFF,
+    0x03,
+    0x03,
+    0xFF,
+    0xFF,
+    0xC0,
+    0xC0,
+    0xFF,
+    0xFF,
+    0xFF,
+    0xFF,
+    0x03,
+    0x03,
+    0xFF,
+    0xFF,
+    0x03,
+    0x03,
+    0xFF,
+    0xFF,
+    0xC3,
+    0xC3,
+    0xC3,
+    0xC3,
+    0xFF,
+    0xFF,
+    0x03,
+    0x03,
+    0x03,
+    0x03,
+    0xFF,
+    0xFF,
+    0xC0,
+    0xC0,
+    0xFF,
+    0xFF,
+    0x03,
+    0x03,
+    0xFF,
+    0xFF,
+    0xFF,
+    0xFF,
+    0xC0,
+    0xC0,
+    0xFF,
+    0xFF,
+    0xC3,
+    0xC3,
+    0xFF,
+    0xFF,
+    0xFF,
+    0xFF,
+    0x03,
+    0x03,
+    0x06,
+    0x0C,
+    0x18,
+    0x18,
+    0x18,
+    0x18,
+    0xFF,
+    0xFF,
+    0xC3,
+    0xC3,
+    0xFF,
+    0xFF,
+    0xC3,
+    0xC3,
+    0xFF,
+    0xFF,
+    0xFF,
+    0xFF,
+    0xC3,
+    0xC3,
+    0xFF,
+    0xFF,
+    0x03,
+    0x03,
+    0xFF,
+    0xFF,
+    0x7E,
+    0xFF,
+    0xC3,
+    0xC3,
+    0xC3,
+    0xFF,
+    0xFF,
+    0xC3,
+    0xC3,
+    0xC3,
+    0xFC,
+    0xFC,
+    0xC3,
+    0xC3,
+    0xFC,
+    0xFC,
+    0xC3,
+    0xC3,
+    0xFC,
+    0xFC,
+    0x3C,
+    0xFF,
+    0xC3,
+    0xC0,
+    0xC0,
+    0xC0,
+    0xC0,
+    0xC3,
+    0xFF,
+    0x3C,
+    0xFC,
+    0xFE,
+    0xC3,
+    0xC3,
+    0xC3,
+    0xC3,
+    0xC3,
+    0xC3,
+    0xFE,
+    0xFC,
+    0xFF,
+    0xFF,
+    0xC0,
+    0xC0,
+    0xFF,
+    0xFF,
+    0xC0,
+    0xC0,
+    0xFF,
+    0xFF,
+    0xFF,
+    0xFF,
+    0xC0,
+    0xC0,
+    0xFF,
+    0xFF,
+    0xC0,
+    0xC0,
+    0xC0,
+    0xC0,
+];
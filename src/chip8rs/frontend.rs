@@ -0,0 +1,55 @@
+use super::audio::AudioPattern;
+use super::megachip::{DigitalSound, MegaFrame};
+use super::vm::{SCHIP_HEIGHT, SCHIP_WIDTH};
+
+// The pieces `Emulator` drives. `UI` implements them with SDL; other frontends only need to
+// implement these too.
+
+pub trait Display {
+    // Draws the top-left `width`x`height` pixels of `pixels`. Each pixel is a mask of the bitplanes
+    // it is lit on.
+    fn draw(&mut self, pixels: &[[u8; SCHIP_WIDTH]; SCHIP_HEIGHT], width: usize, height: usize);
+
+    fn draw_mega(&mut self, frame: &MegaFrame);
+}
+
+pub trait Input {
+    // Called once per frame, returns the keypad state or `None` once the user wants to quit.
+    fn poll(&mut self) -> Option<[bool; 16]>;
+
+    // Commands requested since the last call.
+    fn commands(&mut self) -> Vec<Command> {
+        Vec::new()
+    }
+
+    // Whether the rewind key is held, as of the last `poll`.
+    fn rewinding(&self) -> bool {
+        false
+    }
+}
+
+pub trait Audio {
+    // Plays a tone, or the XO-CHIP audio pattern if there is one, while `beep` is set.
+    fn play(&mut self, beep: bool, pattern: Option<AudioPattern>);
+
+    // Plays a MegaChip digitised sound, restarting it only when the VM started a new one.
+    fn play_sound(&mut self, sound: Option<&DigitalSound>);
+}
+
+pub trait Clock {
+    // Number of instructions to run in the current frame.
+    fn instructions_this_frame(&self) -> u32;
+
+    // Waits until the next frame is due, then moves on to it.
+    fn wait_next_frame(&mut self);
+}
+
+// Emulator actions requested by the user, outside of the CHIP-8 keypad.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Command {
+    SaveState(u8),
+    LoadState(u8),
+    ToggleRecording,
+    PlayMovie,
+    TogglePause,
+}
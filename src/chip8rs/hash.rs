@@ -0,0 +1,31 @@
+// Small hashes for telling games apart and checking saved files.
+//
+// `Rom` also has a SHA-1, the usual key of ROM databases. Save states and movies keep identifying
+// their game by the FNV-1a of `VM::rom_hash` instead: the VM computes it from whatever it loads,
+// including programs assembled by the debug adapter that never were a `Rom`, it fits the existing
+// 8 byte header field, and it only has to catch loading a state into the wrong game.
+
+const FNV_OFFSET_BASIS: u64 = 0xcbf29ce484222325;
+const FNV_PRIME: u64 = 0x100000001b3;
+const CRC32_POLYNOMIAL: u32 = 0xedb88320;
+
+// 64 bit FNV-1a.
+pub fn fnv1a(data: &[u8]) -> u64 {
+    data.iter().fold(FNV_OFFSET_BASIS, |h, &b| (h ^ b as u64).wrapping_mul(FNV_PRIME))
+}
+
+// CRC-32 as used by zlib and PNG.
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ CRC32_POLYNOMIAL } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+#[cfg(test)]
+#[path = "./hash_test.rs"]
+mod hash_test;
@@ -1,14 +1,28 @@
+use std::fmt;
+
 pub type Register = u8;
 pub type Addr = u16;
 pub type Byte = u8;
 
 #[allow(dead_code)]
-#[derive(PartialEq, Eq, Debug)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
 pub enum Instruction {
     // 00E0 - CLS, clear the screen
     Clear,
     // 00EE - RET
     Return,
+    // 00Cn - SCD nibble: Scrolls the display down by `Nibble` pixels (SUPER-CHIP)
+    ScrollDown(Byte),
+    // 00FB - SCR: Scrolls the display right by 4 pixels (SUPER-CHIP)
+    ScrollRight,
+    // 00FC - SCL: Scrolls the display left by 4 pixels (SUPER-CHIP)
+    ScrollLeft,
+    // 00FD - EXIT: Stops the interpreter (SUPER-CHIP)
+    Exit,
+    // 00FE - LOW: Switches to the 64x32 low resolution mode (SUPER-CHIP)
+    LowRes,
+    // 00FF - HIGH: Switches to the 128x64 high resolution mode (SUPER-CHIP)
+    HighRes,
     // 0nnn - SYS addr
     Sys(Addr),
     // 1nnn - JP addr
@@ -21,6 +35,10 @@ pub enum Instruction {
     SkipNotEqualK(Register, Byte),
     // 5xy0 - SE Vx, Vy: Skips the next instruction if `Vx` and `Vy` are equal
     SkipEqual(Register, Register),
+    // 5xy2 - SAVE Vx - Vy: Stores the registers `Vx` to `Vy` inclusive at address `I`, in either direction. `I` is left unchanged (XO-CHIP)
+    StoreRange(Register, Register),
+    // 5xy3 - LOAD Vx - Vy: Reads the registers `Vx` to `Vy` inclusive from address `I`, in either direction. `I` is left unchanged (XO-CHIP)
+    LoadRange(Register, Register),
     // 6xkk - LD Vx, byte: Sets `Vy` to `Byte`
     LoadK(Register, Byte),
     // 7xkk - LD Vx, byte: Sets `Vy` to `Byte`
@@ -38,11 +56,11 @@ pub enum Instruction {
     // 8xy5 - SUB Vx, Vy: Subtracts `Vy` from `Vx`, then stores the result in `Vx`, `VF` is set to `1` if `Vx` is larger than `Vy` prior subtraction, `0` otherwise.
     Sub(Register, Register),
     // 8xy6 - SHR Vx {, Vy}: Shifts `Vy` right by one bit, then stores the result in `Vx`. Stores the least-significant bit prior shift of `Vy` in `VF`.
-    ShiftRight(Register),
+    ShiftRight(Register, Register),
     // 8xy7 - SUBN Vx, Vy: Subtracts `Vx` from `Vy`, then stores the result in `Vx`. `VF` is set to `1` if `Vx` is larger than `Vy` prior subtraction, `0` otherwise. Note that this is the same as `Sub` with inverted register operands.
     SubInv(Register, Register),
     // 8xyE - SHL Vx {, Vy}: Shifts `Vy` left by one bit, then stores the result in `Vx`. Stores the most-significant bit prior shift of `Vy` in `VF`.
-    ShiftLeft(Register),
+    ShiftLeft(Register, Register),
     // 9xy0 - SNE Vx, Vy: Skips the next instruction if `Vx` and `Vy` are not equal
     SkipNotEqual(Register, Register),
     // Annn - LD I, addr: Sets the `I` register to `Addr`
@@ -51,15 +69,21 @@ pub enum Instruction {
     LongJump(Addr),
     // Cxkk - RND Vx, byte: Sets `Vx` to a random byte ANDed with `Byte`
     Rand(Register, Byte),
-    // Dxyn - DRW Vx, Vy, nibble: Draws the sprite with `Nibble` bytes of data from the `I` register at position `(Vx, Vy)`. Sets `VF` to `1` if any pixels are set to unlit state, `0` otherwise. Note that sprites wrap around onto the opposite side of the screen.
+    // Dxyn - DRW Vx, Vy, nibble: Draws the sprite with `Nibble` bytes of data from the `I` register at position `(Vx, Vy)`. Sets `VF` to `1` if any pixels are set to unlit state, `0` otherwise. Note that sprites wrap around onto the opposite side of the screen. On SUPER-CHIP, `Dxy0` draws a 16x16 sprite of 32 bytes.
     Draw(Register, Register, Byte),
     // Ex9E - SKP Vx: Skips the next instruction if key `Vx` is pressed
     SkipPressed(Register),
     // ExA1 - SKNP Vx: Skips the next instruction if key `Vx` is not pressed
     SkipNotPressed(Register),
+    // F000 nnnn - LD I, long addr: Sets the `I` register to the 16 bit address in the word following the opcode (XO-CHIP). `decode` only sees the opcode and leaves the address at `0`, use `decode_long` to fill it in.
+    LoadLongI(Addr),
+    // Fn01 - PLANE n: Selects the bitplanes mask `n` used by drawing, clearing and scrolling (XO-CHIP)
+    Plane(Byte),
+    // F002 - AUDIO: Loads the 16 byte audio pattern buffer from address `I` (XO-CHIP)
+    LoadAudio,
     // Fx07 - LD Vx, DT: Stores the value of the `delay timer` in `Vx`
     GetTimer(Register),
-    // Fx0A - LD Vx, K: Stops execution until a key is pressed, then stores that key in `Vx`
+    // Fx0A - LD Vx, K: Stops execution until a key is pressed and released, then stores that key in `Vx`
     WaitKey(Register),
     // Fx15 - LD DT, Vx: Sets the `delay timer` to `Vx`
     SetTimer(Register),
@@ -69,26 +93,34 @@ pub enum Instruction {
     AddI(Register),
     // Fx29 - LD F, Vx: Stores the address of the hexadecimal digit `Vx` in the `I` register
     LoadHexGlyph(Register),
+    // Fx30 - LD HF, Vx: Stores the address of the large hexadecimal digit `Vx` in the `I` register (SUPER-CHIP)
+    LoadBigHexGlyph(Register),
+    // Fx3A - PITCH Vx: Sets the audio pattern playback rate to `4000 * 2 ^ ((Vx - 64) / 48)` bits per second (XO-CHIP)
+    Pitch(Register),
     // Fx33 - LD B, Vx: Stores the binary-coded decimal representation of `Vx` at address `I`, `I + 1` and `I + 2`
     StoreBCD(Register),
     // Fx55 - LD [I], Vx: Stores the registers `V0` to `Vx` inclusive at address `I`. Register `I` is set to `I + Vx + 1` afterwards.
     StoreRegisters(Register),
     // Fx65 - LD Vx, [I]: Reads the registers `V0` to `Vx` inclusive from address `I`. Register `I` is set to `I + Vx + 1` afterwards.
     LoadRegisters(Register),
-    // Placeholder for an unknown or illegal instruction.
-    Unknown,
+    // Fx75 - LD R, Vx: Stores the registers `V0` to `Vx` inclusive in the RPL user flags (SUPER-CHIP)
+    StoreFlags(Register),
+    // Fx85 - LD Vx, R: Reads the registers `V0` to `Vx` inclusive from the RPL user flags (SUPER-CHIP)
+    LoadFlags(Register),
+    // An unknown or illegal instruction, with its opcode so that it encodes back unchanged.
+    Unknown(u16),
 }
 
 impl Instruction {
     pub fn decode(opcode: u16) -> Instruction {
         let nibbles = (
-            (opcode & 0xF000) >> 12 as u8,
-            (opcode & 0x0F00) >> 8 as u8,
-            (opcode & 0x00F0) >> 4 as u8,
-            (opcode & 0x000F) as u8,
+            (opcode & 0xF000) >> 12,
+            (opcode & 0x0F00) >> 8,
+            (opcode & 0x00F0) >> 4,
+            opcode & 0x000F,
         );
 
-        let nnn = (opcode & 0x0FFF) as usize;
+        let nnn = opcode & 0x0FFF;
         let kk = (opcode & 0x00FF) as Byte;
         let x = nibbles.1 as Register;
         let y = nibbles.2 as Register;
@@ -97,11 +129,19 @@ impl Instruction {
         match nibbles {
             (0x00, 0x00, 0x0e, 0x00) => Instruction::Clear,
             (0x00, 0x00, 0x0e, 0x0e) => Instruction::Return,
-            (0x01, _, _, _) => Instruction::Jump(nnn as Addr),
-            (0x02, _, _, _) => Instruction::Call(nnn as Addr),
+            (0x00, 0x00, 0x0c, _) => Instruction::ScrollDown(n),
+            (0x00, 0x00, 0x0f, 0x0b) => Instruction::ScrollRight,
+            (0x00, 0x00, 0x0f, 0x0c) => Instruction::ScrollLeft,
+            (0x00, 0x00, 0x0f, 0x0d) => Instruction::Exit,
+            (0x00, 0x00, 0x0f, 0x0e) => Instruction::LowRes,
+            (0x00, 0x00, 0x0f, 0x0f) => Instruction::HighRes,
+            (0x01, _, _, _) => Instruction::Jump(nnn),
+            (0x02, _, _, _) => Instruction::Call(nnn),
             (0x03, _, _, _) => Instruction::SkipEqualK(x, kk),
             (0x04, _, _, _) => Instruction::SkipNotEqualK(x, kk),
             (0x05, _, _, 0x00) => Instruction::SkipEqual(x, y),
+            (0x05, _, _, 0x02) => Instruction::StoreRange(x, y),
+            (0x05, _, _, 0x03) => Instruction::LoadRange(x, y),
             (0x06, _, _, _) => Instruction::LoadK(x, kk),
             (0x07, _, _, _) => Instruction::AddK(x, kk),
             (0x08, _, _, 0x00) => Instruction::Set(x, y),
@@ -110,26 +150,172 @@ impl Instruction {
             (0x08, _, _, 0x03) => Instruction::Xor(x, y),
             (0x08, _, _, 0x04) => Instruction::Add(x, y),
             (0x08, _, _, 0x05) => Instruction::Sub(x, y),
-            (0x08, _, _, 0x06) => Instruction::ShiftRight(x),
+            (0x08, _, _, 0x06) => Instruction::ShiftRight(x, y),
             (0x08, _, _, 0x07) => Instruction::SubInv(x, y),
-            (0x08, _, _, 0x0e) => Instruction::ShiftLeft(x),
+            (0x08, _, _, 0x0e) => Instruction::ShiftLeft(x, y),
             (0x09, _, _, 0x00) => Instruction::SkipNotEqual(x, y),
-            (0x0a, _, _, _) => Instruction::LoadI(nnn as Addr),
-            (0x0b, _, _, _) => Instruction::LongJump(nnn as Addr),
+            (0x0a, _, _, _) => Instruction::LoadI(nnn),
+            (0x0b, _, _, _) => Instruction::LongJump(nnn),
             (0x0c, _, _, _) => Instruction::Rand(x, kk),
             (0x0d, _, _, _) => Instruction::Draw(x, y, n),
             (0x0e, _, 0x09, 0x0e) => Instruction::SkipPressed(x),
             (0x0e, _, 0x0a, 0x01) => Instruction::SkipNotPressed(x),
+            (0x0f, 0x00, 0x00, 0x00) => Instruction::LoadLongI(0),
+            (0x0f, _, 0x00, 0x01) => Instruction::Plane(x),
+            (0x0f, 0x00, 0x00, 0x02) => Instruction::LoadAudio,
             (0x0f, _, 0x00, 0x07) => Instruction::GetTimer(x),
             (0x0f, _, 0x00, 0x0A) => Instruction::WaitKey(x),
             (0x0f, _, 0x01, 0x05) => Instruction::SetTimer(x),
             (0x0f, _, 0x01, 0x08) => Instruction::SetSoundTimer(x),
             (0x0f, _, 0x01, 0x0e) => Instruction::AddI(x),
             (0x0f, _, 0x02, 0x09) => Instruction::LoadHexGlyph(x),
+            (0x0f, _, 0x03, 0x00) => Instruction::LoadBigHexGlyph(x),
             (0x0f, _, 0x03, 0x03) => Instruction::StoreBCD(x),
+            (0x0f, _, 0x03, 0x0a) => Instruction::Pitch(x),
             (0x0f, _, 0x05, 0x05) => Instruction::StoreRegisters(x),
             (0x0f, _, 0x06, 0x05) => Instruction::LoadRegisters(x),
-            _ => Instruction::Unknown,
+            (0x0f, _, 0x07, 0x05) => Instruction::StoreFlags(x),
+            (0x0f, _, 0x08, 0x05) => Instruction::LoadFlags(x),
+            _ => Instruction::Unknown(opcode),
+        }
+    }
+
+    // Decodes the opcode word `opcode` followed by the word `next`, which is the operand of the
+    // 4 byte instructions.
+    pub fn decode_long(opcode: u16, next: u16) -> Instruction {
+        match Instruction::decode(opcode) {
+            Instruction::LoadLongI(_) => Instruction::LoadLongI(next),
+            instruction => instruction,
+        }
+    }
+
+    // The opcode word of the instruction, the exact inverse of `decode`. The operand word of 4 byte
+    // instructions isn't included.
+    pub fn encode(&self) -> u16 {
+        let xy = |op: u16, x: Register, y: Register, n: u16| op << 12 | (x as u16 & 0xF) << 8 | (y as u16 & 0xF) << 4 | n;
+        let xkk = |op: u16, x: Register, k: Byte| op << 12 | (x as u16 & 0xF) << 8 | k as u16;
+        let fx = |x: Register, low: u16| 0xF000 | (x as u16 & 0xF) << 8 | low;
+        match *self {
+            Instruction::Clear => 0x00E0,
+            Instruction::Return => 0x00EE,
+            Instruction::ScrollDown(n) => 0x00C0 | (n as u16 & 0xF),
+            Instruction::ScrollRight => 0x00FB,
+            Instruction::ScrollLeft => 0x00FC,
+            Instruction::Exit => 0x00FD,
+            Instruction::LowRes => 0x00FE,
+            Instruction::HighRes => 0x00FF,
+            Instruction::Sys(addr) => addr & 0x0FFF,
+            Instruction::Jump(addr) => 0x1000 | (addr & 0x0FFF),
+            Instruction::Call(addr) => 0x2000 | (addr & 0x0FFF),
+            Instruction::SkipEqualK(x, k) => xkk(0x3, x, k),
+            Instruction::SkipNotEqualK(x, k) => xkk(0x4, x, k),
+            Instruction::SkipEqual(x, y) => xy(0x5, x, y, 0x0),
+            Instruction::StoreRange(x, y) => xy(0x5, x, y, 0x2),
+            Instruction::LoadRange(x, y) => xy(0x5, x, y, 0x3),
+            Instruction::LoadK(x, k) => xkk(0x6, x, k),
+            Instruction::AddK(x, k) => xkk(0x7, x, k),
+            Instruction::Set(x, y) => xy(0x8, x, y, 0x0),
+            Instruction::Or(x, y) => xy(0x8, x, y, 0x1),
+            Instruction::And(x, y) => xy(0x8, x, y, 0x2),
+            Instruction::Xor(x, y) => xy(0x8, x, y, 0x3),
+            Instruction::Add(x, y) => xy(0x8, x, y, 0x4),
+            Instruction::Sub(x, y) => xy(0x8, x, y, 0x5),
+            Instruction::ShiftRight(x, y) => xy(0x8, x, y, 0x6),
+            Instruction::SubInv(x, y) => xy(0x8, x, y, 0x7),
+            Instruction::ShiftLeft(x, y) => xy(0x8, x, y, 0xE),
+            Instruction::SkipNotEqual(x, y) => xy(0x9, x, y, 0x0),
+            Instruction::LoadI(addr) => 0xA000 | (addr & 0x0FFF),
+            Instruction::LongJump(addr) => 0xB000 | (addr & 0x0FFF),
+            Instruction::Rand(x, k) => xkk(0xC, x, k),
+            Instruction::Draw(x, y, n) => xy(0xD, x, y, n as u16 & 0xF),
+            Instruction::SkipPressed(x) => xkk(0xE, x, 0x9E),
+            Instruction::SkipNotPressed(x) => xkk(0xE, x, 0xA1),
+            Instruction::LoadLongI(_) => 0xF000,
+            Instruction::Plane(n) => fx(n, 0x01),
+            Instruction::LoadAudio => 0xF002,
+            Instruction::GetTimer(x) => fx(x, 0x07),
+            Instruction::WaitKey(x) => fx(x, 0x0A),
+            Instruction::SetTimer(x) => fx(x, 0x15),
+            Instruction::SetSoundTimer(x) => fx(x, 0x18),
+            Instruction::AddI(x) => fx(x, 0x1E),
+            Instruction::LoadHexGlyph(x) => fx(x, 0x29),
+            Instruction::LoadBigHexGlyph(x) => fx(x, 0x30),
+            Instruction::StoreBCD(x) => fx(x, 0x33),
+            Instruction::Pitch(x) => fx(x, 0x3A),
+            Instruction::StoreRegisters(x) => fx(x, 0x55),
+            Instruction::LoadRegisters(x) => fx(x, 0x65),
+            Instruction::StoreFlags(x) => fx(x, 0x75),
+            Instruction::LoadFlags(x) => fx(x, 0x85),
+            Instruction::Unknown(opcode) => opcode,
+        }
+    }
+
+    // Size of the instruction in bytes, including its operand words.
+    pub fn size(&self) -> usize {
+        match self {
+            Instruction::LoadLongI(_) => 4,
+            _ => 2,
+        }
+    }
+}
+
+// Cowgod's mnemonics, e.g. "LD V3, 0x1F" and "DRW V0, V1, 5", with the SUPER-CHIP and XO-CHIP
+// extensions named after their documentation. Bytes and addresses are in hex, nibbles in decimal.
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Instruction::Clear => write!(f, "CLS"),
+            Instruction::Return => write!(f, "RET"),
+            Instruction::ScrollDown(n) => write!(f, "SCD {}", n),
+            Instruction::ScrollRight => write!(f, "SCR"),
+            Instruction::ScrollLeft => write!(f, "SCL"),
+            Instruction::Exit => write!(f, "EXIT"),
+            Instruction::LowRes => write!(f, "LOW"),
+            Instruction::HighRes => write!(f, "HIGH"),
+            Instruction::Sys(addr) => write!(f, "SYS 0x{:03X}", addr),
+            Instruction::Jump(addr) => write!(f, "JP 0x{:03X}", addr),
+            Instruction::Call(addr) => write!(f, "CALL 0x{:03X}", addr),
+            Instruction::SkipEqualK(x, k) => write!(f, "SE V{:X}, 0x{:02X}", x, k),
+            Instruction::SkipNotEqualK(x, k) => write!(f, "SNE V{:X}, 0x{:02X}", x, k),
+            Instruction::SkipEqual(x, y) => write!(f, "SE V{:X}, V{:X}", x, y),
+            Instruction::StoreRange(x, y) => write!(f, "SAVE V{:X} - V{:X}", x, y),
+            Instruction::LoadRange(x, y) => write!(f, "LOAD V{:X} - V{:X}", x, y),
+            Instruction::LoadK(x, k) => write!(f, "LD V{:X}, 0x{:02X}", x, k),
+            Instruction::AddK(x, k) => write!(f, "ADD V{:X}, 0x{:02X}", x, k),
+            Instruction::Set(x, y) => write!(f, "LD V{:X}, V{:X}", x, y),
+            Instruction::Or(x, y) => write!(f, "OR V{:X}, V{:X}", x, y),
+            Instruction::And(x, y) => write!(f, "AND V{:X}, V{:X}", x, y),
+            Instruction::Xor(x, y) => write!(f, "XOR V{:X}, V{:X}", x, y),
+            Instruction::Add(x, y) => write!(f, "ADD V{:X}, V{:X}", x, y),
+            Instruction::Sub(x, y) => write!(f, "SUB V{:X}, V{:X}", x, y),
+            Instruction::ShiftRight(x, y) => write!(f, "SHR V{:X}, V{:X}", x, y),
+            Instruction::SubInv(x, y) => write!(f, "SUBN V{:X}, V{:X}", x, y),
+            Instruction::ShiftLeft(x, y) => write!(f, "SHL V{:X}, V{:X}", x, y),
+            Instruction::SkipNotEqual(x, y) => write!(f, "SNE V{:X}, V{:X}", x, y),
+            Instruction::LoadI(addr) => write!(f, "LD I, 0x{:03X}", addr),
+            Instruction::LongJump(addr) => write!(f, "JP V0, 0x{:03X}", addr),
+            Instruction::Rand(x, k) => write!(f, "RND V{:X}, 0x{:02X}", x, k),
+            Instruction::Draw(x, y, n) => write!(f, "DRW V{:X}, V{:X}, {}", x, y, n),
+            Instruction::SkipPressed(x) => write!(f, "SKP V{:X}", x),
+            Instruction::SkipNotPressed(x) => write!(f, "SKNP V{:X}", x),
+            Instruction::LoadLongI(addr) => write!(f, "LD I, LONG 0x{:04X}", addr),
+            Instruction::Plane(n) => write!(f, "PLANE {}", n),
+            Instruction::LoadAudio => write!(f, "AUDIO"),
+            Instruction::GetTimer(x) => write!(f, "LD V{:X}, DT", x),
+            Instruction::WaitKey(x) => write!(f, "LD V{:X}, K", x),
+            Instruction::SetTimer(x) => write!(f, "LD DT, V{:X}", x),
+            Instruction::SetSoundTimer(x) => write!(f, "LD ST, V{:X}", x),
+            Instruction::AddI(x) => write!(f, "ADD I, V{:X}", x),
+            Instruction::LoadHexGlyph(x) => write!(f, "LD F, V{:X}", x),
+            Instruction::LoadBigHexGlyph(x) => write!(f, "LD HF, V{:X}", x),
+            Instruction::Pitch(x) => write!(f, "PITCH V{:X}", x),
+            Instruction::StoreBCD(x) => write!(f, "LD B, V{:X}", x),
+            Instruction::StoreRegisters(x) => write!(f, "LD [I], V{:X}", x),
+            Instruction::LoadRegisters(x) => write!(f, "LD V{:X}, [I]", x),
+            Instruction::StoreFlags(x) => write!(f, "LD R, V{:X}", x),
+            Instruction::LoadFlags(x) => write!(f, "LD V{:X}, R", x),
+            // Shown as data, so that listings still assemble
+            Instruction::Unknown(opcode) => write!(f, "DW 0x{:04X}", opcode),
         }
     }
 }
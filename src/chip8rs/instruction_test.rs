@@ -6,4 +6,31 @@ fn test_decode() {
     assert_eq!(Instruction::decode(0x00e0), Instruction::Clear);
     assert_eq!(Instruction::decode(0x00ee), Instruction::Return);
     assert_eq!(Instruction::decode(0x1000), Instruction::Jump(0 as Addr));
-}
\ No newline at end of file
+}
+
+#[test]
+fn test_display() {
+    assert_eq!(Instruction::decode(0x631f).to_string(), "LD V3, 0x1F");
+    assert_eq!(Instruction::decode(0xd015).to_string(), "DRW V0, V1, 5");
+    assert_eq!(Instruction::decode(0xb300).to_string(), "JP V0, 0x300");
+    assert_eq!(Instruction::decode(0x2abc).to_string(), "CALL 0xABC");
+    assert_eq!(Instruction::decode(0xfa65).to_string(), "LD VA, [I]");
+    assert_eq!(Instruction::decode(0x5123).to_string(), "LOAD V1 - V2");
+    assert_eq!(Instruction::decode_long(0xf000, 0x1234).to_string(), "LD I, LONG 0x1234");
+    assert_eq!(Instruction::decode(0x5121).to_string(), "DW 0x5121");
+}
+
+#[test]
+fn test_encode_inverts_decode() {
+    for opcode in 0..=0xffff {
+        let instruction = Instruction::decode(opcode);
+        assert_eq!(instruction.encode(), opcode, "{:04x} decoded as {:?}", opcode, instruction);
+    }
+}
+
+#[test]
+fn test_shifts_keep_vy() {
+    assert_eq!(Instruction::decode(0x8126), Instruction::ShiftRight(1, 2));
+    assert_eq!(Instruction::decode(0x812e), Instruction::ShiftLeft(1, 2));
+    assert_eq!(Instruction::decode(0x5121), Instruction::Unknown(0x5121));
+}
@@ -0,0 +1,98 @@
+use std::fmt;
+use super::instruction::{Addr, Byte};
+
+// The MegaChip-8 extensions, following the Mega8 documentation. They only decode in MegaChip mode,
+// and take precedence over `Instruction` there.
+#[derive(PartialEq, Eq, Debug)]
+pub enum MegaInstruction {
+    // 0010 - MEGAOFF: Leaves MegaChip mode, back to SUPER-CHIP graphics
+    MegaOff,
+    // 0011 - MEGAON: Enters MegaChip mode, with the 256x192 colour display
+    MegaOn,
+    // 00Bn - SCRU nibble: Scrolls the display up by `Nibble` pixels
+    ScrollUp(Byte),
+    // 01nn nnnn - LDHI I, addr: Sets the `I` register to the 24 bit address made of `nn` and the following word. `decode` leaves the address at the `nn` part, use `decode_long` to fill in the rest.
+    LoadHighI(u32),
+    // 02nn - LDPAL nn: Loads `nn` ARGB colours from address `I` into the palette, starting at index 1
+    LoadPalette(Byte),
+    // 03nn - SPRW nn: Sets the sprite width to `nn` pixels, `0` meaning 256
+    SpriteWidth(Byte),
+    // 04nn - SPRH nn: Sets the sprite height to `nn` pixels, `0` meaning 256
+    SpriteHeight(Byte),
+    // 05nn - ALPHA nn: Sets the screen alpha to `nn`
+    Alpha(Byte),
+    // 060n - DIGISND n: Plays the digitised sound at address `I`, looping when `n` is `0`
+    PlaySound(Byte),
+    // 0700 - STOPSND: Stops the digitised sound
+    StopSound,
+    // 080n - BMODE n: Sets the sprite blend mode to `n`, see `BlendMode`
+    BlendMode(Byte),
+    // 09nn - CCOL nn: Sets the palette index that counts as a collision when drawn over
+    CollisionColor(Byte),
+}
+
+impl MegaInstruction {
+    pub fn decode(opcode: u16) -> Option<MegaInstruction> {
+        let nibbles = (
+            (opcode & 0xF000) >> 12,
+            (opcode & 0x0F00) >> 8,
+            (opcode & 0x00F0) >> 4,
+            opcode & 0x000F,
+        );
+        let nn = (opcode & 0x00FF) as Byte;
+        let n = nibbles.3 as Byte;
+
+        match nibbles {
+            (0x00, 0x00, 0x01, 0x00) => Some(MegaInstruction::MegaOff),
+            (0x00, 0x00, 0x01, 0x01) => Some(MegaInstruction::MegaOn),
+            (0x00, 0x00, 0x0b, _) => Some(MegaInstruction::ScrollUp(n)),
+            (0x00, 0x01, _, _) => Some(MegaInstruction::LoadHighI((nn as u32) << 16)),
+            (0x00, 0x02, _, _) => Some(MegaInstruction::LoadPalette(nn)),
+            (0x00, 0x03, _, _) => Some(MegaInstruction::SpriteWidth(nn)),
+            (0x00, 0x04, _, _) => Some(MegaInstruction::SpriteHeight(nn)),
+            (0x00, 0x05, _, _) => Some(MegaInstruction::Alpha(nn)),
+            (0x00, 0x06, 0x00, _) => Some(MegaInstruction::PlaySound(n)),
+            (0x00, 0x07, 0x00, 0x00) => Some(MegaInstruction::StopSound),
+            (0x00, 0x08, 0x00, _) => Some(MegaInstruction::BlendMode(n)),
+            (0x00, 0x09, _, _) => Some(MegaInstruction::CollisionColor(nn)),
+            _ => None,
+        }
+    }
+
+    // Decodes the opcode word `opcode` followed by the word `next`, which is the operand of the
+    // 4 byte instructions.
+    pub fn decode_long(opcode: u16, next: Addr) -> Option<MegaInstruction> {
+        match MegaInstruction::decode(opcode) {
+            Some(MegaInstruction::LoadHighI(high)) => Some(MegaInstruction::LoadHighI(high | next as u32)),
+            instruction => instruction,
+        }
+    }
+
+    // Size of the instruction in bytes, including its operand words.
+    pub fn size(&self) -> usize {
+        match self {
+            MegaInstruction::LoadHighI(_) => 4,
+            _ => 2,
+        }
+    }
+}
+
+// The mnemonics of the Mega8 documentation.
+impl fmt::Display for MegaInstruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            MegaInstruction::MegaOff => write!(f, "MEGAOFF"),
+            MegaInstruction::MegaOn => write!(f, "MEGAON"),
+            MegaInstruction::ScrollUp(n) => write!(f, "SCRU {}", n),
+            MegaInstruction::LoadHighI(addr) => write!(f, "LDHI I, 0x{:06X}", addr),
+            MegaInstruction::LoadPalette(n) => write!(f, "LDPAL 0x{:02X}", n),
+            MegaInstruction::SpriteWidth(n) => write!(f, "SPRW 0x{:02X}", n),
+            MegaInstruction::SpriteHeight(n) => write!(f, "SPRH 0x{:02X}", n),
+            MegaInstruction::Alpha(n) => write!(f, "ALPHA 0x{:02X}", n),
+            MegaInstruction::PlaySound(n) => write!(f, "DIGISND {}", n),
+            MegaInstruction::StopSound => write!(f, "STOPSND"),
+            MegaInstruction::BlendMode(n) => write!(f, "BMODE {}", n),
+            MegaInstruction::CollisionColor(n) => write!(f, "CCOL 0x{:02X}", n),
+        }
+    }
+}
@@ -0,0 +1,285 @@
+use super::snapshot::{SnapshotError, StateReader, StateWriter};
+
+pub const MEGA_WIDTH: usize = 256;
+pub const MEGA_HEIGHT: usize = 192;
+const PALETTE_SIZE: usize = 256;
+const SOUND_HEADER_SIZE: usize = 6;
+
+// How sprite pixels are combined with the pixels already on screen.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum BlendMode {
+    Normal,
+    Alpha25,
+    Alpha50,
+    Alpha75,
+    Add,
+    Multiply,
+}
+
+impl BlendMode {
+    pub fn from_byte(n: u8) -> BlendMode {
+        match n {
+            1 => BlendMode::Alpha25,
+            2 => BlendMode::Alpha50,
+            3 => BlendMode::Alpha75,
+            4 => BlendMode::Add,
+            5 => BlendMode::Multiply,
+            _ => BlendMode::Normal,
+        }
+    }
+
+    pub fn id(self) -> u8 {
+        match self {
+            BlendMode::Normal => 0,
+            BlendMode::Alpha25 => 1,
+            BlendMode::Alpha50 => 2,
+            BlendMode::Alpha75 => 3,
+            BlendMode::Add => 4,
+            BlendMode::Multiply => 5,
+        }
+    }
+
+    fn blend(self, src: u8, dst: u8) -> u8 {
+        let (src, dst) = (src as u32, dst as u32);
+        let c = match self {
+            BlendMode::Normal => src,
+            BlendMode::Alpha25 => (src + dst * 3) / 4,
+            BlendMode::Alpha50 => (src + dst) / 2,
+            BlendMode::Alpha75 => (src * 3 + dst) / 4,
+            BlendMode::Add => (src + dst).min(0xff),
+            BlendMode::Multiply => src * dst / 0xff,
+        };
+        c as u8
+    }
+}
+
+// A digitised sound started by 060n: unsigned 8 bit mono samples.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct DigitalSound {
+    pub id: u32,  // changes every time a sound is started, so frontends can tell a restart
+    pub rate: u32,
+    pub samples: Vec<u8>,
+    pub looping: bool,
+}
+
+// What a frontend needs to present a MegaChip frame.
+pub struct MegaFrame<'a> {
+    pub pixels: &'a [u32],  // MEGA_WIDTH x MEGA_HEIGHT, row major, 0xAARRGGBB
+    pub sound: Option<&'a DigitalSound>,
+}
+
+// MegaChip display and sound state. Sprites are drawn to a back buffer, which 00E0 presents before
+// clearing it.
+pub struct MegaChip {
+    pub enabled: bool,
+    palette: [u32; PALETTE_SIZE],
+    front: Vec<u32>,
+    back: Vec<u32>,
+    indices: Vec<u8>,  // palette index last drawn on each back buffer pixel, for collisions
+    sprite_width: usize,
+    sprite_height: usize,
+    alpha: u8,
+    blend_mode: BlendMode,
+    collision_color: u8,
+    sound: Option<DigitalSound>,
+    sounds_started: u32,
+}
+
+impl Default for MegaChip {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MegaChip {
+    pub fn new() -> Self {
+        MegaChip {
+            enabled: false,
+            palette: [0; PALETTE_SIZE],
+            front: vec![0; MEGA_WIDTH * MEGA_HEIGHT],
+            back: vec![0; MEGA_WIDTH * MEGA_HEIGHT],
+            indices: vec![0; MEGA_WIDTH * MEGA_HEIGHT],
+            sprite_width: 0,
+            sprite_height: 0,
+            alpha: 0xff,
+            blend_mode: BlendMode::Normal,
+            collision_color: 0,
+            sound: None,
+            sounds_started: 0,
+        }
+    }
+
+    pub fn frame(&self) -> MegaFrame<'_> {
+        MegaFrame {
+            pixels: &self.front,
+            sound: self.sound.as_ref(),
+        }
+    }
+
+    // Bytes read by a sprite draw.
+    pub fn sprite_size(&self) -> usize {
+        self.sprite_width * self.sprite_height
+    }
+
+    // `n` of `0` means 256 pixels.
+    pub fn set_sprite_width(&mut self, n: u8) {
+        self.sprite_width = if n == 0 { 256 } else { n as usize };
+    }
+
+    pub fn set_sprite_height(&mut self, n: u8) {
+        self.sprite_height = if n == 0 { 256 } else { n as usize };
+    }
+
+    pub fn set_alpha(&mut self, alpha: u8) {
+        self.alpha = alpha;
+    }
+
+    pub fn set_blend_mode(&mut self, mode: BlendMode) {
+        self.blend_mode = mode;
+    }
+
+    pub fn set_collision_color(&mut self, index: u8) {
+        self.collision_color = index;
+    }
+
+    // Loads `colors` ARGB entries into the palette from index 1, index 0 being transparent.
+    pub fn load_palette(&mut self, colors: &[u8]) {
+        for (n, argb) in colors.chunks(4).enumerate().take(PALETTE_SIZE - 1) {
+            self.palette[n + 1] = argb.iter().fold(0, |c, &b| c << 8 | b as u32);
+        }
+    }
+
+    // Presents the back buffer, then clears it.
+    pub fn clear(&mut self) {
+        self.front.copy_from_slice(&self.back);
+        self.back.iter_mut().for_each(|p| *p = 0);
+        self.indices.iter_mut().for_each(|p| *p = 0);
+    }
+
+    // Draws a sprite of one palette index per byte at `x`, `y`, clipped at the screen edges.
+    // Returns whether any pixel was drawn over the collision colour.
+    pub fn draw(&mut self, sprite: &[u8], x: usize, y: usize) -> bool {
+        let mut collision = false;
+        for row in 0..self.sprite_height {
+            let sy = y + row;
+            if sy >= MEGA_HEIGHT {
+                break;
+            }
+            for col in 0..self.sprite_width {
+                let sx = x + col;
+                if sx >= MEGA_WIDTH {
+                    break;
+                }
+                let index = sprite[row * self.sprite_width + col];
+                if index == 0 {
+                    continue;
+                }
+                let p = sy * MEGA_WIDTH + sx;
+                if self.indices[p] == self.collision_color {
+                    collision = true;
+                }
+                self.indices[p] = index;
+                self.back[p] = self.blend(self.palette[index as usize], self.back[p]);
+            }
+        }
+        collision
+    }
+
+    fn blend(&self, src: u32, dst: u32) -> u32 {
+        let channel = |c: u32, shift: u32| ((c >> shift) & 0xff) as u8;
+        let mut out = (self.alpha as u32) << 24;
+        for &shift in &[16, 8, 0] {
+            let c = self.blend_mode.blend(channel(src, shift), channel(dst, shift));
+            out |= (c as u32) << shift;
+        }
+        out
+    }
+
+    // Moves the back buffer by `dx`, `dy` pixels. Pixels scrolled in from outside are cleared.
+    pub fn scroll(&mut self, dx: isize, dy: isize) {
+        let back = self.back.clone();
+        let indices = self.indices.clone();
+        for y in 0..MEGA_HEIGHT {
+            for x in 0..MEGA_WIDTH {
+                let sx = x as isize - dx;
+                let sy = y as isize - dy;
+                let inside = sx >= 0 && sy >= 0 && (sx as usize) < MEGA_WIDTH && (sy as usize) < MEGA_HEIGHT;
+                let p = y * MEGA_WIDTH + x;
+                if inside {
+                    let s = sy as usize * MEGA_WIDTH + sx as usize;
+                    self.back[p] = back[s];
+                    self.indices[p] = indices[s];
+                } else {
+                    self.back[p] = 0;
+                    self.indices[p] = 0;
+                }
+            }
+        }
+    }
+
+    // Starts the sound at the start of `data`: a 16 bit sample rate, a 24 bit length, a padding
+    // byte, then the samples. Returns `None` if `data` is shorter than the header says.
+    pub fn play_sound(&mut self, data: &[u8], looping: bool) -> Option<()> {
+        let header = data.get(..SOUND_HEADER_SIZE)?;
+        let rate = (header[0] as u32) << 8 | header[1] as u32;
+        let len = (header[2] as usize) << 16 | (header[3] as usize) << 8 | header[4] as usize;
+        let samples = data.get(SOUND_HEADER_SIZE..SOUND_HEADER_SIZE + len)?.to_vec();
+        self.sounds_started += 1;
+        self.sound = Some(DigitalSound {
+            id: self.sounds_started,
+            rate,
+            samples,
+            looping,
+        });
+        Some(())
+    }
+
+    pub fn stop_sound(&mut self) {
+        self.sound = None;
+    }
+
+    pub fn save_state(&self, w: &mut StateWriter) {
+        w.bool(self.enabled);
+        self.palette.iter().for_each(|&c| w.u32(c));
+        self.front.iter().for_each(|&c| w.u32(c));
+        self.back.iter().for_each(|&c| w.u32(c));
+        w.raw(&self.indices);
+        w.u16(self.sprite_width as u16);
+        w.u16(self.sprite_height as u16);
+        w.u8(self.alpha);
+        w.u8(self.blend_mode.id());
+        w.u8(self.collision_color);
+        w.u32(self.sounds_started);
+        w.bool(self.sound.is_some());
+        if let Some(ref sound) = self.sound {
+            w.u32(sound.id);
+            w.u32(sound.rate);
+            w.bool(sound.looping);
+            w.bytes(&sound.samples);
+        }
+    }
+
+    pub fn load_state(r: &mut StateReader) -> Result<MegaChip, SnapshotError> {
+        let mut mega = MegaChip::new();
+        mega.enabled = r.bool()?;
+        for c in mega.palette.iter_mut().chain(mega.front.iter_mut()).chain(mega.back.iter_mut()) {
+            *c = r.u32()?;
+        }
+        mega.indices.copy_from_slice(r.raw(MEGA_WIDTH * MEGA_HEIGHT)?);
+        mega.sprite_width = r.u16()? as usize;
+        mega.sprite_height = r.u16()? as usize;
+        mega.alpha = r.u8()?;
+        mega.blend_mode = BlendMode::from_byte(r.u8()?);
+        mega.collision_color = r.u8()?;
+        mega.sounds_started = r.u32()?;
+        if r.bool()? {
+            mega.sound = Some(DigitalSound {
+                id: r.u32()?,
+                rate: r.u32()?,
+                looping: r.bool()?,
+                samples: r.bytes()?.to_vec(),
+            });
+        }
+        Ok(mega)
+    }
+}
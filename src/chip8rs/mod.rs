@@ -1,7 +1,39 @@
+pub mod args;
+pub mod asm;
+pub mod audio;
+pub mod dap;
+pub mod debugger;
+pub mod disasm;
+pub mod dump;
+pub mod emulator;
+pub mod error;
+pub mod flow;
 pub mod font;
+pub mod frontend;
+pub mod hash;
 pub mod instruction;
+pub mod mega_instruction;
+pub mod megachip;
+pub mod mode;
+pub mod movie;
+pub mod quirks;
+pub mod random;
+pub mod rewind;
+pub mod rom;
+pub mod roms;
+pub mod scheduler;
+pub mod script;
+pub mod snapshot;
+#[cfg(test)]
+mod test_support;
+pub mod trace;
+#[cfg(feature = "sdl")]
 pub mod ui;
 pub mod vm;
+pub mod watch;
 
 pub use vm::VM;
-pub use ui::UI;
\ No newline at end of file
+#[cfg(feature = "sdl")]
+pub use ui::UI;
+pub use scheduler::Scheduler;
+pub use emulator::Emulator;
@@ -0,0 +1,83 @@
+use std::path::Path;
+use super::instruction::Instruction;
+use super::quirks::Quirks;
+use super::vm::PROGRAM_START;
+
+// The member of the CHIP-8 family being emulated. XO-CHIP and MegaChip both extend SUPER-CHIP,
+// which extends CHIP-8.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug, Default)]
+pub enum Mode {
+    #[default]
+    Chip8 = 0,
+    SuperChip = 1,
+    XoChip = 2,
+    MegaChip = 3,
+}
+
+impl Mode {
+    // Guesses the mode from the ROM's file extension, falling back to plain CHIP-8.
+    pub fn from_path(path: &Path) -> Mode {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some(ext) if ext.eq_ignore_ascii_case("sc8") => Mode::SuperChip,
+            Some(ext) if ext.eq_ignore_ascii_case("xo8") => Mode::XoChip,
+            Some(ext) if ext.eq_ignore_ascii_case("mc8") => Mode::MegaChip,
+            _ => Mode::Chip8,
+        }
+    }
+
+    // Stable number of the mode, for save files.
+    pub fn id(self) -> u8 {
+        self as u8
+    }
+
+    pub fn from_id(id: u8) -> Option<Mode> {
+        [Mode::Chip8, Mode::SuperChip, Mode::XoChip, Mode::MegaChip]
+            .iter()
+            .cloned()
+            .find(|mode| mode.id() == id)
+    }
+
+    // The quirks ROMs written for this mode usually expect.
+    pub fn default_quirks(self) -> Quirks {
+        match self {
+            Mode::Chip8 => Quirks::default(),
+            Mode::SuperChip | Mode::MegaChip => Quirks::SCHIP,
+            Mode::XoChip => Quirks::XO_CHIP,
+        }
+    }
+
+    // Bytes of addressable memory.
+    pub fn memory_size(self) -> usize {
+        match self {
+            Mode::Chip8 | Mode::SuperChip => 4096,
+            Mode::XoChip => 0x10000,
+            Mode::MegaChip => 0x1000000,
+        }
+    }
+
+    // Largest ROM that fits in memory.
+    pub fn max_rom_size(self) -> usize {
+        self.memory_size() - PROGRAM_START
+    }
+
+    pub fn supports(self, instruction: &Instruction) -> bool {
+        match instruction {
+            Instruction::ScrollDown(_)
+            | Instruction::ScrollRight
+            | Instruction::ScrollLeft
+            | Instruction::Exit
+            | Instruction::LowRes
+            | Instruction::HighRes
+            | Instruction::LoadBigHexGlyph(_)
+            | Instruction::StoreFlags(_)
+            | Instruction::LoadFlags(_) => self >= Mode::SuperChip,
+            Instruction::StoreRange(_, _)
+            | Instruction::LoadRange(_, _)
+            | Instruction::LoadLongI(_)
+            | Instruction::Plane(_)
+            | Instruction::LoadAudio
+            | Instruction::Pitch(_) => self == Mode::XoChip,
+            _ => true,
+        }
+    }
+}
@@ -0,0 +1,245 @@
+use std::error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use super::hash::crc32;
+use super::mode::Mode;
+use super::quirks::Quirks;
+use super::snapshot::{Snapshot, SnapshotError, StateReader, StateWriter};
+use super::vm::{keypad_bits, VM};
+use super::error::VmError;
+
+const MAGIC: &[u8; 4] = b"C8MV";
+const VERSION: u16 = 2;
+const CHECK_INTERVAL: u64 = 60;  // frames between framebuffer hashes
+
+#[derive(Debug)]
+pub enum MovieError {
+    Io(io::Error),
+    BadFormat,
+    UnsupportedVersion(u16),
+    RomMismatch,
+    // The replay drew something else than the recording did
+    Desync { frame: u64 },
+    Vm(VmError),
+}
+
+impl fmt::Display for MovieError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MovieError::Io(err) => write!(f, "{}", err),
+            MovieError::BadFormat => write!(f, "not a chip8rs movie, or corrupt"),
+            MovieError::UnsupportedVersion(v) => write!(f, "unsupported movie version {}", v),
+            MovieError::RomMismatch => write!(f, "movie was recorded with a different ROM"),
+            MovieError::Desync { frame } => write!(f, "replay desynced at frame {}", frame),
+            MovieError::Vm(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl error::Error for MovieError {}
+
+impl From<io::Error> for MovieError {
+    fn from(err: io::Error) -> Self {
+        MovieError::Io(err)
+    }
+}
+
+impl From<SnapshotError> for MovieError {
+    fn from(_: SnapshotError) -> Self {
+        MovieError::BadFormat
+    }
+}
+
+impl From<VmError> for MovieError {
+    fn from(err: VmError) -> Self {
+        MovieError::Vm(err)
+    }
+}
+
+// What the VM was given for one 60 Hz frame: `instructions` steps, all with `keypad`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct FrameInput {
+    pub keypad: [bool; 16],
+    pub instructions: u32,
+}
+
+// Identical frames in a row.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+struct Run {
+    input: FrameInput,
+    frames: u32,
+}
+
+// A recorded session, replayed by feeding the same input to a VM restored to the state the
+// recording started from.
+//
+// Files start with the "C8MV" magic and a format version, and end with the CRC-32 of everything
+// before it. All integers are little endian.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Movie {
+    pub rom_hash: u64,
+    pub mode: Mode,
+    pub quirks: Quirks,
+    state: Vec<u8>,  // `VM::snapshot` when the recording started
+    runs: Vec<Run>,
+    checks: Vec<(u64, u64)>,  // frame number and `VM::frame_hash` after it, every CHECK_INTERVAL frames
+    frames: u64,
+}
+
+impl Movie {
+    // Starts a recording from the current state of `vm`.
+    pub fn new(vm: &VM) -> Self {
+        let snapshot = vm.snapshot();
+        Movie {
+            rom_hash: snapshot.rom_hash,
+            mode: snapshot.mode,
+            quirks: snapshot.quirks,
+            state: snapshot.state,
+            runs: Vec::new(),
+            checks: Vec::new(),
+            frames: 0,
+        }
+    }
+
+    // A VM in the state the recording started from, once `rom` is loaded.
+    pub fn start(&self, rom: &[u8]) -> Result<VM, MovieError> {
+        let mut vm = VM::with_mode(self.mode, self.quirks);
+        vm.load(rom);
+        if vm.rom_hash() != self.rom_hash {
+            return Err(MovieError::RomMismatch);
+        }
+        vm.restore(&Snapshot {
+            rom_hash: self.rom_hash,
+            mode: self.mode,
+            quirks: self.quirks,
+            state: self.state.clone(),
+        })?;
+        Ok(vm)
+    }
+
+    pub fn frames(&self) -> u64 {
+        self.frames
+    }
+
+    // Appends a frame which `vm` has just run.
+    pub fn record(&mut self, input: FrameInput, vm: &VM) {
+        match self.runs.last_mut() {
+            Some(run) if run.input == input && run.frames < u32::MAX => run.frames += 1,
+            _ => self.runs.push(Run { input, frames: 1 }),
+        }
+        self.frames += 1;
+        if self.frames % CHECK_INTERVAL == 0 {
+            self.checks.push((self.frames, vm.frame_hash()));
+        }
+    }
+
+    // Frame inputs in order.
+    pub fn inputs(&self) -> impl Iterator<Item = FrameInput> + '_ {
+        self.runs.iter().flat_map(|run| (0..run.frames).map(move |_| run.input))
+    }
+
+    // Compares the screen of `vm` after frame `frame` (counting from 1) with the recording, if it
+    // kept a hash for that frame.
+    pub fn check(&self, frame: u64, vm: &VM) -> Result<(), MovieError> {
+        match self.checks.binary_search_by_key(&frame, |&(n, _)| n) {
+            Ok(n) if self.checks[n].1 != vm.frame_hash() => Err(MovieError::Desync { frame }),
+            _ => Ok(()),
+        }
+    }
+
+    // Replays the whole movie on `vm`, as returned by `start`, without any frontend.
+    pub fn replay(&self, vm: &mut VM) -> Result<(), MovieError> {
+        for (n, input) in self.inputs().enumerate() {
+            vm.run_frame(input.keypad, input.instructions)?;
+            self.check(n as u64 + 1, vm)?;
+        }
+        Ok(())
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut w = StateWriter::new();
+        w.raw(MAGIC);
+        w.u16(VERSION);
+        w.u64(self.rom_hash);
+        w.u8(self.mode.id());
+        w.u8(self.quirks.bits());
+        w.bytes(&self.state);
+        w.u32(self.runs.len() as u32);
+        for run in &self.runs {
+            w.u32(run.frames);
+            w.u16(keypad_bits(&run.input.keypad));
+            w.u32(run.input.instructions);
+        }
+        w.u32(self.checks.len() as u32);
+        for &(frame, hash) in &self.checks {
+            w.u64(frame);
+            w.u64(hash);
+        }
+        let mut data = w.finish();
+        let checksum = crc32(&data);
+        data.extend_from_slice(&checksum.to_le_bytes());
+        data
+    }
+
+    pub fn from_bytes(data: &[u8]) -> Result<Movie, MovieError> {
+        if data.len() < MAGIC.len() + 4 || &data[..MAGIC.len()] != MAGIC {
+            return Err(MovieError::BadFormat);
+        }
+        let (body, checksum) = data.split_at(data.len() - 4);
+        if StateReader::new(checksum).u32()? != crc32(body) {
+            return Err(MovieError::BadFormat);
+        }
+
+        let mut r = StateReader::new(&body[MAGIC.len()..]);
+        let version = r.u16()?;
+        if version != VERSION {
+            return Err(MovieError::UnsupportedVersion(version));
+        }
+        let rom_hash = r.u64()?;
+        let mode = Mode::from_id(r.u8()?).ok_or(MovieError::BadFormat)?;
+        let quirks = Quirks::from_bits(r.u8()?);
+        let state = r.bytes()?.to_vec();
+        let mut runs = Vec::new();
+        for _ in 0..r.u32()? {
+            let frames = r.u32()?;
+            let bits = r.u16()?;
+            let mut keypad = [false; 16];
+            for (key, down) in keypad.iter_mut().enumerate() {
+                *down = bits & 1 << key != 0;
+            }
+            let instructions = r.u32()?;
+            runs.push(Run { input: FrameInput { keypad, instructions }, frames });
+        }
+        let mut checks = Vec::new();
+        for _ in 0..r.u32()? {
+            checks.push((r.u64()?, r.u64()?));
+        }
+        if !r.is_empty() {
+            return Err(MovieError::BadFormat);
+        }
+
+        let frames = runs.iter().map(|run| run.frames as u64).sum();
+        Ok(Movie { rom_hash, mode, quirks, state, runs, checks, frames })
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), MovieError> {
+        fs::write(path, self.to_bytes())?;
+        Ok(())
+    }
+
+    pub fn load(path: &Path) -> Result<Movie, MovieError> {
+        Movie::from_bytes(&fs::read(path)?)
+    }
+}
+
+// The movie of a ROM lives next to it, e.g. "games/Pong.ch8.movie".
+pub fn movie_path(rom_path: &Path) -> PathBuf {
+    let name = rom_path.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+    rom_path.with_file_name(format!("{}.movie", name))
+}
+
+#[cfg(test)]
+#[path = "./movie_test.rs"]
+mod movie_test;
@@ -0,0 +1,89 @@
+use super::*;
+use super::super::random::CosmacVip;
+
+
+// Draws a digit at a random place for every step key 5 is held.
+const ROM: &[u8] = &[
+    0xC0, 0x3F,  // 200: RND V0, 0x3F
+    0xC1, 0x1F,  // 202: RND V1, 0x1F
+    0x62, 0x05,  // 204: LD V2, 5
+    0xE2, 0xA1,  // 206: SKNP V2
+    0xD0, 0x15,  // 208: DRW V0, V1, 5
+    0x12, 0x00,  // 20A: JP 0x200
+];
+
+// Records `frames` frames, after running `skip` frames without recording them.
+fn record(seed: u64, skip: u64, frames: u64) -> Movie {
+    record_with(VM::with_seed(Mode::Chip8, Quirks::default(), seed), skip, frames)
+}
+
+fn record_with(mut vm: VM, skip: u64, frames: u64) -> Movie {
+    vm.load(ROM);
+    for _ in 0..skip {
+        vm.run_frame([true; 16], 9).unwrap();
+    }
+    let mut movie = Movie::new(&vm);
+    for frame in 0..frames {
+        let mut keypad = [false; 16];
+        keypad[5] = frame % 20 < 10;
+        let input = FrameInput { keypad, instructions: 9 };
+        vm.run_frame(input.keypad, input.instructions).unwrap();
+        movie.record(input, &vm);
+    }
+    movie
+}
+
+#[test]
+fn test_replay() {
+    let movie = record(3, 0, 200);
+    assert_eq!(movie.frames(), 200);
+    assert_eq!(movie.runs.len(), 20);
+    let mut vm = movie.start(ROM).unwrap();
+    movie.replay(&mut vm).unwrap();
+}
+
+#[test]
+fn test_replay_from_running_game() {
+    let movie = record(3, 50, 200);
+    let vm = movie.start(ROM).unwrap();
+    assert_ne!(vm.frame_hash(), VM::new().frame_hash());
+    assert_eq!(vm.snapshot().state, movie.state);
+    let mut vm = vm;
+    movie.replay(&mut vm).unwrap();
+}
+
+#[test]
+fn test_bytes_round_trip() {
+    let movie = record(3, 0, 200);
+    assert_eq!(Movie::from_bytes(&movie.to_bytes()).unwrap(), movie);
+
+    let mut bytes = movie.to_bytes();
+    bytes[10] ^= 1;
+    assert!(matches!(Movie::from_bytes(&bytes), Err(MovieError::BadFormat)));
+}
+
+#[test]
+fn test_desync() {
+    let mut movie = record(3, 0, 200);
+    movie.state = record(4, 0, 0).state;
+    let mut vm = movie.start(ROM).unwrap();
+    assert!(matches!(movie.replay(&mut vm), Err(MovieError::Desync { .. })));
+}
+
+#[test]
+fn test_rom_mismatch() {
+    let movie = record(3, 0, 10);
+    assert!(matches!(movie.start(&[0x12, 0x00]), Err(MovieError::RomMismatch)));
+}
+
+#[test]
+fn test_cosmac_vip_random() {
+    // Any interpreter image does, the movie carries the page the algorithm reads
+    let interpreter: Vec<u8> = (0..0x200).map(|n| (n * 7) as u8).collect();
+    let rng = CosmacVip::new(&interpreter, 0x1234).unwrap();
+    let movie = record_with(VM::with_rng(Mode::Chip8, Quirks::default(), Box::new(rng)), 50, 200);
+    let movie = Movie::from_bytes(&movie.to_bytes()).unwrap();
+    let mut vm = movie.start(ROM).unwrap();
+    assert_eq!(vm.snapshot().state, movie.state);
+    movie.replay(&mut vm).unwrap();
+}
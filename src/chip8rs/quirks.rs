@@ -0,0 +1,107 @@
+// Behaviour switches for the opcodes that interpreters historically disagree on.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Quirks {
+    // 8xy6/8xyE shift `Vx` in place and ignore `Vy`
+    pub shift: bool,
+    // Fx55/Fx65 leave `I` unchanged instead of setting it to `I + x + 1`
+    pub load_store: bool,
+    // Bnnn jumps to `Vx + nnn`, where `x` is the highest nibble of `nnn`, instead of `V0 + nnn`
+    pub jump: bool,
+    // Dxyn clips sprites at the screen edges instead of wrapping them around
+    pub clip: bool,
+    // 8xy1/8xy2/8xy3 reset `VF` to `0`
+    pub vf_reset: bool,
+    // Fx1E sets `VF` to `1` when `I + Vx` goes past 0xF00, `0` otherwise. chip8rs always used 0xF00
+    // rather than the end of memory, 0xFFF.
+    pub add_i_overflow: bool,
+}
+
+impl Quirks {
+    // The original COSMAC VIP interpreter.
+    pub const COSMAC_VIP: Quirks = Quirks {
+        shift: false,
+        load_store: false,
+        jump: false,
+        clip: true,
+        vf_reset: true,
+        add_i_overflow: false,
+    };
+
+    // CHIP-48 on the HP-48 calculators.
+    pub const CHIP48: Quirks = Quirks {
+        shift: true,
+        load_store: true,
+        jump: true,
+        clip: true,
+        vf_reset: false,
+        add_i_overflow: false,
+    };
+
+    // SUPER-CHIP 1.1.
+    pub const SCHIP: Quirks = Quirks {
+        shift: true,
+        load_store: true,
+        jump: true,
+        clip: true,
+        vf_reset: false,
+        add_i_overflow: false,
+    };
+
+    // XO-CHIP, as implemented by Octo.
+    pub const XO_CHIP: Quirks = Quirks {
+        shift: false,
+        load_store: false,
+        jump: false,
+        clip: false,
+        vf_reset: false,
+        add_i_overflow: false,
+    };
+
+    // Preset names accepted on the command line.
+    pub const PRESETS: &'static [&'static str] = &["chip8rs", "vip", "chip48", "schip", "xo-chip"];
+
+    pub fn preset(name: &str) -> Option<Quirks> {
+        match name {
+            "chip8rs" => Some(Quirks::default()),
+            "vip" => Some(Quirks::COSMAC_VIP),
+            "chip48" => Some(Quirks::CHIP48),
+            "schip" => Some(Quirks::SCHIP),
+            "xo-chip" => Some(Quirks::XO_CHIP),
+            _ => None,
+        }
+    }
+
+    // Packs the switches into one byte, in field order from the lowest bit.
+    pub fn bits(&self) -> u8 {
+        [self.shift, self.load_store, self.jump, self.clip, self.vf_reset, self.add_i_overflow]
+            .iter()
+            .enumerate()
+            .fold(0, |bits, (n, &on)| bits | (on as u8) << n)
+    }
+
+    pub fn from_bits(bits: u8) -> Quirks {
+        let on = |n: u8| bits & (1 << n) != 0;
+        Quirks {
+            shift: on(0),
+            load_store: on(1),
+            jump: on(2),
+            clip: on(3),
+            vf_reset: on(4),
+            add_i_overflow: on(5),
+        }
+    }
+}
+
+// The interpretation chip8rs has always used.
+impl Default for Quirks {
+    fn default() -> Self {
+        Quirks {
+            shift: true,
+            load_store: true,
+            jump: false,
+            clip: false,
+            vf_reset: false,
+            add_i_overflow: true,
+        }
+    }
+}
@@ -0,0 +1,115 @@
+use super::snapshot::SnapshotError;
+
+pub const DEFAULT_SEED: u64 = 0;
+const INTERPRETER_SIZE: usize = 0x200;
+const CODE_PAGE: usize = 0x100;
+
+// Source of the random bytes returned by Cxkk. Its state is part of the VM's save states, so it
+// must be able to export and import it.
+pub trait Random {
+    fn next_byte(&mut self) -> u8;
+
+    fn state(&self) -> Vec<u8>;
+
+    // Restores a state returned by `state`, leaving the source untouched on error.
+    fn set_state(&mut self, state: &[u8]) -> Result<(), SnapshotError>;
+}
+
+// The default source: SplitMix64, which gives a good sequence for any seed, zero included.
+pub struct SplitMix64 {
+    state: u64,
+}
+
+impl SplitMix64 {
+    pub fn new(seed: u64) -> Self {
+        SplitMix64 { state: seed }
+    }
+}
+
+impl Random for SplitMix64 {
+    fn next_byte(&mut self) -> u8 {
+        self.state = self.state.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        (z ^ (z >> 31)) as u8
+    }
+
+    fn state(&self) -> Vec<u8> {
+        self.state.to_le_bytes().to_vec()
+    }
+
+    fn set_state(&mut self, state: &[u8]) -> Result<(), SnapshotError> {
+        let mut b = [0; 8];
+        if state.len() != b.len() {
+            return Err(SnapshotError::Corrupt);
+        }
+        b.copy_from_slice(state);
+        self.state = u64::from_le_bytes(b);
+        Ok(())
+    }
+}
+
+// The random algorithm of the original COSMAC VIP interpreter. It keeps a 16 bit counter in
+// register R9, which every Cxkk bumps; the counter's high byte is then added to the interpreter
+// byte at 0x01nn, `nn` being the counter's low byte, and the sum becomes the new high byte and the
+// random number. The results depend on the interpreter's own code, so an image of it is needed,
+// and its state carries that page along with R9 to restore without one.
+pub struct CosmacVip {
+    code_page: [u8; 256],
+    r9: u16,
+}
+
+impl CosmacVip {
+    // `interpreter` is the 512 byte CHIP-8 interpreter, as loaded at 0x000 on the VIP. `seed`
+    // stands for the value R9 happened to hold at power on.
+    pub fn new(interpreter: &[u8], seed: u16) -> Option<Self> {
+        if interpreter.len() != INTERPRETER_SIZE {
+            return None;
+        }
+        let mut code_page = [0; 256];
+        code_page.copy_from_slice(&interpreter[CODE_PAGE..]);
+        Some(CosmacVip { code_page, r9: seed })
+    }
+}
+
+impl Random for CosmacVip {
+    fn next_byte(&mut self) -> u8 {
+        self.r9 = self.r9.wrapping_add(1);
+        let [low, high] = self.r9.to_le_bytes();
+        let byte = self.code_page[low as usize].wrapping_add(high);
+        self.r9 = u16::from_le_bytes([low, byte]);
+        byte
+    }
+
+    fn state(&self) -> Vec<u8> {
+        let mut state = self.r9.to_le_bytes().to_vec();
+        state.extend_from_slice(&self.code_page);
+        state
+    }
+
+    fn set_state(&mut self, state: &[u8]) -> Result<(), SnapshotError> {
+        if state.len() != 2 + self.code_page.len() {
+            return Err(SnapshotError::Corrupt);
+        }
+        self.r9 = u16::from_le_bytes([state[0], state[1]]);
+        self.code_page.copy_from_slice(&state[2..]);
+        Ok(())
+    }
+}
+
+// A source in the `state` of one of the sources above, telling them apart by its size, for save
+// states taken with another source than the VM restoring them has.
+pub fn from_state(state: &[u8]) -> Result<Box<dyn Random>, SnapshotError> {
+    let mut split_mix = SplitMix64::new(DEFAULT_SEED);
+    if split_mix.set_state(state).is_ok() {
+        return Ok(Box::new(split_mix));
+    }
+    let mut vip = CosmacVip { code_page: [0; 256], r9: 0 };
+    vip.set_state(state)?;
+    Ok(Box::new(vip))
+}
+
+#[cfg(test)]
+#[path = "./random_test.rs"]
+mod random_test;
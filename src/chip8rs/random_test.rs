@@ -0,0 +1,49 @@
+use super::*;
+
+
+fn bytes(random: &mut dyn Random, n: usize) -> Vec<u8> {
+    (0..n).map(|_| random.next_byte()).collect()
+}
+
+#[test]
+fn test_split_mix_seeded() {
+    let a = bytes(&mut SplitMix64::new(42), 16);
+    assert_eq!(a, bytes(&mut SplitMix64::new(42), 16));
+    assert_ne!(a, bytes(&mut SplitMix64::new(43), 16));
+    assert_ne!(bytes(&mut SplitMix64::new(0), 16), [0; 16]);
+}
+
+#[test]
+fn test_state_round_trip() {
+    let mut random = SplitMix64::new(7);
+    bytes(&mut random, 5);
+    let mut copy = SplitMix64::new(0);
+    copy.set_state(&random.state()).unwrap();
+    assert_eq!(bytes(&mut copy, 8), bytes(&mut random, 8));
+    assert!(copy.set_state(&[1, 2, 3]).is_err());
+}
+
+#[test]
+fn test_cosmac_vip() {
+    let mut interpreter = vec![0; 0x200];
+    interpreter[0x101] = 0x10;
+    interpreter[0x102] = 0x01;
+    let mut random = CosmacVip::new(&interpreter, 0).unwrap();
+    assert_eq!(bytes(&mut random, 2), [0x10, 0x11]);
+    assert_eq!(random.state()[..3], [0x02, 0x11, 0x00]);
+    assert!(CosmacVip::new(&interpreter[..0x100], 0).is_none());
+}
+
+#[test]
+fn test_from_state() {
+    let mut interpreter = vec![0; 0x200];
+    interpreter[0x1A0] = 0x5C;
+    let mut vip = CosmacVip::new(&interpreter, 0x9F).unwrap();
+    let mut copy = from_state(&vip.state()).unwrap();
+    assert_eq!(bytes(copy.as_mut(), 300), bytes(&mut vip, 300));
+
+    let mut split_mix = SplitMix64::new(7);
+    let mut copy = from_state(&split_mix.state()).unwrap();
+    assert_eq!(bytes(copy.as_mut(), 8), bytes(&mut split_mix, 8));
+    assert!(from_state(&[1, 2, 3]).is_err());
+}
@@ -0,0 +1,125 @@
+use std::collections::VecDeque;
+use super::scheduler::FRAMES_PER_SECOND;
+use super::snapshot::{Snapshot, SnapshotError, StateReader, StateWriter};
+
+pub const DEFAULT_LENGTH: usize = 30 * FRAMES_PER_SECOND as usize;  // captures, one per frame
+pub const DEFAULT_BUDGET: usize = 16 * 1024 * 1024;  // bytes
+
+// Ring buffer of recent VM states for stepping backwards in time.
+//
+// Only the newest capture is kept whole. Every older capture is stored as the difference from the
+// one after it, which is small since most of memory doesn't change between frames.
+pub struct Rewind {
+    length: usize,
+    budget: usize,
+    newest: Option<Snapshot>,
+    deltas: VecDeque<Vec<u8>>,  // oldest first
+    delta_bytes: usize,
+}
+
+impl Rewind {
+    // Keeps at most `length` captures, dropping the oldest ones sooner when they would take more
+    // than `budget` bytes.
+    pub fn new(length: usize, budget: usize) -> Self {
+        Rewind {
+            length,
+            budget,
+            newest: None,
+            deltas: VecDeque::new(),
+            delta_bytes: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.deltas.len() + self.newest.is_some() as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.newest.is_none()
+    }
+
+    // Bytes used by the captures.
+    pub fn size(&self) -> usize {
+        self.delta_bytes + self.newest.as_ref().map_or(0, |s| s.state.len())
+    }
+
+    pub fn push(&mut self, snapshot: Snapshot) {
+        if let Some(previous) = self.newest.take() {
+            let delta = diff(&snapshot.state, &previous.state);
+            self.delta_bytes += delta.len();
+            self.deltas.push_back(delta);
+        }
+        self.newest = Some(snapshot);
+
+        while self.len() > self.length.max(1) || (self.size() > self.budget && !self.deltas.is_empty()) {
+            if let Some(delta) = self.deltas.pop_front() {
+                self.delta_bytes -= delta.len();
+            } else {
+                break;
+            }
+        }
+    }
+
+    // Takes the newest capture out of the buffer.
+    pub fn pop(&mut self) -> Option<Snapshot> {
+        let newest = self.newest.take()?;
+        if let Some(delta) = self.deltas.pop_back() {
+            self.delta_bytes -= delta.len();
+            // Deltas are only ever made by `diff`, so they always apply
+            let state = patch(&newest.state, &delta).expect("corrupt rewind delta");
+            self.newest = Some(Snapshot {
+                rom_hash: newest.rom_hash,
+                mode: newest.mode,
+                quirks: newest.quirks,
+                state,
+            });
+        }
+        Some(newest)
+    }
+
+    pub fn clear(&mut self) {
+        self.newest = None;
+        self.deltas.clear();
+        self.delta_bytes = 0;
+    }
+}
+
+// Encodes how to turn `from` into `to`: the length of `to`, then each run of differing bytes as an
+// offset, a length and the new bytes.
+pub fn diff(from: &[u8], to: &[u8]) -> Vec<u8> {
+    let mut w = StateWriter::new();
+    w.u32(to.len() as u32);
+    let mut n = 0;
+    while n < to.len() {
+        if from.get(n) == Some(&to[n]) {
+            n += 1;
+            continue;
+        }
+        let start = n;
+        while n < to.len() && from.get(n) != Some(&to[n]) {
+            n += 1;
+        }
+        w.u32(start as u32);
+        w.bytes(&to[start..n]);
+    }
+    w.finish()
+}
+
+// Applies a delta made by `diff` to `from`.
+pub fn patch(from: &[u8], delta: &[u8]) -> Result<Vec<u8>, SnapshotError> {
+    let mut r = StateReader::new(delta);
+    let mut to = from.to_vec();
+    to.resize(r.u32()? as usize, 0);
+    while !r.is_empty() {
+        let start = r.u32()? as usize;
+        let run = r.bytes()?;
+        to.get_mut(start..start + run.len())
+            .ok_or(SnapshotError::Corrupt)?
+            .copy_from_slice(run);
+    }
+    Ok(to)
+}
+
+#[cfg(test)]
+#[path = "./rewind_test.rs"]
+mod rewind_test;
@@ -0,0 +1,62 @@
+use super::*;
+use super::super::mode::Mode;
+use super::super::quirks::Quirks;
+
+
+fn capture(state: &[u8]) -> Snapshot {
+    Snapshot {
+        rom_hash: 1,
+        mode: Mode::Chip8,
+        quirks: Quirks::default(),
+        state: state.to_vec(),
+    }
+}
+
+#[test]
+fn test_diff_patch() {
+    let cases: &[(&[u8], &[u8])] = &[
+        (b"", b""),
+        (b"abcdef", b"abcdef"),
+        (b"abcdef", b"aXcdYZ"),
+        (b"abc", b"abcdef"),
+        (b"abcdef", b"aXc"),
+    ];
+    for &(from, to) in cases {
+        assert_eq!(patch(from, &diff(from, to)).unwrap(), to);
+    }
+    assert_eq!(diff(b"abcdef", b"abcdef").len(), 4);
+}
+
+#[test]
+fn test_pop_in_reverse() {
+    let mut rewind = Rewind::new(DEFAULT_LENGTH, DEFAULT_BUDGET);
+    for frame in 0..10u8 {
+        rewind.push(capture(&[0, 0, frame, 0]));
+    }
+    for frame in (0..10u8).rev() {
+        assert_eq!(rewind.pop().unwrap().state, [0, 0, frame, 0]);
+    }
+    assert!(rewind.pop().is_none());
+    assert_eq!(rewind.size(), 0);
+}
+
+#[test]
+fn test_length_limit() {
+    let mut rewind = Rewind::new(3, DEFAULT_BUDGET);
+    for frame in 0..10u8 {
+        rewind.push(capture(&[frame]));
+    }
+    assert_eq!(rewind.len(), 3);
+    let frames: Vec<u8> = std::iter::from_fn(|| rewind.pop()).map(|s| s.state[0]).collect();
+    assert_eq!(frames, [9, 8, 7]);
+}
+
+#[test]
+fn test_budget_limit() {
+    let mut rewind = Rewind::new(DEFAULT_LENGTH, 1024);
+    for frame in 0..100u8 {
+        rewind.push(capture(&[frame; 64]));
+    }
+    assert!(rewind.size() <= 1024);
+    assert_eq!(rewind.pop().unwrap().state, [99; 64]);
+}
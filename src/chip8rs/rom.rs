@@ -0,0 +1,161 @@
+use std::error;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Cursor, Read};
+use std::path::{Path, PathBuf};
+use flate2::read::GzDecoder;
+use zip::ZipArchive;
+use super::mode::Mode;
+
+// Nothing bigger fits in any mode, so reading stops there. This also bounds what a compressed file
+// can expand to.
+const READ_LIMIT: usize = 0x1000000;
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+const ZIP_MAGIC: &[u8] = b"PK\x03\x04";
+const ROM_EXTENSIONS: &[&str] = &["ch8", "c8", "sc8", "xo8", "mc8"];
+
+#[derive(Debug)]
+pub enum RomError {
+    NotFound,
+    Io(io::Error),
+    // The ROM doesn't fit in memory above 0x200
+    TooLarge { size: usize, max: usize },
+    // A damaged archive, or one without a ROM in it
+    Archive(String),
+}
+
+impl fmt::Display for RomError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RomError::NotFound => write!(f, "no such file"),
+            RomError::Io(err) => write!(f, "can't read ROM: {}", err),
+            RomError::TooLarge { size, max } => write!(f, "ROM is {} bytes, but only {} fit in memory", size, max),
+            RomError::Archive(err) => write!(f, "can't extract ROM: {}", err),
+        }
+    }
+}
+
+impl error::Error for RomError {}
+
+impl From<io::Error> for RomError {
+    fn from(err: io::Error) -> Self {
+        match err.kind() {
+            io::ErrorKind::NotFound => RomError::NotFound,
+            _ => RomError::Io(err),
+        }
+    }
+}
+
+// A ROM image, read from a file, stdin or an archive.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Rom {
+    pub bytes: Vec<u8>,
+    pub path: PathBuf,  // where it was read from, "-" for stdin
+    pub name: PathBuf,  // the ROM's own file name, inside the archive if any
+    pub sha1: [u8; 20],  // for looking the ROM up, save states use `VM::rom_hash` instead
+    pub size: usize,
+}
+
+impl Rom {
+    // Reads the ROM at `path`, or stdin for "-". Gzip and zip files are detected by their contents
+    // and extracted; a zip must hold exactly one ROM, recognised by its extension if it holds other
+    // files too.
+    pub fn load(path: &Path) -> Result<Rom, RomError> {
+        let data = if path == Path::new("-") {
+            read_limited(io::stdin().lock())?
+        } else {
+            read_limited(File::open(path)?)?
+        };
+        Rom::from_bytes(data, path)
+    }
+
+    pub fn from_bytes(data: Vec<u8>, path: &Path) -> Result<Rom, RomError> {
+        let (bytes, name) = if data.starts_with(GZIP_MAGIC) {
+            let bytes = extract(GzDecoder::new(data.as_slice()))?;
+            // "pong.sc8.gz" holds "pong.sc8"
+            let name = if path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("gz")) {
+                path.with_extension("")
+            } else {
+                path.to_path_buf()
+            };
+            (bytes, name)
+        } else if data.starts_with(ZIP_MAGIC) {
+            unzip(data)?
+        } else {
+            (data, path.to_path_buf())
+        };
+
+        let mut hasher = sha1_smol::Sha1::new();
+        hasher.update(&bytes);
+        Ok(Rom {
+            size: bytes.len(),
+            sha1: hasher.digest().bytes(),
+            bytes,
+            path: path.to_path_buf(),
+            name,
+        })
+    }
+
+    // The mode to run the ROM in, from its extension.
+    pub fn mode(&self) -> Mode {
+        Mode::from_path(&self.name)
+    }
+
+    pub fn check_size(&self, mode: Mode) -> Result<(), RomError> {
+        if self.size > mode.max_rom_size() {
+            return Err(RomError::TooLarge { size: self.size, max: mode.max_rom_size() });
+        }
+        Ok(())
+    }
+
+    pub fn sha1_hex(&self) -> String {
+        self.sha1.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+}
+
+// Reads everything, failing once past READ_LIMIT.
+fn read_limited<R: Read>(reader: R) -> Result<Vec<u8>, RomError> {
+    let mut data = Vec::new();
+    reader.take(READ_LIMIT as u64 + 1).read_to_end(&mut data)?;
+    if data.len() > READ_LIMIT {
+        return Err(RomError::TooLarge { size: data.len(), max: READ_LIMIT });
+    }
+    Ok(data)
+}
+
+// Like `read_limited`, for a reader decompressing data already in memory. Its errors all come from
+// compressed data which is corrupt or cut short, so they make a damaged archive rather than an I/O
+// error: flate2 reports them as InvalidData, InvalidInput or UnexpectedEof.
+fn extract<R: Read>(reader: R) -> Result<Vec<u8>, RomError> {
+    read_limited(reader).map_err(|err| match err {
+        RomError::Io(err) => RomError::Archive(err.to_string()),
+        err => err,
+    })
+}
+
+fn unzip(data: Vec<u8>) -> Result<(Vec<u8>, PathBuf), RomError> {
+    let archive_error = |err: zip::result::ZipError| RomError::Archive(err.to_string());
+    let mut archive = ZipArchive::new(Cursor::new(data)).map_err(archive_error)?;
+
+    let files: Vec<String> = archive.file_names().filter(|name| !name.ends_with('/')).map(String::from).collect();
+    let is_rom = |name: &&String| {
+        Path::new(name.as_str())
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .is_some_and(|ext| ROM_EXTENSIONS.iter().any(|rom| ext.eq_ignore_ascii_case(rom)))
+    };
+    let roms: Vec<&String> = files.iter().filter(is_rom).collect();
+    let name = match (roms.as_slice(), files.as_slice()) {
+        ([name], _) => (*name).clone(),
+        ([], [name]) => name.clone(),
+        ([], _) => return Err(RomError::Archive("no ROM in archive".to_string())),
+        _ => return Err(RomError::Archive(format!("several ROMs in archive: {}", roms.iter().map(|s| s.as_str()).collect::<Vec<_>>().join(", ")))),
+    };
+
+    let file = archive.by_name(&name).map_err(archive_error)?;
+    Ok((extract(file)?, PathBuf::from(name)))
+}
+
+#[cfg(test)]
+#[path = "./rom_test.rs"]
+mod rom_test;
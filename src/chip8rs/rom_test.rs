@@ -0,0 +1,65 @@
+use super::*;
+use std::io::Write;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use zip::write::{FileOptions, ZipWriter};
+
+
+fn zip(files: &[(&str, &[u8])]) -> Vec<u8> {
+    let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+    for &(name, data) in files {
+        writer.start_file(name, FileOptions::default()).unwrap();
+        writer.write_all(data).unwrap();
+    }
+    writer.finish().unwrap().into_inner()
+}
+
+#[test]
+fn test_plain() {
+    let rom = Rom::from_bytes(b"abc".to_vec(), Path::new("games/abc.sc8")).unwrap();
+    assert_eq!(rom.bytes, b"abc");
+    assert_eq!(rom.size, 3);
+    assert_eq!(rom.sha1_hex(), "a9993e364706816aba3e25717850c26c9cd0d89d");
+    assert_eq!(rom.mode(), Mode::SuperChip);
+}
+
+#[test]
+fn test_too_large() {
+    let rom = Rom::from_bytes(vec![0; 4000], Path::new("big.ch8")).unwrap();
+    assert!(matches!(rom.check_size(Mode::Chip8), Err(RomError::TooLarge { size: 4000, max: 3584 })));
+    assert!(rom.check_size(Mode::XoChip).is_ok());
+}
+
+#[test]
+fn test_gzip() {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(b"\x12\x00").unwrap();
+    let rom = Rom::from_bytes(encoder.finish().unwrap(), Path::new("loop.xo8.gz")).unwrap();
+    assert_eq!(rom.bytes, b"\x12\x00");
+    assert_eq!(rom.name, Path::new("loop.xo8"));
+    assert_eq!(rom.mode(), Mode::XoChip);
+}
+
+#[test]
+fn test_corrupt_gzip() {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(&[0x12; 100]).unwrap();
+    let data = encoder.finish().unwrap();
+    let truncated = data[..data.len() / 2].to_vec();
+    assert!(matches!(Rom::from_bytes(truncated, Path::new("loop.ch8.gz")), Err(RomError::Archive(_))));
+
+    let mut corrupt = data;
+    corrupt[10..14].copy_from_slice(&[0xff; 4]);
+    assert!(matches!(Rom::from_bytes(corrupt, Path::new("loop.ch8.gz")), Err(RomError::Archive(_))));
+}
+
+#[test]
+fn test_zip() {
+    let data = zip(&[("readme.txt", b"hi"), ("roms/loop.sc8", b"\x12\x00")]);
+    let rom = Rom::from_bytes(data, Path::new("loop.zip")).unwrap();
+    assert_eq!(rom.bytes, b"\x12\x00");
+    assert_eq!(rom.name, Path::new("roms/loop.sc8"));
+
+    let data = zip(&[("a.ch8", b"a"), ("b.ch8", b"b")]);
+    assert!(matches!(Rom::from_bytes(data, Path::new("two.zip")), Err(RomError::Archive(_))));
+}
@@ -0,0 +1,48 @@
+use std::fs;
+use std::path::Path;
+use serde::Deserialize;
+use super::quirks::Quirks;
+
+const ROMS_JSON: &str = "roms.json";
+
+#[derive(Deserialize)]
+struct RomEntry {
+    file: String,
+    quirks: Option<RomQuirks>,
+}
+
+// Tags which are left out keep the quirk from `base`.
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "camelCase")]
+struct RomQuirks {
+    shift: Option<bool>,
+    load_store: Option<bool>,
+}
+
+// Looks the ROM up in the `roms.json` sitting next to it, and applies the quirks it is tagged
+// with on top of `base`. ROMs which are not listed or not tagged keep `base` unchanged.
+pub fn quirks_for(rom_path: &Path, base: Quirks) -> Quirks {
+    let entry = rom_path.parent()
+        .and_then(|dir| fs::read_to_string(dir.join(ROMS_JSON)).ok())
+        .and_then(|json| serde_json::from_str::<Vec<RomEntry>>(&json).ok())
+        .and_then(|entries| {
+            let name = rom_path.file_name()?.to_str()?;
+            entries.into_iter().find(|e| e.file == name)
+        });
+
+    match entry {
+        Some(entry) => {
+            let tags = entry.quirks.unwrap_or_default();
+            Quirks {
+                shift: tags.shift.unwrap_or(base.shift),
+                load_store: tags.load_store.unwrap_or(base.load_store),
+                ..base
+            }
+        }
+        None => base,
+    }
+}
+
+#[cfg(test)]
+#[path = "./roms_test.rs"]
+mod roms_test;
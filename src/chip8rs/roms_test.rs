@@ -0,0 +1,22 @@
+use super::*;
+use std::env;
+
+
+const ROMS: &str = r#"[
+    { "file": "tagged.ch8", "quirks": { "shift": false } },
+    { "file": "untagged.ch8" }
+]"#;
+
+#[test]
+fn test_quirks_for() {
+    let dir = env::temp_dir().join(format!("chip8rs-roms-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join(ROMS_JSON), ROMS).unwrap();
+    let base = Quirks::default();
+
+    let tagged = quirks_for(&dir.join("tagged.ch8"), base);
+    assert_eq!(tagged, Quirks { shift: false, ..base });
+    assert_eq!(quirks_for(&dir.join("untagged.ch8"), base), base);
+    assert_eq!(quirks_for(&dir.join("unlisted.ch8"), Quirks::COSMAC_VIP), Quirks::COSMAC_VIP);
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,64 @@
+use std::thread;
+use std::time::{Duration, Instant};
+use super::frontend::Clock;
+
+pub const FRAMES_PER_SECOND: u32 = 60;
+pub const DEFAULT_IPS: u32 = 500;
+
+// Paces emulation in 60 Hz frames, and spreads the configured instructions per second over them.
+pub struct Scheduler {
+    ips: u32,
+    frame: u64,
+    next_frame: Instant,
+}
+
+impl Scheduler {
+    pub fn new(ips: u32) -> Self {
+        Scheduler {
+            ips,
+            frame: 0,
+            next_frame: Instant::now(),
+        }
+    }
+
+    // Number of instructions to run in the current frame. Rates which aren't a multiple of 60 are
+    // spread so that every second still runs exactly `ips` instructions.
+    pub fn instructions_this_frame(&self) -> u32 {
+        let ips = self.ips as u64;
+        let fps = FRAMES_PER_SECOND as u64;
+        let n = self.frame % fps;
+        ((n + 1) * ips / fps - n * ips / fps) as u32
+    }
+
+    // Sleeps until the next frame is due, then moves on to it. If the host fell behind by more
+    // than a frame the schedule is reset instead of running a burst of frames to catch up.
+    pub fn wait_next_frame(&mut self) {
+        self.next_frame += Duration::from_secs(1) / FRAMES_PER_SECOND;
+        let now = Instant::now();
+        if self.next_frame > now {
+            thread::sleep(self.next_frame - now);
+        } else {
+            self.next_frame = now;
+        }
+        self.advance();
+    }
+
+    // Moves on to the next frame without waiting, for running faster than real time.
+    pub fn advance(&mut self) {
+        self.frame += 1;
+    }
+}
+
+impl Clock for Scheduler {
+    fn instructions_this_frame(&self) -> u32 {
+        Scheduler::instructions_this_frame(self)
+    }
+
+    fn wait_next_frame(&mut self) {
+        Scheduler::wait_next_frame(self)
+    }
+}
+
+#[cfg(test)]
+#[path = "./scheduler_test.rs"]
+mod scheduler_test;
@@ -0,0 +1,15 @@
+use super::*;
+
+
+#[test]
+fn test_instructions_per_second() {
+    for &ips in &[60, 500, 700, 1000, 59] {
+        let mut scheduler = Scheduler::new(ips);
+        let mut total = 0;
+        for _ in 0..FRAMES_PER_SECOND {
+            total += scheduler.instructions_this_frame();
+            scheduler.frame += 1;
+        }
+        assert_eq!(total, ips);
+    }
+}
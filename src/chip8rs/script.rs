@@ -0,0 +1,100 @@
+use std::error;
+use std::fmt;
+
+// Scripted keypad input for unattended runs, one event per line:
+//
+//     # start the game
+//     frame 120: press 5 for 10 frames
+//     frame 300: press 4 6
+//
+// Keys are hex digits; without "for", keys are held for one frame. Frames count from 0.
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct Script {
+    presses: Vec<Press>,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+struct Press {
+    frame: u64,
+    frames: u64,
+    keys: Vec<usize>,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct ScriptError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ScriptError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl error::Error for ScriptError {}
+
+impl Script {
+    pub fn parse(text: &str) -> Result<Script, ScriptError> {
+        let mut presses = Vec::new();
+        for (n, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let press = parse_line(line).map_err(|message| ScriptError { line: n + 1, message })?;
+            presses.push(press);
+        }
+        Ok(Script { presses })
+    }
+
+    // The keypad state during `frame`.
+    pub fn keypad(&self, frame: u64) -> [bool; 16] {
+        let mut keypad = [false; 16];
+        for press in &self.presses {
+            if frame >= press.frame && frame - press.frame < press.frames {
+                press.keys.iter().for_each(|&key| keypad[key] = true);
+            }
+        }
+        keypad
+    }
+}
+
+// "frame <n>: press <keys> [for <n> frame[s]]"
+fn parse_line(line: &str) -> Result<Press, String> {
+    let syntax = || format!("expected \"frame <n>: press <keys> [for <n> frames]\", got {:?}", line);
+    let number = |word: &str| word.parse::<u64>().map_err(|_| format!("invalid number {:?}", word));
+
+    let (head, tail) = line.split_once(':').ok_or_else(syntax)?;
+    let frame = match head.split_whitespace().collect::<Vec<_>>().as_slice() {
+        ["frame", n] => number(n)?,
+        _ => return Err(syntax()),
+    };
+    let words: Vec<&str> = tail.split(|c: char| c.is_whitespace() || c == ',').filter(|w| !w.is_empty()).collect();
+    if words.first() != Some(&"press") {
+        return Err(syntax());
+    }
+    let (keys, frames) = match words.iter().position(|&w| w == "for") {
+        Some(n) => match &words[n + 1..] {
+            [count] | [count, "frame"] | [count, "frames"] => (&words[1..n], number(count)?),
+            _ => return Err(syntax()),
+        },
+        None => (&words[1..], 1),
+    };
+    if keys.is_empty() {
+        return Err(syntax());
+    }
+    let keys = keys
+        .iter()
+        .map(|key| match usize::from_str_radix(key, 16) {
+            Ok(k) if k < 16 => Ok(k),
+            _ => Err(format!("invalid key {:?}, expected 0-F", key)),
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(Press { frame, frames, keys })
+}
+
+#[cfg(test)]
+#[path = "./script_test.rs"]
+mod script_test;
@@ -0,0 +1,23 @@
+use super::*;
+
+
+#[test]
+fn test_parse() {
+    let script = Script::parse("# title screen\nframe 120: press 5 for 10 frames\n\nframe 125: press a, F\n").unwrap();
+    assert_eq!(script.keypad(119), [false; 16]);
+    let keypad = script.keypad(125);
+    assert!(keypad[5] && keypad[0xa] && keypad[0xf]);
+    assert!(script.keypad(126)[5] && !script.keypad(126)[0xa]);
+    assert!(script.keypad(129)[5]);
+    assert!(!script.keypad(130)[5]);
+}
+
+#[test]
+fn test_errors() {
+    assert_eq!(Script::parse("frame 1: press 5\nframe x: press 5").unwrap_err().line, 2);
+    assert!(Script::parse("frame 1: press 10").is_err());
+    assert!(Script::parse("frame 1: press").is_err());
+    assert!(Script::parse("frame 1: hold 5").is_err());
+    assert!(Script::parse("frame 1: press 5 for").is_err());
+    assert!(Script::parse("press 5").is_err());
+}
@@ -0,0 +1,231 @@
+use std::error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use super::hash::crc32;
+use super::mode::Mode;
+use super::quirks::Quirks;
+
+const MAGIC: &[u8; 4] = b"C8SS";
+const VERSION: u16 = 2;
+const HEADER_SIZE: usize = 4 + 2 + 4;
+const CHECKSUM_SIZE: usize = 4;
+
+#[derive(Debug)]
+pub enum SnapshotError {
+    Io(io::Error),
+    BadMagic,
+    UnsupportedVersion(u16),
+    BadChecksum,
+    // The state ended early or holds values the VM can't take
+    Corrupt,
+    RomMismatch,
+    ModeMismatch,
+    QuirksMismatch,
+}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SnapshotError::Io(err) => write!(f, "{}", err),
+            SnapshotError::BadMagic => write!(f, "not a chip8rs save state"),
+            SnapshotError::UnsupportedVersion(v) => write!(f, "unsupported save state version {}", v),
+            SnapshotError::BadChecksum => write!(f, "save state checksum mismatch"),
+            SnapshotError::Corrupt => write!(f, "save state is corrupt"),
+            SnapshotError::RomMismatch => write!(f, "save state was taken from a different ROM"),
+            SnapshotError::ModeMismatch => write!(f, "save state was taken in a different mode"),
+            SnapshotError::QuirksMismatch => write!(f, "save state was taken with different quirks"),
+        }
+    }
+}
+
+impl error::Error for SnapshotError {}
+
+impl From<io::Error> for SnapshotError {
+    fn from(err: io::Error) -> Self {
+        SnapshotError::Io(err)
+    }
+}
+
+// A captured VM state, see `VM::snapshot` and `VM::restore`.
+//
+// Files start with the "C8SS" magic, a format version and the payload length, and end with the
+// CRC-32 of the payload. All integers are little endian.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Snapshot {
+    pub rom_hash: u64,
+    pub mode: Mode,
+    pub quirks: Quirks,
+    pub state: Vec<u8>,  // the VM's registers and memory, encoded by `VM::snapshot`
+}
+
+impl Snapshot {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut payload = StateWriter::new();
+        payload.u64(self.rom_hash);
+        payload.u8(self.mode.id());
+        payload.u8(self.quirks.bits());
+        payload.bytes(&self.state);
+        let payload = payload.finish();
+
+        let mut out = StateWriter::new();
+        out.raw(MAGIC);
+        out.u16(VERSION);
+        out.u32(payload.len() as u32);
+        out.raw(&payload);
+        out.u32(crc32(&payload));
+        out.finish()
+    }
+
+    pub fn from_bytes(data: &[u8]) -> Result<Snapshot, SnapshotError> {
+        if data.len() < HEADER_SIZE + CHECKSUM_SIZE || &data[..4] != MAGIC {
+            return Err(SnapshotError::BadMagic);
+        }
+        let mut header = StateReader::new(&data[4..HEADER_SIZE]);
+        let version = header.u16()?;
+        if version != VERSION {
+            return Err(SnapshotError::UnsupportedVersion(version));
+        }
+        let len = header.u32()? as usize;
+        if data.len() != HEADER_SIZE + len + CHECKSUM_SIZE {
+            return Err(SnapshotError::Corrupt);
+        }
+        let payload = &data[HEADER_SIZE..HEADER_SIZE + len];
+        let mut checksum = StateReader::new(&data[HEADER_SIZE + len..]);
+        if checksum.u32()? != crc32(payload) {
+            return Err(SnapshotError::BadChecksum);
+        }
+
+        let mut r = StateReader::new(payload);
+        Ok(Snapshot {
+            rom_hash: r.u64()?,
+            mode: Mode::from_id(r.u8()?).ok_or(SnapshotError::Corrupt)?,
+            quirks: Quirks::from_bits(r.u8()?),
+            state: r.bytes()?.to_vec(),
+        })
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), SnapshotError> {
+        fs::write(path, self.to_bytes())?;
+        Ok(())
+    }
+
+    pub fn load(path: &Path) -> Result<Snapshot, SnapshotError> {
+        Snapshot::from_bytes(&fs::read(path)?)
+    }
+}
+
+// Save slot `n` of a ROM lives next to it, e.g. "games/Pong.ch8.state1".
+pub fn slot_path(rom_path: &Path, slot: u8) -> PathBuf {
+    let name = rom_path.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+    rom_path.with_file_name(format!("{}.state{}", name, slot))
+}
+
+pub struct StateWriter {
+    buf: Vec<u8>,
+}
+
+impl Default for StateWriter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl StateWriter {
+    pub fn new() -> Self {
+        StateWriter { buf: Vec::new() }
+    }
+
+    pub fn finish(self) -> Vec<u8> {
+        self.buf
+    }
+
+    pub fn u8(&mut self, v: u8) {
+        self.buf.push(v);
+    }
+
+    pub fn bool(&mut self, v: bool) {
+        self.u8(v as u8);
+    }
+
+    pub fn u16(&mut self, v: u16) {
+        self.raw(&v.to_le_bytes());
+    }
+
+    pub fn u32(&mut self, v: u32) {
+        self.raw(&v.to_le_bytes());
+    }
+
+    pub fn u64(&mut self, v: u64) {
+        self.raw(&v.to_le_bytes());
+    }
+
+    pub fn raw(&mut self, data: &[u8]) {
+        self.buf.extend_from_slice(data);
+    }
+
+    // Length prefixed bytes.
+    pub fn bytes(&mut self, data: &[u8]) {
+        self.u32(data.len() as u32);
+        self.raw(data);
+    }
+}
+
+pub struct StateReader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> StateReader<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        StateReader { data }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    pub fn raw(&mut self, len: usize) -> Result<&'a [u8], SnapshotError> {
+        if self.data.len() < len {
+            return Err(SnapshotError::Corrupt);
+        }
+        let (head, tail) = self.data.split_at(len);
+        self.data = tail;
+        Ok(head)
+    }
+
+    pub fn u8(&mut self) -> Result<u8, SnapshotError> {
+        Ok(self.raw(1)?[0])
+    }
+
+    pub fn bool(&mut self) -> Result<bool, SnapshotError> {
+        Ok(self.u8()? != 0)
+    }
+
+    pub fn u16(&mut self) -> Result<u16, SnapshotError> {
+        let mut b = [0; 2];
+        b.copy_from_slice(self.raw(2)?);
+        Ok(u16::from_le_bytes(b))
+    }
+
+    pub fn u32(&mut self) -> Result<u32, SnapshotError> {
+        let mut b = [0; 4];
+        b.copy_from_slice(self.raw(4)?);
+        Ok(u32::from_le_bytes(b))
+    }
+
+    pub fn u64(&mut self) -> Result<u64, SnapshotError> {
+        let mut b = [0; 8];
+        b.copy_from_slice(self.raw(8)?);
+        Ok(u64::from_le_bytes(b))
+    }
+
+    pub fn bytes(&mut self) -> Result<&'a [u8], SnapshotError> {
+        let len = self.u32()? as usize;
+        self.raw(len)
+    }
+}
+
+#[cfg(test)]
+#[path = "./snapshot_test.rs"]
+mod snapshot_test;
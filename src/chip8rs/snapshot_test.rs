@@ -0,0 +1,59 @@
+use super::*;
+use super::super::vm::VM;
+
+
+fn running_vm() -> VM {
+    let mut vm = VM::new();
+    // LD V0, 0x2A; LD I, 0x300; LD [I], V0; DRW V0, V0, 5; JP 0x208
+    vm.load(&[0x60, 0x2A, 0xA3, 0x00, 0xF0, 0x55, 0xD0, 0x05, 0x12, 0x08]);
+    vm.run_frame([false; 16], 4).unwrap();
+    vm
+}
+
+#[test]
+fn test_bytes_round_trip() {
+    let snapshot = running_vm().snapshot();
+    assert_eq!(Snapshot::from_bytes(&snapshot.to_bytes()).unwrap(), snapshot);
+}
+
+#[test]
+fn test_bad_file_rejected() {
+    let mut bytes = running_vm().snapshot().to_bytes();
+    assert!(matches!(Snapshot::from_bytes(b"not a state"), Err(SnapshotError::BadMagic)));
+
+    let last = bytes.len() - 1;
+    bytes[last] ^= 0xff;
+    assert!(matches!(Snapshot::from_bytes(&bytes), Err(SnapshotError::BadChecksum)));
+
+    bytes[4] = 0xff;
+    assert!(matches!(Snapshot::from_bytes(&bytes), Err(SnapshotError::UnsupportedVersion(_))));
+}
+
+#[test]
+fn test_restore() {
+    let mut vm = running_vm();
+    let snapshot = vm.snapshot();
+    vm.run_frame([true; 16], 10).unwrap();
+    assert_ne!(vm.snapshot(), snapshot);
+
+    vm.restore(&snapshot).unwrap();
+    assert_eq!(vm.snapshot(), snapshot);
+}
+
+#[test]
+fn test_restore_rejects_other_rom_and_quirks() {
+    let snapshot = running_vm().snapshot();
+
+    let mut vm = VM::new();
+    vm.load(&[0x12, 0x00]);
+    assert!(matches!(vm.restore(&snapshot), Err(SnapshotError::RomMismatch)));
+
+    let mut vm = VM::with_quirks(Quirks::COSMAC_VIP);
+    vm.load(&[0x60, 0x2A, 0xA3, 0x00, 0xF0, 0x55, 0xD0, 0x05, 0x12, 0x08]);
+    assert!(matches!(vm.restore(&snapshot), Err(SnapshotError::QuirksMismatch)));
+}
+
+#[test]
+fn test_slot_path() {
+    assert_eq!(slot_path(Path::new("games/Pong.ch8"), 3), Path::new("games/Pong.ch8.state3"));
+}
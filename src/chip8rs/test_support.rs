@@ -0,0 +1,59 @@
+// Fakes shared by the tests of the modules which drive a frontend.
+use std::mem;
+use super::audio::AudioPattern;
+use super::frontend::{self, Audio, Command, Display, Input};
+use super::megachip::{DigitalSound, MegaFrame};
+use super::vm::{SCHIP_HEIGHT, SCHIP_WIDTH};
+
+// Quits after `frames` polls, pressing `keys` the whole time.
+pub struct Frontend {
+    pub frames: u32,
+    pub keys: [bool; 16],
+    pub commands: Vec<Command>,
+    pub draws: u32,
+}
+
+impl Frontend {
+    pub fn new(frames: u32) -> Self {
+        Frontend { frames, keys: [false; 16], commands: Vec::new(), draws: 0 }
+    }
+}
+
+impl Display for Frontend {
+    fn draw(&mut self, _: &[[u8; SCHIP_WIDTH]; SCHIP_HEIGHT], _: usize, _: usize) {
+        self.draws += 1;
+    }
+
+    fn draw_mega(&mut self, _: &MegaFrame) {}
+}
+
+impl Input for Frontend {
+    fn poll(&mut self) -> Option<[bool; 16]> {
+        if self.frames == 0 {
+            return None;
+        }
+        self.frames -= 1;
+        Some(self.keys)
+    }
+
+    fn commands(&mut self) -> Vec<Command> {
+        mem::take(&mut self.commands)
+    }
+}
+
+impl Audio for Frontend {
+    fn play(&mut self, _: bool, _: Option<AudioPattern>) {}
+
+    fn play_sound(&mut self, _: Option<&DigitalSound>) {}
+}
+
+// Runs 10 instructions a frame, without waiting.
+pub struct Clock;
+
+impl frontend::Clock for Clock {
+    fn instructions_this_frame(&self) -> u32 {
+        10
+    }
+
+    fn wait_next_frame(&mut self) {}
+}
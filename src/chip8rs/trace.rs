@@ -0,0 +1,142 @@
+use std::io::{self, Write};
+use std::ops::RangeInclusive;
+use super::snapshot::StateWriter;
+use super::vm::Registers;
+
+const MAGIC: &[u8; 4] = b"C8TR";
+const VERSION: u16 = 1;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TraceFormat {
+    // One line per instruction
+    Text,
+    // The "C8TR" magic and a format version, then one fixed size little endian record per
+    // instruction: frame (u64), pc (u32), opcode (u16), V0-VF, I (u32), sp, delay and sound timers
+    Binary,
+}
+
+impl TraceFormat {
+    pub const NAMES: &'static [&'static str] = &["text", "binary"];
+
+    pub fn from_name(name: &str) -> Option<TraceFormat> {
+        match name {
+            "text" => Some(TraceFormat::Text),
+            "binary" => Some(TraceFormat::Binary),
+            _ => None,
+        }
+    }
+}
+
+// Which instructions are traced. Unset filters let everything through.
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct TraceFilter {
+    pub pc: Option<RangeInclusive<usize>>,
+    // Mnemonics such as "DRW" or "LD", matched against the first word of the instruction
+    pub kinds: Option<Vec<String>>,
+    pub frames: Option<RangeInclusive<u64>>,
+}
+
+// One executed instruction, with the state before it ran.
+pub struct TraceEntry<'a> {
+    pub opcode: u16,
+    pub mnemonic: &'a str,
+    pub registers: &'a Registers,
+}
+
+// Logs every instruction a VM executes, see `VM::set_tracer`. Frames are counted by
+// `VM::tick_timers`. Write errors stop the trace, and are reported by `finish`.
+pub struct Tracer {
+    out: Box<dyn Write>,
+    format: TraceFormat,
+    filter: TraceFilter,
+    frame: u64,
+    error: Option<io::Error>,
+}
+
+impl Tracer {
+    pub fn new(out: Box<dyn Write>, format: TraceFormat, filter: TraceFilter) -> Self {
+        let mut tracer = Tracer {
+            out,
+            format,
+            filter,
+            frame: 0,
+            error: None,
+        };
+        if format == TraceFormat::Binary {
+            let mut header = StateWriter::new();
+            header.raw(MAGIC);
+            header.u16(VERSION);
+            tracer.write(&header.finish());
+        }
+        tracer
+    }
+
+    pub fn frame(&self) -> u64 {
+        self.frame
+    }
+
+    pub fn next_frame(&mut self) {
+        self.frame += 1;
+    }
+
+    // Whether an instruction at `pc` would be traced in the current frame, before its mnemonic
+    // is known.
+    pub fn wants(&self, pc: usize) -> bool {
+        let filter = &self.filter;
+        self.error.is_none()
+            && filter.pc.as_ref().map_or(true, |range| range.contains(&pc))
+            && filter.frames.as_ref().map_or(true, |range| range.contains(&self.frame))
+    }
+
+    pub fn record(&mut self, entry: &TraceEntry) {
+        let kind = entry.mnemonic.split_whitespace().next().unwrap_or_default();
+        if let Some(ref kinds) = self.filter.kinds {
+            if !kinds.iter().any(|k| k.eq_ignore_ascii_case(kind)) {
+                return;
+            }
+        }
+        let r = entry.registers;
+        match self.format {
+            TraceFormat::Text => {
+                let v: String = r.v.iter().map(|v| format!("{:02X}", v)).collect();
+                let line = format!(
+                    "{} {:04X} {:04X} {:<20} V={} I={:04X} SP={:X} DT={:02X} ST={:02X}\n",
+                    self.frame, r.pc, entry.opcode, entry.mnemonic, v, r.i, r.sp, r.delay_timer, r.sound_timer
+                );
+                self.write(line.as_bytes());
+            }
+            TraceFormat::Binary => {
+                let mut w = StateWriter::new();
+                w.u64(self.frame);
+                w.u32(r.pc as u32);
+                w.u16(entry.opcode);
+                w.raw(&r.v);
+                w.u32(r.i);
+                w.u8(r.sp as u8);
+                w.u8(r.delay_timer);
+                w.u8(r.sound_timer);
+                self.write(&w.finish());
+            }
+        }
+    }
+
+    fn write(&mut self, data: &[u8]) {
+        if self.error.is_none() {
+            if let Err(err) = self.out.write_all(data) {
+                self.error = Some(err);
+            }
+        }
+    }
+
+    // Flushes the log, returning the first write error.
+    pub fn finish(mut self) -> io::Result<()> {
+        if let Some(err) = self.error.take() {
+            return Err(err);
+        }
+        self.out.flush()
+    }
+}
+
+#[cfg(test)]
+#[path = "./trace_test.rs"]
+mod trace_test;
@@ -0,0 +1,79 @@
+use super::*;
+use std::cell::RefCell;
+use std::rc::Rc;
+use super::super::vm::VM;
+
+
+// A log the test can read back after handing it to the tracer.
+#[derive(Clone, Default)]
+struct Log(Rc<RefCell<Vec<u8>>>);
+
+impl Write for Log {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(data);
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+// LD V0, 5; ADD V0, 1; LD I, 0x300; JP 0x202
+const ROM: &[u8] = &[0x60, 0x05, 0x70, 0x01, 0xA3, 0x00, 0x12, 0x02];
+
+// Runs `frames` frames of 3 instructions, tracing them, and returns the log.
+fn trace(format: TraceFormat, filter: TraceFilter, frames: usize) -> Vec<u8> {
+    let log = Log::default();
+    let mut vm = VM::new();
+    vm.load(ROM);
+    vm.set_tracer(Tracer::new(Box::new(log.clone()), format, filter));
+    for _ in 0..frames {
+        vm.run_frame([false; 16], 3).unwrap();
+    }
+    vm.take_tracer().unwrap().finish().unwrap();
+    let data = log.0.borrow().clone();
+    data
+}
+
+fn text(filter: TraceFilter, frames: usize) -> Vec<String> {
+    let log = trace(TraceFormat::Text, filter, frames);
+    String::from_utf8(log).unwrap().lines().map(String::from).collect()
+}
+
+#[test]
+fn test_text() {
+    let lines = text(TraceFilter::default(), 1);
+    assert_eq!(lines, vec![
+        "0 0200 6005 LD V0, 0x05          V=00000000000000000000000000000000 I=0000 SP=0 DT=00 ST=00",
+        "0 0202 7001 ADD V0, 0x01         V=05000000000000000000000000000000 I=0000 SP=0 DT=00 ST=00",
+        "0 0204 A300 LD I, 0x300          V=06000000000000000000000000000000 I=0000 SP=0 DT=00 ST=00",
+    ]);
+}
+
+#[test]
+fn test_binary() {
+    let log = trace(TraceFormat::Binary, TraceFilter::default(), 1);
+    assert_eq!(&log[..6], b"C8TR\x01\x00");
+    let records = &log[6..];
+    assert_eq!(records.len(), 3 * 37);
+    let second = &records[37..74];
+    assert_eq!(&second[..8], &0u64.to_le_bytes());
+    assert_eq!(&second[8..12], &0x202u32.to_le_bytes());
+    assert_eq!(&second[12..14], &0x7001u16.to_le_bytes());
+    assert_eq!(second[14], 5);
+}
+
+#[test]
+fn test_filters() {
+    let kinds = TraceFilter { kinds: Some(vec!["ld".to_string()]), ..TraceFilter::default() };
+    assert!(text(kinds, 2).iter().all(|line| line.contains(" LD ")));
+
+    let pc = TraceFilter { pc: Some(0x202..=0x203), ..TraceFilter::default() };
+    assert_eq!(text(pc, 2).len(), 2);
+
+    let frames = TraceFilter { frames: Some(1..=2), ..TraceFilter::default() };
+    let lines = text(frames, 4);
+    assert_eq!(lines.len(), 6);
+    assert!(lines[0].starts_with("1 0206 1202 JP 0x202"), "{}", lines[0]);
+}
@@ -1,19 +1,27 @@
 
 use sdl2;
 use sdl2::event::Event;
-use sdl2::keyboard::Keycode;
+use sdl2::keyboard::{Keycode, Mod};
 use sdl2::rect::Rect;
-use sdl2::pixels::Color;
-use super::vm::{CHIP8_HEIGHT, CHIP8_WIDTH};
+use sdl2::pixels::{Color, PixelFormatEnum};
+use sdl2::render::Texture;
+use sdl2::audio::{AudioCallback, AudioDevice, AudioSpecDesired};
+use super::vm::{CHIP8_HEIGHT, CHIP8_WIDTH, SCHIP_HEIGHT, SCHIP_WIDTH};
+use super::audio::{AudioPattern, Beeper, Tone};
+use super::megachip::{DigitalSound, MegaFrame, MEGA_HEIGHT, MEGA_WIDTH};
+use super::frontend::{Audio, Command, Display, Input};
 
 pub struct UI {
     canvas: sdl2::render::Canvas<sdl2::video::Window>,
+    mega_texture: Texture,  // MEGA_WIDTH x MEGA_HEIGHT, updated by `draw_mega`
     events: sdl2::EventPump,
+    audio: AudioDevice<Player>,
+    commands: Vec<Command>,
+    rewinding: bool,  // Backspace held
 }
 
-const SCALE_FACTOR: u32 = 5;
-const SCREEN_WIDTH: u32 = CHIP8_WIDTH as u32 * SCALE_FACTOR;
-const SCREEN_HEIGHT: u32 = CHIP8_HEIGHT as u32 * SCALE_FACTOR;
+pub const DEFAULT_SCALE: u32 = 5;  // window pixels per low resolution pixel
+const SAMPLE_RATE: i32 = 44100;
 
 //
 // Keypad                   Keyboard
@@ -30,44 +38,122 @@ const SCREEN_HEIGHT: u32 = CHIP8_HEIGHT as u32 * SCALE_FACTOR;
 
 
 impl UI {
-    pub fn new(sdl_context: sdl2::Sdl) -> UI {
+    // Opens a window named `title`, `scale` times the size of the low resolution screen.
+    pub fn new(sdl_context: sdl2::Sdl, title: &str, scale: u32, fullscreen: bool, tone: Tone) -> UI {
         let video = sdl_context.video().unwrap();
-        let window = video
-            .window("sdl2", SCREEN_WIDTH, SCREEN_HEIGHT)
-            .position_centered()
-            .opengl()
-            .build()
-            .unwrap();
-        let mut canvas = window.into_canvas().build().unwrap();
+        let mut window = video.window(title, CHIP8_WIDTH as u32 * scale, CHIP8_HEIGHT as u32 * scale);
+        window.position_centered().opengl();
+        if fullscreen {
+            window.fullscreen_desktop();
+        }
+        let mut canvas = window.build().unwrap().into_canvas().build().unwrap();
+        // Draw in hi-res pixels, SDL scales them to the window and letterboxes in fullscreen
+        canvas.set_logical_size(SCHIP_WIDTH as u32, SCHIP_HEIGHT as u32).unwrap();
         canvas.set_draw_color(Color::RGB(0, 0, 0));
         canvas.clear();
         canvas.present();
+        let mega_texture = canvas
+            .texture_creator()
+            .create_texture_streaming(PixelFormatEnum::ARGB8888, MEGA_WIDTH as u32, MEGA_HEIGHT as u32)
+            .unwrap();
+
+        let spec = AudioSpecDesired {
+            freq: Some(SAMPLE_RATE),
+            channels: Some(1),
+            samples: None,
+        };
+        let audio = sdl_context.audio().unwrap()
+            .open_playback(None, &spec, |spec| Player {
+                beeper: Beeper::new(tone, spec.freq as f64),
+                sound: None,
+                sound_position: 0.0,
+                sample_rate: spec.freq as f64,
+                volume: tone.volume,
+            })
+            .unwrap();
+        audio.resume();
+
         UI {
-            canvas: canvas,
+            canvas,
+            mega_texture,
             events: sdl_context.event_pump().unwrap(),
+            audio,
+            commands: Vec::new(),
+            rewinding: false,
         }
     }
 
-    pub fn draw(&mut self, pixels: &[[u8; CHIP8_WIDTH]; CHIP8_HEIGHT]) {
-        for (y, row) in pixels.iter().enumerate() {
-            for (x, &col) in row.iter().enumerate() {
-                let x = (x as u32) * SCALE_FACTOR;
-                let y = (y as u32) * SCALE_FACTOR;
+    // `v` is a mask of the bitplanes the pixel is lit on.
+    fn get_color(&mut self, v: u8) -> Color {
+        match v & 0b11 {
+            0 => Color::RGB(0, 0, 0),
+            1 => Color::RGB(0, 250, 0),
+            2 => Color::RGB(250, 120, 0),
+            _ => Color::RGB(250, 250, 250),
+        }
+    }
+}
+
+impl Display for UI {
+    // Scaled to fill the window.
+    fn draw(&mut self, pixels: &[[u8; SCHIP_WIDTH]; SCHIP_HEIGHT], width: usize, height: usize) {
+        let scale = (SCHIP_WIDTH / width) as u32;
+        for (y, row) in pixels[..height].iter().enumerate() {
+            for (x, &col) in row[..width].iter().enumerate() {
+                let x = (x as u32) * scale;
+                let y = (y as u32) * scale;
 
                 let c = self.get_color(col);
                 self.canvas.set_draw_color(c);
-                self.canvas.fill_rect(Rect::new(x as i32, y as i32, SCALE_FACTOR, SCALE_FACTOR));
+                self.canvas.fill_rect(Rect::new(x as i32, y as i32, scale, scale)).unwrap();
             }
         }
         self.canvas.present();
     }
 
-    pub fn poll(&mut self) -> Result<[bool; 16], ()> {
+    // Draws a MegaChip frame as large as fits, keeping its 4:3 shape between black bars.
+    fn draw_mega(&mut self, frame: &MegaFrame) {
+        self.mega_texture
+            .with_lock(None, |buffer, pitch| {
+                for (y, row) in frame.pixels.chunks(MEGA_WIDTH).enumerate() {
+                    for (x, pixel) in row.iter().enumerate() {
+                        let offset = y * pitch + x * 4;
+                        buffer[offset..offset + 4].copy_from_slice(&pixel.to_ne_bytes());
+                    }
+                }
+            })
+            .unwrap();
+        let width = SCHIP_HEIGHT * MEGA_WIDTH / MEGA_HEIGHT;
+        let target = Rect::new(((SCHIP_WIDTH - width) / 2) as i32, 0, width as u32, SCHIP_HEIGHT as u32);
+        self.canvas.set_draw_color(Color::RGB(0, 0, 0));
+        self.canvas.clear();
+        self.canvas.copy(&self.mega_texture, None, target).unwrap();
+        self.canvas.present();
+    }
+}
+
+impl Input for UI {
+    // F1-F9 save a state, Shift+F1-F9 load it, F10 toggles movie recording, F11 plays the movie
+    // back and P pauses.
+    fn poll(&mut self) -> Option<[bool; 16]> {
         let mut chip8_keys = [false; 16];
 
         for event in self.events.poll_iter() {
-            if let Event::Quit { .. } = event {
-                return Err(());
+            match event {
+                Event::Quit { .. } => return None,
+                Event::KeyDown { keycode: Some(key), keymod, repeat: false, .. } => {
+                    let shift = keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD);
+                    let command = match (key, state_slot(key)) {
+                        (_, Some(slot)) if shift => Some(Command::LoadState(slot)),
+                        (_, Some(slot)) => Some(Command::SaveState(slot)),
+                        (Keycode::F10, _) => Some(Command::ToggleRecording),
+                        (Keycode::F11, _) => Some(Command::PlayMovie),
+                        (Keycode::P, _) => Some(Command::TogglePause),
+                        _ => None,
+                    };
+                    self.commands.extend(command);
+                }
+                _ => {}
             }
         }
 
@@ -76,7 +162,8 @@ impl UI {
             .pressed_scancodes()
             .filter_map(Keycode::from_scancode)
             .collect();
-        
+        self.rewinding = keys.contains(&Keycode::Backspace);
+
         for key in keys {
             let index = match key {
                 Keycode::Num1 => Some(0x1),
@@ -102,14 +189,80 @@ impl UI {
                 chip8_keys[i] = true;
             }
         }
-        Ok(chip8_keys)
+        Some(chip8_keys)
     }
 
-    fn get_color(&mut self, v: u8) -> Color {
-        if v == 0 {
-            Color::RGB(0, 0, 0)
-        } else {
-            Color::RGB(0, 250, 0)
+    // Held with Backspace
+    fn rewinding(&self) -> bool {
+        self.rewinding
+    }
+
+    fn commands(&mut self) -> Vec<Command> {
+        std::mem::take(&mut self.commands)
+    }
+}
+
+impl Audio for UI {
+    fn play(&mut self, beep: bool, pattern: Option<AudioPattern>) {
+        self.audio.lock().beeper.set(beep, pattern);
+    }
+
+    fn play_sound(&mut self, sound: Option<&DigitalSound>) {
+        let mut player = self.audio.lock();
+        if player.sound.as_ref().map(|s| s.id) != sound.map(|s| s.id) {
+            player.sound = sound.cloned();
+            player.sound_position = 0.0;
+        }
+    }
+}
+
+fn state_slot(key: Keycode) -> Option<u8> {
+    match key {
+        Keycode::F1 => Some(1),
+        Keycode::F2 => Some(2),
+        Keycode::F3 => Some(3),
+        Keycode::F4 => Some(4),
+        Keycode::F5 => Some(5),
+        Keycode::F6 => Some(6),
+        Keycode::F7 => Some(7),
+        Keycode::F8 => Some(8),
+        Keycode::F9 => Some(9),
+        _ => None,
+    }
+}
+
+struct Player {
+    beeper: Beeper,
+    sound: Option<DigitalSound>,
+    sound_position: f64,  // in samples
+    sample_rate: f64,
+    volume: f32,
+}
+
+impl Player {
+    fn next_sound_sample(&mut self) -> Option<f32> {
+        let sound = self.sound.as_ref()?;
+        if self.sound_position as usize >= sound.samples.len() {
+            if !sound.looping || sound.samples.is_empty() {
+                self.sound = None;
+                return None;
+            }
+            self.sound_position = 0.0;
+        }
+        let sample = sound.samples[self.sound_position as usize];
+        self.sound_position += sound.rate as f64 / self.sample_rate;
+        Some((sample as f32 - 128.0) / 128.0 * self.volume)
+    }
+}
+
+impl AudioCallback for Player {
+    type Channel = f32;
+
+    fn callback(&mut self, out: &mut [f32]) {
+        for sample in out.iter_mut() {
+            // The beeper keeps running under MegaChip sounds, so its fades stay in time
+            let beep = self.beeper.next_sample();
+            *sample = self.next_sound_sample().unwrap_or(beep);
         }
     }
-}
\ No newline at end of file
+}
@@ -1,12 +1,26 @@
-use rand;
 use super::instruction::{Instruction, Addr, Byte};
-use super::font::{FONT_SET};
+use super::font::{FONT_SET, BIG_FONT_SET};
+use super::audio::{AudioPattern, AUDIO_PATTERN_SIZE};
+use super::mode::Mode;
+use super::mega_instruction::MegaInstruction;
+use super::megachip::{MegaChip, MegaFrame, BlendMode};
+use super::quirks::Quirks;
+use super::error::VmError;
+use super::flow::Op;
+use super::hash::fnv1a;
+use super::random::{self, Random, SplitMix64, DEFAULT_SEED};
+use super::snapshot::{Snapshot, SnapshotError, StateReader, StateWriter};
+use super::trace::{TraceEntry, Tracer};
+use super::watch::{Access, WatchHit, Watchpoint};
 
 
 const OPCODE_SIZE: usize = 2;
-const CHIP8_RAM_SIZE: usize = 4096;
 pub const CHIP8_WIDTH: usize = 64;
 pub const CHIP8_HEIGHT: usize = 32;
+pub const SCHIP_WIDTH: usize = 128;
+pub const SCHIP_HEIGHT: usize = 64;
+const BIG_FONT_ADDR: usize = 0x50;
+pub const PROGRAM_START: usize = 0x200;  // where ROMs are loaded and execution starts
 
 
 enum ProgramCounter {
@@ -26,94 +40,319 @@ impl ProgramCounter {
 }
 
 pub struct OutputState<'a> {
-    pub vram: &'a [[u8; CHIP8_WIDTH]; CHIP8_HEIGHT],
+    // Only the top-left `width`x`height` pixels are in use. Each pixel is a mask of the bitplanes
+    // it is lit on: bit 0 for the first plane and bit 1 for the second, which only XO-CHIP uses.
+    pub vram: &'a [[u8; SCHIP_WIDTH]; SCHIP_HEIGHT],
+    pub width: usize,
+    pub height: usize,
     pub vram_changed: bool,
     pub beep: bool,
+    pub audio: Option<AudioPattern>,  // XO-CHIP pattern to play while `beep` is set, if one was loaded
+    pub waiting: bool,  // blocked on Fx0A until a key is pressed and released
+    pub halted: bool,  // stopped by 00FD
+    pub mega: Option<MegaFrame<'a>>,  // replaces `vram` while MegaChip mode is on
+}
+
+// An instruction decoded from memory, with the opcode it came from for error reports.
+#[derive(Clone, Copy)]
+struct Decoded {
+    opcode: u16,
+    instruction: Instruction,
+}
+
+// The CPU state, as seen and changed by debuggers.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Registers {
+    pub pc: usize,
+    pub i: u32,
+    pub sp: usize,
+    pub stack: [usize; 16],
+    pub v: [u8; 16],
+    pub delay_timer: u8,
+    pub sound_timer: u8,
 }
 
 pub struct VM {
-    ram: [u8; CHIP8_RAM_SIZE],
-    vram: [[u8; CHIP8_WIDTH]; CHIP8_HEIGHT],  // graphics memory
+    ram: Vec<u8>,  // sized for the mode, see `Mode::memory_size`
+    vram: [[u8; SCHIP_WIDTH]; SCHIP_HEIGHT],  // graphics memory
     vram_changed: bool,
+    hires: bool,  // SUPER-CHIP 128x64 mode
     stack: [usize; 16],
     v: [u8; 16],  // cpu registers
-    i: u16,
+    i: u32,  // 24 bits on MegaChip, 16 bits otherwise
     pc: usize,
     sp: usize,
+    opcode: u16,  // opcode being executed, for error reports
     delay_timer: u8,
     sound_timer: u8,
     keypad: [bool; 16],
-    keypad_waiting: bool, // ?
-    keypad_register: usize, // ?
+    keypad_waiting: bool,  // executing Fx0A
+    keypad_register: usize,  // register receiving the key once Fx0A completes
+    keypad_pressed: Option<usize>,  // key pressed during Fx0A, waiting for its release
+    flags: [u8; 16],  // SUPER-CHIP RPL user flags
+    planes: u8,  // XO-CHIP bitplanes selected for drawing
+    audio_pattern: Option<[u8; AUDIO_PATTERN_SIZE]>,  // XO-CHIP audio pattern buffer
+    pitch: u8,
+    halted: bool,
+    mega: Option<MegaChip>,  // MegaChip display and sound, in MegaChip mode only
+    mode: Mode,
+    quirks: Quirks,
+    rom_hash: u64,  // of the last loaded ROM, so save states can't be restored into another game
+    rng: Box<dyn Random>,  // source of Cxkk
+    tracer: Option<Box<Tracer>>,
+    watchpoints: Vec<Watchpoint>,
+    watch_hits: Vec<WatchHit>,  // since the last `take_watch_hits`
+    cache: Vec<Option<Decoded>>,  // by address, empty in MegaChip mode
+}
+
+impl Default for VM {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl VM {
     pub fn new() -> Self {
-        let mut ram = [0; CHIP8_RAM_SIZE];
-        for i in 0..FONT_SET.len() {
-            ram[i] = FONT_SET[i];
-        }
+        Self::with_quirks(Quirks::default())
+    }
+
+    pub fn with_quirks(quirks: Quirks) -> Self {
+        Self::with_mode(Mode::Chip8, quirks)
+    }
+
+    pub fn with_mode(mode: Mode, quirks: Quirks) -> Self {
+        Self::with_seed(mode, quirks, DEFAULT_SEED)
+    }
+
+    // Two VMs created with the same seed and fed the same input run identically.
+    pub fn with_seed(mode: Mode, quirks: Quirks, seed: u64) -> Self {
+        Self::with_rng(mode, quirks, Box::new(SplitMix64::new(seed)))
+    }
+
+    pub fn with_rng(mode: Mode, quirks: Quirks, rng: Box<dyn Random>) -> Self {
+        let mut ram = vec![0; mode.memory_size()];
+        ram[..FONT_SET.len()].copy_from_slice(&FONT_SET);
+        ram[BIG_FONT_ADDR..BIG_FONT_ADDR + BIG_FONT_SET.len()].copy_from_slice(&BIG_FONT_SET);
 
         Self {
-            vram: [[0; CHIP8_WIDTH]; CHIP8_HEIGHT],
+            vram: [[0; SCHIP_WIDTH]; SCHIP_HEIGHT],
             vram_changed: false,
-            ram: ram,
+            hires: false,
+            ram,
             v: [0; 16],
             stack: [0; 16],
             i: 0,
-            pc: 0x200,
+            pc: PROGRAM_START,
             sp: 0,
+            opcode: 0,
             keypad: [false; 16],
             keypad_waiting: false,
             keypad_register: 0,
+            keypad_pressed: None,
+            flags: [0; 16],
+            planes: 1,
+            audio_pattern: None,
+            pitch: AudioPattern::default().pitch,
+            halted: false,
+            mega: if mode == Mode::MegaChip { Some(MegaChip::new()) } else { None },
+            mode,
             delay_timer: 0,
             sound_timer: 0,
+            quirks,
+            rom_hash: fnv1a(&[]),
+            rng,
+            tracer: None,
+            watchpoints: Vec::new(),
+            watch_hits: Vec::new(),
+            cache: if mode == Mode::MegaChip { Vec::new() } else { vec![None; mode.memory_size()] },
         }
     }
 
-    pub fn step(&mut self, keypad: [bool; 16]) -> OutputState {
+    pub fn step(&mut self, keypad: [bool; 16]) -> Result<OutputState<'_>, VmError> {
         self.vram_changed = false;
+        let previous = self.keypad;
+        self.keypad = keypad;
 
-        for i in 0..keypad.len() {
-            self.keypad[i] = keypad[i];
+        if self.halted {
+            return Ok(self.output());
         }
-
         if self.keypad_waiting {
-            for i in 0..keypad.len() {
-                if keypad[i] {
+            self.wait_key(previous);
+        } else {
+            self.run_next()?;
+        }
+
+        Ok(self.output())
+    }
+
+    // Runs one 60 Hz frame: `instructions` steps followed by a timer tick. `vram_changed` reports
+    // whether any of the steps touched the screen.
+    pub fn run_frame(&mut self, keypad: [bool; 16], instructions: u32) -> Result<OutputState<'_>, VmError> {
+        let mut vram_changed = false;
+        for _ in 0..instructions {
+            vram_changed |= self.step(keypad)?.vram_changed;
+        }
+        self.tick_timers();
+        self.vram_changed = vram_changed;
+        Ok(self.output())
+    }
+
+    // Executes the instruction at `pc` and nothing else: the keypad keeps its last state and the
+    // timers don't tick. For debuggers. While Fx0A waits, see `OutputState::waiting`, nothing
+    // happens, since only a key going down and up again in `step` can end the wait.
+    pub fn step_instruction(&mut self) -> Result<(), VmError> {
+        self.vram_changed = false;
+        if self.halted || self.keypad_waiting {
+            return Ok(());
+        }
+        self.run_next()
+    }
+
+    pub fn registers(&self) -> Registers {
+        Registers {
+            pc: self.pc,
+            i: self.i,
+            sp: self.sp,
+            stack: self.stack,
+            v: self.v,
+            delay_timer: self.delay_timer,
+            sound_timer: self.sound_timer,
+        }
+    }
+
+    // Replaces the CPU state. `sp` is clamped to the stack size.
+    pub fn set_registers(&mut self, registers: &Registers) {
+        self.pc = registers.pc;
+        self.i = registers.i;
+        self.sp = registers.sp.min(self.stack.len());
+        self.stack = registers.stack;
+        self.v = registers.v;
+        self.delay_timer = registers.delay_timer;
+        self.sound_timer = registers.sound_timer;
+    }
+
+    pub fn memory(&self) -> &[u8] {
+        &self.ram
+    }
+
+    pub fn memory_mut(&mut self) -> &mut [u8] {
+        self.invalidate(0, self.ram.len());
+        &mut self.ram
+    }
+
+    // Like the COSMAC VIP, Fx0A only accepts a key which goes down while waiting, and completes once
+    // that key is released. Keys already held when the wait started are ignored.
+    fn wait_key(&mut self, previous: [bool; 16]) {
+        match self.keypad_pressed {
+            None => {
+                self.keypad_pressed = (0..self.keypad.len()).find(|&i| self.keypad[i] && !previous[i]);
+            }
+            Some(key) => {
+                if !self.keypad[key] {
+                    self.v[self.keypad_register] = key as u8;
                     self.keypad_waiting = false;
-                    self.v[self.keypad_register] = i as u8;
-                    break;
+                    self.keypad_pressed = None;
                 }
             }
-        } else {
-            if self.delay_timer > 0 {
-                self.delay_timer -= 1;
-            }
-            if self.sound_timer > 0 {
-                self.sound_timer -= 1;
+        }
+    }
+
+    // Decrements the delay and sound timers. Must be called at 60 Hz, independently of how many
+    // instructions are executed.
+    pub fn tick_timers(&mut self) {
+        if let Some(ref mut tracer) = self.tracer {
+            tracer.next_frame();
+        }
+        if self.delay_timer > 0 {
+            self.delay_timer -= 1;
+        }
+        if self.sound_timer > 0 {
+            self.sound_timer -= 1;
+        }
+    }
+
+    pub fn run_opcode(&mut self, opcode: u16) -> Result<OutputState<'_>, VmError> {
+        self.opcode = opcode;
+        if self.tracer.is_some() {
+            self.trace(opcode);
+        }
+        if self.mega.is_some() {
+            if let Some(instruction) = MegaInstruction::decode(opcode) {
+                return self.run_mega_instruction(instruction);
             }
-            let opcode = self.get_opcode();
-            self.run_opcode(opcode);
         }
 
-        OutputState {
-            vram: &self.vram,
-            vram_changed: self.vram_changed,
-            beep: self.sound_timer > 0,
+        let instruction = self.decode(opcode)?;
+        self.execute(instruction)?;
+        Ok(self.output())
+    }
+
+    // Runs the instruction at `pc`. Its decoding is cached until the memory it came from is
+    // written, except while tracing or watching, which need to see every fetch, and in MegaChip
+    // mode.
+    fn run_next(&mut self) -> Result<(), VmError> {
+        if self.cache.is_empty() || self.tracer.is_some() || !self.watchpoints.is_empty() {
+            let opcode = self.get_opcode()?;
+            self.run_opcode(opcode)?;
+            return Ok(());
+        }
+
+        let decoded = match self.cache.get(self.pc) {
+            Some(&Some(decoded)) => decoded,
+            _ => {
+                let opcode = self.get_opcode()?;
+                let decoded = Decoded { opcode, instruction: self.decode(opcode)? };
+                self.cache[self.pc] = Some(decoded);
+                decoded
+            }
+        };
+        self.opcode = decoded.opcode;
+        self.execute(decoded.instruction)
+    }
+
+    // Forgets the cached instructions overlapping the `len` bytes at `addr`, including 4 byte
+    // ones starting up to 3 bytes before.
+    fn invalidate(&mut self, addr: usize, len: usize) {
+        let end = (addr + len).min(self.cache.len());
+        let start = addr.saturating_sub(3).min(end);
+        self.cache[start..end].iter_mut().for_each(|entry| *entry = None);
+    }
+
+    // The instruction at `pc` whose first word is `opcode`, reading the second word if it has one.
+    fn decode(&self, opcode: u16) -> Result<Instruction, VmError> {
+        let instruction = Instruction::decode(opcode);
+        if !self.mode.supports(&instruction) {
+            return Err(VmError::UnknownOpcode { pc: self.pc, opcode });
         }
+        if instruction.size() > OPCODE_SIZE {
+            let next = self.read_word(self.pc + OPCODE_SIZE)
+                .ok_or(VmError::PcOutOfBounds { pc: self.pc, opcode })?;
+            return Ok(Instruction::decode_long(opcode, next));
+        }
+        Ok(instruction)
     }
 
-    pub fn run_opcode(&mut self, opcode: u16) {
-        let pc_change = match Instruction::decode(opcode) {
+    fn execute(&mut self, instruction: Instruction) -> Result<(), VmError> {
+        let opcode = self.opcode;
+        let size = instruction.size();
+
+        let pc_change = match instruction {
             Instruction::Clear => self.op_clear(),
+            Instruction::ScrollDown(n) => self.op_scroll_down(n as usize),
+            Instruction::ScrollRight => self.op_scroll_right(),
+            Instruction::ScrollLeft => self.op_scroll_left(),
+            Instruction::Exit => self.op_exit(),
+            Instruction::LowRes => self.op_set_hires(false),
+            Instruction::HighRes => self.op_set_hires(true),
             Instruction::Sys(_) => ProgramCounter::Next,
-            Instruction::Return => self.op_return(),
+            Instruction::Return => self.op_return()?,
             Instruction::Jump(addr) => self.op_jump(addr),
-            Instruction::Call(addr) => self.op_call(addr),
+            Instruction::Call(addr) => self.op_call(addr)?,
             Instruction::SkipEqualK(x, k) => self.op_skip_equal_k(x as usize, k),
             Instruction::SkipNotEqualK(x, k) => self.op_skip_not_equal_k(x as usize, k),
             Instruction::SkipEqual(x, y) => self.op_skip_equal(x as usize, y as usize),
+            Instruction::StoreRange(x, y) => self.op_store_range(x as usize, y as usize)?,
+            Instruction::LoadRange(x, y) => self.op_load_range(x as usize, y as usize)?,
             Instruction::LoadK(x, k) => self.op_load_k(x as usize, k), 
             Instruction::AddK(x, k) => self.op_add_k(x as usize, k),
             Instruction::Set(x, y) => self.op_set(x as usize, y as usize),
@@ -122,14 +361,18 @@ impl VM {
             Instruction::Xor(x, y) => self.op_xor(x as usize, y as usize),
             Instruction::Add(x, y) => self.op_add(x as usize, y as usize),
             Instruction::Sub(x, y) => self.op_sub(x as usize, y as usize),
-            Instruction::ShiftRight(x) => self.op_shift_right(x as usize),
+            Instruction::ShiftRight(x, y) => self.op_shift_right(x as usize, y as usize),
             Instruction::SubInv(x, y) => self.op_sub_inv(x as usize, y as usize),
-            Instruction::ShiftLeft(x) => self.op_shift_left(x as usize),
+            Instruction::ShiftLeft(x, y) => self.op_shift_left(x as usize, y as usize),
             Instruction::SkipNotEqual(x, y) => self.op_skip_not_equal(x as usize, y as usize),
             Instruction::LoadI(addr) => self.op_load_i(addr),
             Instruction::LongJump(addr) => self.op_long_jump(addr),
+            Instruction::LoadLongI(addr) => self.op_load_i(addr),
+            Instruction::Plane(n) => self.op_plane(n),
+            Instruction::LoadAudio => self.op_load_audio()?,
+            Instruction::Pitch(x) => self.op_pitch(x as usize),
             Instruction::Rand(x, k) => self.op_rand(x as usize, k),
-            Instruction::Draw(x, y, k) => self.op_draw(x as usize, y as usize, k as usize),
+            Instruction::Draw(x, y, k) => self.op_draw(x as usize, y as usize, k as usize)?,
             Instruction::SkipPressed(x) => self.op_skip_pressed(x as usize),
             Instruction::SkipNotPressed(x) => self.op_skip_not_pressed(x as usize),
             Instruction::GetTimer(x) => self.op_get_timer(x as usize),
@@ -138,56 +381,503 @@ impl VM {
             Instruction::SetSoundTimer(x) => self.op_set_sound_timer(x as usize),
             Instruction::AddI(x) => self.op_add_i(x as usize),
             Instruction::LoadHexGlyph(x) => self.op_load_hex_glyph(x as usize),
-            Instruction::StoreBCD(x) => self.op_store_bcd(x as usize),
-            Instruction::StoreRegisters(x) => self.op_store_registers(x as usize),
-            Instruction::LoadRegisters(x) => self.op_load_registers(x as usize),
-            Instruction::Unknown => ProgramCounter::Next,
+            Instruction::LoadBigHexGlyph(x) => self.op_load_big_hex_glyph(x as usize),
+            Instruction::StoreBCD(x) => self.op_store_bcd(x as usize)?,
+            Instruction::StoreRegisters(x) => self.op_store_registers(x as usize)?,
+            Instruction::LoadRegisters(x) => self.op_load_registers(x as usize)?,
+            Instruction::StoreFlags(x) => self.op_store_flags(x as usize),
+            Instruction::LoadFlags(x) => self.op_load_flags(x as usize),
+            Instruction::Unknown(_) => return Err(VmError::UnknownOpcode { pc: self.pc, opcode }),
+        };
+
+        self.advance(pc_change, size);
+        Ok(())
+    }
+
+    fn run_mega_instruction(&mut self, mut instruction: MegaInstruction) -> Result<OutputState<'_>, VmError> {
+        if instruction.size() > OPCODE_SIZE {
+            let opcode = self.opcode;
+            let next = self.read_word(self.pc + OPCODE_SIZE)
+                .ok_or(VmError::PcOutOfBounds { pc: self.pc, opcode })?;
+            instruction = MegaInstruction::decode_long(opcode, next)
+                .ok_or(VmError::UnknownOpcode { pc: self.pc, opcode })?;
+        }
+        let size = instruction.size();
+
+        let pc_change = match instruction {
+            MegaInstruction::MegaOff => self.op_mega_enable(false),
+            MegaInstruction::MegaOn => self.op_mega_enable(true),
+            MegaInstruction::ScrollUp(n) => self.op_scroll_up(n as usize),
+            MegaInstruction::LoadHighI(addr) => self.op_load_high_i(addr),
+            MegaInstruction::LoadPalette(n) => self.op_load_palette(n as usize)?,
+            MegaInstruction::SpriteWidth(n) => self.op_mega(|mega| mega.set_sprite_width(n)),
+            MegaInstruction::SpriteHeight(n) => self.op_mega(|mega| mega.set_sprite_height(n)),
+            MegaInstruction::Alpha(n) => self.op_mega(|mega| mega.set_alpha(n)),
+            MegaInstruction::PlaySound(n) => self.op_play_sound(n == 0)?,
+            MegaInstruction::StopSound => self.op_mega(|mega| mega.stop_sound()),
+            MegaInstruction::BlendMode(n) => self.op_mega(|mega| mega.set_blend_mode(BlendMode::from_byte(n))),
+            MegaInstruction::CollisionColor(n) => self.op_mega(|mega| mega.set_collision_color(n)),
         };
 
+        self.advance(pc_change, size);
+        Ok(self.output())
+    }
+
+    fn advance(&mut self, pc_change: ProgramCounter, size: usize) {
         match pc_change {
-            ProgramCounter::Next => self.pc += OPCODE_SIZE,
-            ProgramCounter::Skip => self.pc += OPCODE_SIZE * 2,
+            ProgramCounter::Next => self.pc += size,
+            ProgramCounter::Skip => self.pc += size + self.instruction_size(self.pc + size),
             ProgramCounter::Jump(addr) => self.pc = addr
         }
     }
 
+    // The MegaChip state, while MegaChip mode is on.
+    fn mega_enabled(&mut self) -> Option<&mut MegaChip> {
+        self.mega.as_mut().filter(|mega| mega.enabled)
+    }
+
+    // The current output, as last returned by `step`.
+    pub fn output(&self) -> OutputState<'_> {
+        let (width, height) = self.resolution();
+        OutputState {
+            vram: &self.vram,
+            width,
+            height,
+            vram_changed: self.vram_changed,
+            beep: self.sound_timer > 0,
+            audio: self.audio_pattern.map(|pattern| AudioPattern { pattern, pitch: self.pitch }),
+            waiting: self.keypad_waiting,
+            halted: self.halted,
+            mega: self.mega.as_ref().filter(|mega| mega.enabled).map(|mega| mega.frame()),
+        }
+    }
+
+    // Hash of what is on screen, for telling whether two runs drew the same thing.
+    pub fn frame_hash(&self) -> u64 {
+        let output = self.output();
+        let mut pixels = Vec::new();
+        match output.mega {
+            Some(frame) => frame.pixels.iter().for_each(|p| pixels.extend_from_slice(&p.to_le_bytes())),
+            None => output.vram[..output.height].iter().for_each(|row| pixels.extend_from_slice(&row[..output.width])),
+        }
+        fnv1a(&pixels)
+    }
+
+    // Whether the program is done: halted by 00FD, or spinning on a jump to itself as many ROMs do
+    // once they have drawn their final screen.
+    pub fn finished(&self) -> bool {
+        let spinning = self.pc <= 0xfff && self.read_word(self.pc) == Some(0x1000 | self.pc as u16);
+        self.halted || spinning
+    }
+
+    pub fn rom_hash(&self) -> u64 {
+        self.rom_hash
+    }
+
+    pub fn mode(&self) -> Mode {
+        self.mode
+    }
+
+    pub fn quirks(&self) -> Quirks {
+        self.quirks
+    }
+
+    fn resolution(&self) -> (usize, usize) {
+        if self.hires {
+            (SCHIP_WIDTH, SCHIP_HEIGHT)
+        } else {
+            (CHIP8_WIDTH, CHIP8_HEIGHT)
+        }
+    }
+
+    // Copies `data` to 0x200. Bytes which don't fit in memory are dropped, `Rom::check_size`
+    // catches those ROMs beforehand.
     pub fn load(&mut self, data: &[u8]) {
+        self.rom_hash = fnv1a(data);
+        self.invalidate(0, self.ram.len());
         for (i, &byte) in data.iter().enumerate() {
-            let addr = 0x200 + i;
-            if addr >= 4096 {
+            let addr = PROGRAM_START + i;
+            if addr >= self.mode.memory_size() {
                 break
             }
             self.ram[addr] = byte;
         }
     }
 
-    fn get_opcode(&self) -> u16 {
-        (self.ram[self.pc] as u16) << 8 | (self.ram[self.pc+1] as u16)
+    pub fn snapshot(&self) -> Snapshot {
+        let mut w = StateWriter::new();
+        w.u32(self.pc as u32);
+        w.u32(self.i);
+        w.u8(self.sp as u8);
+        self.stack.iter().for_each(|&addr| w.u32(addr as u32));
+        w.raw(&self.v);
+        w.u8(self.delay_timer);
+        w.u8(self.sound_timer);
+        w.u16(keypad_bits(&self.keypad));
+        w.bool(self.keypad_waiting);
+        w.u8(self.keypad_register as u8);
+        w.u8(self.keypad_pressed.map_or(0xff, |key| key as u8));
+        w.bool(self.hires);
+        w.bool(self.halted);
+        w.raw(&self.flags);
+        w.u8(self.planes);
+        w.bool(self.audio_pattern.is_some());
+        w.raw(&self.audio_pattern.unwrap_or_default());
+        w.u8(self.pitch);
+        w.bytes(&self.rng.state());
+        self.vram.iter().for_each(|row| w.raw(row));
+        // Memory is mostly empty past the ROM, so trailing zeros aren't stored
+        let used = self.ram.iter().rposition(|&b| b != 0).map_or(0, |n| n + 1);
+        w.bytes(&self.ram[..used]);
+        if let Some(ref mega) = self.mega {
+            mega.save_state(&mut w);
+        }
+
+        Snapshot {
+            rom_hash: self.rom_hash,
+            mode: self.mode,
+            quirks: self.quirks,
+            state: w.finish(),
+        }
+    }
+
+    // Restores a state taken by `snapshot`. The state must come from the same ROM, mode and quirks;
+    // on error the VM is left untouched.
+    pub fn restore(&mut self, snapshot: &Snapshot) -> Result<(), SnapshotError> {
+        if snapshot.rom_hash != self.rom_hash {
+            return Err(SnapshotError::RomMismatch);
+        }
+        if snapshot.mode != self.mode {
+            return Err(SnapshotError::ModeMismatch);
+        }
+        if snapshot.quirks != self.quirks {
+            return Err(SnapshotError::QuirksMismatch);
+        }
+
+        let mut vm = VM::with_mode(self.mode, self.quirks);
+        vm.rom_hash = self.rom_hash;
+        let mut r = StateReader::new(&snapshot.state);
+        vm.pc = r.u32()? as usize;
+        vm.i = r.u32()?;
+        vm.sp = r.u8()? as usize;
+        for addr in vm.stack.iter_mut() {
+            *addr = r.u32()? as usize;
+        }
+        vm.v.copy_from_slice(r.raw(16)?);
+        vm.delay_timer = r.u8()?;
+        vm.sound_timer = r.u8()?;
+        let keys = r.u16()?;
+        for (key, down) in vm.keypad.iter_mut().enumerate() {
+            *down = keys & 1 << key != 0;
+        }
+        vm.keypad_waiting = r.bool()?;
+        vm.keypad_register = r.u8()? as usize;
+        vm.keypad_pressed = match r.u8()? {
+            0xff => None,
+            key => Some(key as usize),
+        };
+        vm.hires = r.bool()?;
+        vm.halted = r.bool()?;
+        vm.flags.copy_from_slice(r.raw(16)?);
+        vm.planes = r.u8()?;
+        let has_audio = r.bool()?;
+        let mut pattern = [0; AUDIO_PATTERN_SIZE];
+        pattern.copy_from_slice(r.raw(AUDIO_PATTERN_SIZE)?);
+        vm.audio_pattern = if has_audio { Some(pattern) } else { None };
+        vm.pitch = r.u8()?;
+        let rng_state = r.bytes()?;
+        for row in vm.vram.iter_mut() {
+            row.copy_from_slice(r.raw(SCHIP_WIDTH)?);
+        }
+        let ram = r.bytes()?;
+        if ram.len() > vm.ram.len() {
+            return Err(SnapshotError::Corrupt);
+        }
+        vm.ram[..ram.len()].copy_from_slice(ram);
+        vm.ram[ram.len()..].iter_mut().for_each(|b| *b = 0);
+        if vm.mega.is_some() {
+            vm.mega = Some(MegaChip::load_state(&mut r)?);
+        }
+        let out_of_range = vm.sp > vm.stack.len()
+            || vm.keypad_register >= vm.v.len()
+            || vm.keypad_pressed.is_some_and(|key| key >= vm.keypad.len());
+        if out_of_range || !r.is_empty() {
+            return Err(SnapshotError::Corrupt);
+        }
+
+        // A state from a VM with another of the built in sources, as in a movie, brings it along
+        if self.rng.set_state(rng_state).is_err() {
+            self.rng = random::from_state(rng_state)?;
+        }
+        std::mem::swap(&mut vm.rng, &mut self.rng);
+        vm.tracer = self.tracer.take();
+        vm.watchpoints = std::mem::take(&mut self.watchpoints);
+        vm.watch_hits = std::mem::take(&mut self.watch_hits);
+
+        vm.vram_changed = true;
+        *self = vm;
+        Ok(())
+    }
+
+    // Logs every instruction run from now on, until `take_tracer`.
+    pub fn set_tracer(&mut self, tracer: Tracer) {
+        self.tracer = Some(Box::new(tracer));
+    }
+
+    pub fn take_tracer(&mut self) -> Option<Tracer> {
+        self.tracer.take().map(|tracer| *tracer)
+    }
+
+    // Logs the instruction at `pc` before it runs. Out of line, so that a VM without a tracer only
+    // pays for the check in `run_opcode`.
+    #[inline(never)]
+    fn trace(&mut self, opcode: u16) {
+        let mut tracer = match self.tracer.take() {
+            Some(tracer) => tracer,
+            None => return,
+        };
+        if tracer.wants(self.pc) {
+            let mnemonic = self.mnemonic(opcode);
+            tracer.record(&TraceEntry { opcode, mnemonic: &mnemonic, registers: &self.registers() });
+        }
+        self.tracer = Some(tracer);
+    }
+
+    // The instruction at `pc`, whose first word is `opcode`, as the disassembler prints it.
+    fn mnemonic(&self, opcode: u16) -> String {
+        self.decode_op(opcode, self.read_word(self.pc + OPCODE_SIZE).unwrap_or(0)).to_string()
+    }
+
+    // The instruction at `addr` as this VM would run it, for debuggers: from MegaChip's set in
+    // MegaChip mode, with the second word of 4 byte instructions, and `Unknown` for opcodes the
+    // mode doesn't have. Memory past the end reads as zeros.
+    pub fn instruction_at(&self, addr: usize) -> Op {
+        let byte = |addr: usize| self.ram.get(addr).cloned().unwrap_or(0) as u16;
+        let word = |addr: usize| byte(addr) << 8 | byte(addr + 1);
+        self.decode_op(word(addr), word(addr + OPCODE_SIZE))
+    }
+
+    // Decodes `opcode`, followed by `next`, the way `run_opcode` does.
+    fn decode_op(&self, opcode: u16, next: u16) -> Op {
+        if self.mega.is_some() {
+            if let Some(instruction) = MegaInstruction::decode_long(opcode, next) {
+                return Op::Mega(instruction);
+            }
+        }
+        let instruction = Instruction::decode(opcode);
+        if !self.mode.supports(&instruction) {
+            return Op::Chip(Instruction::Unknown(opcode));
+        }
+        Op::Chip(Instruction::decode_long(opcode, next))
+    }
+
+    // Reports the instructions which access watched memory from now on, see `take_watch_hits`.
+    // An empty list stops watching.
+    pub fn set_watchpoints(&mut self, watchpoints: Vec<Watchpoint>) {
+        self.watchpoints = watchpoints;
+    }
+
+    pub fn watchpoints(&self) -> &[Watchpoint] {
+        &self.watchpoints
+    }
+
+    // The watched accesses since the last call, oldest first.
+    pub fn take_watch_hits(&mut self) -> Vec<WatchHit> {
+        std::mem::take(&mut self.watch_hits)
+    }
+
+    // A copy of the `len` bytes at `addr` if a watchpoint wants this access to them, taken before
+    // a write so that `report` can tell what changed.
+    fn watched(&self, access: Access, addr: usize, len: usize) -> Option<Vec<u8>> {
+        self.watchpoints
+            .iter()
+            .any(|w| w.kind.matches(access) && w.overlaps(addr, len))
+            .then(|| self.ram[addr..addr + len].to_vec())
+    }
+
+    fn report(&mut self, access: Access, opcode: u16, addr: usize, old: Vec<u8>) {
+        let new = self.ram[addr..addr + old.len()].to_vec();
+        self.watch_hits.push(WatchHit {
+            pc: self.pc,
+            opcode,
+            instruction: self.mnemonic(opcode),
+            access,
+            addr,
+            old,
+            new,
+        });
+    }
+
+    // Reports a read by the instruction being executed.
+    fn watch_read(&mut self, addr: usize, len: usize) {
+        if let Some(data) = self.watched(Access::Read, addr, len) {
+            self.report(Access::Read, self.opcode, addr, data);
+        }
+    }
+
+    fn get_opcode(&mut self) -> Result<u16, VmError> {
+        let opcode = self.read_word(self.pc).ok_or_else(|| {
+            let opcode = match self.pc < self.mode.memory_size() {
+                true => (self.ram[self.pc] as u16) << 8,
+                false => 0,
+            };
+            VmError::PcOutOfBounds { pc: self.pc, opcode }
+        })?;
+        if let Some(data) = self.watched(Access::Read, self.pc, OPCODE_SIZE) {
+            self.report(Access::Read, opcode, self.pc, data);
+        }
+        Ok(opcode)
+    }
+
+    fn read_word(&self, addr: usize) -> Option<u16> {
+        if addr + 1 >= self.mode.memory_size() {
+            return None;
+        }
+        Some((self.ram[addr] as u16) << 8 | (self.ram[addr + 1] as u16))
+    }
+
+    // Size of the instruction at `addr`, so skips can step over XO-CHIP's 4 byte instructions.
+    fn instruction_size(&self, addr: usize) -> usize {
+        self.instruction_at(addr).size()
+    }
+
+    // Checks that `len` bytes starting at `addr` are inside memory, returning `addr` if so.
+    fn check_ram(&self, addr: usize, len: usize) -> Result<usize, VmError> {
+        let size = self.mode.memory_size();
+        if addr + len > size {
+            return Err(VmError::MemoryOutOfBounds {
+                pc: self.pc,
+                opcode: self.opcode,
+                addr: addr.max(size),
+            });
+        }
+        Ok(addr)
     }
 
     fn op_clear(&mut self) -> ProgramCounter {
-        for i in 0..CHIP8_HEIGHT {
-            for j in 0..CHIP8_WIDTH {
-                self.vram[i][j] = 0;
+        if let Some(mega) = self.mega_enabled() {
+            mega.clear();
+            self.vram_changed = true;
+            return ProgramCounter::Next;
+        }
+        for row in self.vram.iter_mut() {
+            for pixel in row.iter_mut() {
+                *pixel &= !self.planes;
             }
         }
         self.vram_changed = true;
         ProgramCounter::Next
     }
 
-    fn op_return(&mut self) -> ProgramCounter {
+    fn op_scroll_down(&mut self, n: usize) -> ProgramCounter {
+        self.scroll(0, n as isize);
+        ProgramCounter::Next
+    }
+
+    fn op_scroll_right(&mut self) -> ProgramCounter {
+        self.scroll(4, 0);
+        ProgramCounter::Next
+    }
+
+    fn op_scroll_left(&mut self) -> ProgramCounter {
+        self.scroll(-4, 0);
+        ProgramCounter::Next
+    }
+
+    // Moves the selected planes by `dx`, `dy` pixels. Pixels scrolled in from outside the screen are unlit.
+    fn scroll(&mut self, dx: isize, dy: isize) {
+        self.vram_changed = true;
+        if let Some(mega) = self.mega_enabled() {
+            mega.scroll(dx, dy);
+            return;
+        }
+        let (width, height) = self.resolution();
+        let src = self.vram;
+        for y in 0..height {
+            for x in 0..width {
+                let sx = x as isize - dx;
+                let sy = y as isize - dy;
+                let inside = sx >= 0 && sy >= 0 && (sx as usize) < width && (sy as usize) < height;
+                let moved = if inside { src[sy as usize][sx as usize] & self.planes } else { 0 };
+                self.vram[y][x] = (self.vram[y][x] & !self.planes) | moved;
+            }
+        }
+    }
+
+    fn op_mega_enable(&mut self, enabled: bool) -> ProgramCounter {
+        if let Some(mega) = self.mega.as_mut() {
+            mega.enabled = enabled;
+        }
+        self.vram_changed = true;
+        ProgramCounter::Next
+    }
+
+    fn op_scroll_up(&mut self, n: usize) -> ProgramCounter {
+        self.scroll(0, -(n as isize));
+        ProgramCounter::Next
+    }
+
+    fn op_load_high_i(&mut self, addr: u32) -> ProgramCounter {
+        self.i = addr;
+        ProgramCounter::Next
+    }
+
+    fn op_load_palette(&mut self, n: usize) -> Result<ProgramCounter, VmError> {
+        let addr = self.check_ram(self.i as usize, n * 4)?;
+        if let Some(mega) = self.mega.as_mut() {
+            mega.load_palette(&self.ram[addr..addr + n * 4]);
+        }
+        Ok(ProgramCounter::Next)
+    }
+
+    fn op_play_sound(&mut self, looping: bool) -> Result<ProgramCounter, VmError> {
+        let addr = self.check_ram(self.i as usize, 0)?;
+        let (pc, opcode) = (self.pc, self.opcode);
+        if let Some(mega) = self.mega.as_mut() {
+            mega.play_sound(&self.ram[addr..], looping)
+                .ok_or(VmError::MemoryOutOfBounds { pc, opcode, addr: self.ram.len() })?;
+        }
+        Ok(ProgramCounter::Next)
+    }
+
+    fn op_mega<F: FnOnce(&mut MegaChip)>(&mut self, f: F) -> ProgramCounter {
+        if let Some(mega) = self.mega.as_mut() {
+            f(mega);
+        }
+        ProgramCounter::Next
+    }
+
+    fn op_exit(&mut self) -> ProgramCounter {
+        self.halted = true;
+        ProgramCounter::Jump(self.pc)
+    }
+
+    fn op_set_hires(&mut self, hires: bool) -> ProgramCounter {
+        self.hires = hires;
+        self.vram = [[0; SCHIP_WIDTH]; SCHIP_HEIGHT];
+        self.vram_changed = true;
+        ProgramCounter::Next
+    }
+
+    fn op_return(&mut self) -> Result<ProgramCounter, VmError> {
+        if self.sp == 0 {
+            return Err(VmError::StackUnderflow { pc: self.pc, opcode: self.opcode });
+        }
         self.sp -= 1;
-        ProgramCounter::Jump(self.stack[self.sp])
+        Ok(ProgramCounter::Jump(self.stack[self.sp]))
     }
 
     fn op_jump(&mut self, addr: Addr) -> ProgramCounter {
         ProgramCounter::Jump(addr as usize)
     }
 
-    fn op_call(&mut self, addr: Addr) -> ProgramCounter {
+    fn op_call(&mut self, addr: Addr) -> Result<ProgramCounter, VmError> {
+        if self.sp >= self.stack.len() {
+            return Err(VmError::StackOverflow { pc: self.pc, opcode: self.opcode });
+        }
         self.stack[self.sp] = self.pc + OPCODE_SIZE;
         self.sp += 1;
-        ProgramCounter::Jump(addr as usize)
+        Ok(ProgramCounter::Jump(addr as usize))
     }
 
     fn op_skip_equal_k(&mut self, x: usize, k: Byte) -> ProgramCounter {
@@ -219,19 +909,28 @@ impl VM {
 
     fn op_or(&mut self, x: usize, y: usize) -> ProgramCounter {
         self.v[x] |= self.v[y];
+        self.reset_vf();
         ProgramCounter::Next
     }
 
     fn op_and(&mut self, x: usize, y: usize) -> ProgramCounter {
         self.v[x] &= self.v[y];
+        self.reset_vf();
         ProgramCounter::Next
     }
 
     fn op_xor(&mut self, x: usize, y: usize) -> ProgramCounter {
         self.v[x] ^= self.v[y];
+        self.reset_vf();
         ProgramCounter::Next
     }
 
+    fn reset_vf(&mut self) {
+        if self.quirks.vf_reset {
+            self.v[0xf] = 0;
+        }
+    }
+
     fn op_add(&mut self, x: usize, y: usize) -> ProgramCounter {
         let r = (self.v[x] as u16) + (self.v[y] as u16);
         self.v[x] = r as u8;
@@ -245,9 +944,10 @@ impl VM {
         ProgramCounter::Next
     }
 
-    fn op_shift_right(&mut self, x: usize) -> ProgramCounter {
-        self.v[0xf] = self.v[x] & 1;
-        self.v[x] >>= 1;
+    fn op_shift_right(&mut self, x: usize, y: usize) -> ProgramCounter {
+        let src = if self.quirks.shift { self.v[x] } else { self.v[y] };
+        self.v[x] = src >> 1;
+        self.v[0xf] = src & 1;
         ProgramCounter::Next
     }
 
@@ -257,9 +957,10 @@ impl VM {
         ProgramCounter::Next
     }
 
-    fn op_shift_left(&mut self, x: usize) -> ProgramCounter {
-        self.v[0xf] = (self.v[x] & 0b10000000) >> 7;
-        self.v[x] <<= 1;
+    fn op_shift_left(&mut self, x: usize, y: usize) -> ProgramCounter {
+        let src = if self.quirks.shift { self.v[x] } else { self.v[y] };
+        self.v[x] = src << 1;
+        self.v[0xf] = (src & 0b10000000) >> 7;
         ProgramCounter::Next
     }
 
@@ -268,41 +969,85 @@ impl VM {
     }
 
     fn op_load_i(&mut self, addr: Addr) -> ProgramCounter {
-        self.i = addr as u16;
+        self.i = addr as u32;
         ProgramCounter::Next
     }
 
     fn op_long_jump(&mut self, addr: Addr) -> ProgramCounter {
-        ProgramCounter::Jump((self.v[0] as usize) + (addr as usize))
+        let x = if self.quirks.jump { (addr as usize & 0x0F00) >> 8 } else { 0 };
+        ProgramCounter::Jump((self.v[x] as usize) + (addr as usize))
     }
 
     fn op_rand(&mut self, x: usize, kk: Byte) -> ProgramCounter {
-        let rn = rand::random::<u8>();
+        let rn = self.rng.next_byte();
         self.v[x] = rn & kk;
         ProgramCounter::Next
     }
 
-    fn op_draw(&mut self, x: usize, y: usize, n: usize) -> ProgramCounter {
+    fn op_draw(&mut self, x: usize, y: usize, n: usize) -> Result<ProgramCounter, VmError> {
+        if self.mega.as_ref().is_some_and(|mega| mega.enabled) {
+            return self.op_draw_mega(x, y);
+        }
+        // SUPER-CHIP draws Dxy0 as a 16x16 sprite, two bytes per row
+        let (cols, rows) = if n == 0 && self.mode >= Mode::SuperChip { (16, 16) } else { (8, n) };
+        let row_size = cols / 8;
+        let sprite_size = rows * row_size;
+        // XO-CHIP reads one sprite per selected plane, one after the other
+        let planes = self.planes.count_ones() as usize;
+        let mut addr = self.check_ram(self.i as usize, sprite_size * planes)?;
+        self.watch_read(addr, sprite_size * planes);
+        let (width, height) = self.resolution();
         self.v[0x0f] = 0;
-        for byte in 0..n {
-            let sy = (self.v[y] as usize + byte) % CHIP8_HEIGHT;
-            for bit in 0..8 {
-                let sx = (self.v[x] as usize + bit) % CHIP8_WIDTH;
-                let color = (self.ram[self.i as usize + byte as usize] >> (7 - bit)) & 1;
-                self.v[0xf] |= color & self.vram[sy][sx];
-                self.vram[sy][sx] ^= color;
+        let x0 = self.v[x] as usize % width;
+        let y0 = self.v[y] as usize % height;
+        let selected = self.planes;
+        for &plane in [1u8, 2u8].iter().filter(|&&plane| selected & plane != 0) {
+            for row in 0..rows {
+                if self.quirks.clip && y0 + row >= height {
+                    break;
+                }
+                let sy = (y0 + row) % height;
+                for col in 0..cols {
+                    if self.quirks.clip && x0 + col >= width {
+                        break;
+                    }
+                    let sx = (x0 + col) % width;
+                    let byte = self.ram[addr + row * row_size + col / 8];
+                    if (byte >> (7 - col % 8)) & 1 == 0 {
+                        continue;
+                    }
+                    if self.vram[sy][sx] & plane != 0 {
+                        self.v[0xf] = 1;
+                    }
+                    self.vram[sy][sx] ^= plane;
+                }
             }
+            addr += sprite_size;
         }
         self.vram_changed = true;
-        ProgramCounter::Next
+        Ok(ProgramCounter::Next)
+    }
+
+    // MegaChip sprites are `sprite_width`x`sprite_height` palette indices, drawn without wrapping.
+    fn op_draw_mega(&mut self, x: usize, y: usize) -> Result<ProgramCounter, VmError> {
+        let (sx, sy) = (self.v[x] as usize, self.v[y] as usize);
+        let size = self.mega.as_ref().map_or(0, |mega| mega.sprite_size());
+        let addr = self.check_ram(self.i as usize, size)?;
+        self.watch_read(addr, size);
+        if let Some(mega) = self.mega.as_mut() {
+            let collision = mega.draw(&self.ram[addr..addr + size], sx, sy);
+            self.v[0xf] = collision as u8;
+        }
+        self.vram_changed = true;
+        Ok(ProgramCounter::Next)
     }
 
     fn op_skip_pressed(&mut self, x: usize) -> ProgramCounter {
-        ProgramCounter::skip_if(self.keypad[self.v[x] as usize])
+        ProgramCounter::skip_if(self.keypad[(self.v[x] & 0xf) as usize])
     }
 
     fn op_skip_not_pressed(&mut self, x: usize) -> ProgramCounter {
-        ProgramCounter::skip_if(! self.keypad[self.v[x] as usize])
+        ProgramCounter::skip_if(! self.keypad[(self.v[x] & 0xf) as usize])
     }
 
     fn op_get_timer(&mut self, x: usize) -> ProgramCounter {
@@ -328,35 +1073,127 @@ impl VM {
 
     fn op_add_i(&mut self, x: usize) -> ProgramCounter {
         let n: usize = self.i as usize + self.v[x] as usize;
-        self.i = n as u16;
-        self.v[0xf] = if n > 0x0F00 { 1 } else { 0 };
+        self.i = if self.mode == Mode::MegaChip { n as u32 } else { n as u32 & 0xFFFF };
+        if self.quirks.add_i_overflow {
+            self.v[0xf] = if n > 0x0F00 { 1 } else { 0 };
+        }
         ProgramCounter::Next
     }
 
     fn op_load_hex_glyph(&mut self, x: usize) -> ProgramCounter {
-        self.i = (self.v[x] as u16) * 5;
+        self.i = (self.v[x] as u32) * 5;
         ProgramCounter::Next
    }
 
-    fn op_store_bcd(&mut self, x: usize) -> ProgramCounter {
-        let i: usize = self.i as usize;
+    fn op_load_big_hex_glyph(&mut self, x: usize) -> ProgramCounter {
+        self.i = (BIG_FONT_ADDR + (self.v[x] as usize & 0xf) * 10) as u32;
+        ProgramCounter::Next
+    }
+
+    fn op_store_bcd(&mut self, x: usize) -> Result<ProgramCounter, VmError> {
+        let i = self.check_ram(self.i as usize, 3)?;
+        let old = self.watched(Access::Write, i, 3);
         self.ram[i] = self.v[x] / 100;
         self.ram[i + 1] = (self.v[x] % 100) / 10;
         self.ram[i + 2] = self.v[x] % 10;
-        ProgramCounter::Next
+        self.invalidate(i, 3);
+        if let Some(old) = old {
+            self.report(Access::Write, self.opcode, i, old);
+        }
+        Ok(ProgramCounter::Next)
     }
 
-    fn op_store_registers(&mut self, x: usize) -> ProgramCounter {
-        for i in 0..x+1 {
-            self.ram[self.i as usize + i] = self.v[i];
+    fn op_store_registers(&mut self, x: usize) -> Result<ProgramCounter, VmError> {
+        let addr = self.check_ram(self.i as usize, x + 1)?;
+        let old = self.watched(Access::Write, addr, x + 1);
+        self.ram[addr..addr + x + 1].copy_from_slice(&self.v[..x + 1]);
+        self.invalidate(addr, x + 1);
+        if let Some(old) = old {
+            self.report(Access::Write, self.opcode, addr, old);
         }
-        ProgramCounter::Next
+        if !self.quirks.load_store {
+            self.i += x as u32 + 1;
+        }
+        Ok(ProgramCounter::Next)
+    }
+
+    fn op_load_registers(&mut self, x: usize) -> Result<ProgramCounter, VmError> {
+        let addr = self.check_ram(self.i as usize, x + 1)?;
+        self.watch_read(addr, x + 1);
+        self.v[..x + 1].copy_from_slice(&self.ram[addr..addr + x + 1]);
+        if !self.quirks.load_store {
+            self.i += x as u32 + 1;
+        }
+        Ok(ProgramCounter::Next)
+    }
+
+    fn op_store_range(&mut self, x: usize, y: usize) -> Result<ProgramCounter, VmError> {
+        let len = x.max(y) - x.min(y) + 1;
+        let addr = self.check_ram(self.i as usize, len)?;
+        let old = self.watched(Access::Write, addr, len);
+        for (offset, r) in register_range(x, y).enumerate() {
+            self.ram[addr + offset] = self.v[r];
+        }
+        self.invalidate(addr, len);
+        if let Some(old) = old {
+            self.report(Access::Write, self.opcode, addr, old);
+        }
+        Ok(ProgramCounter::Next)
     }
 
-    fn op_load_registers(&mut self, x: usize) -> ProgramCounter {
-        for i in 0..x+1 {
-            self.v[i] = self.ram[self.i as usize + i];
+    fn op_load_range(&mut self, x: usize, y: usize) -> Result<ProgramCounter, VmError> {
+        let len = x.max(y) - x.min(y) + 1;
+        let addr = self.check_ram(self.i as usize, len)?;
+        self.watch_read(addr, len);
+        for (offset, r) in register_range(x, y).enumerate() {
+            self.v[r] = self.ram[addr + offset];
         }
+        Ok(ProgramCounter::Next)
+    }
+
+    fn op_plane(&mut self, n: Byte) -> ProgramCounter {
+        self.planes = n & 0b11;
         ProgramCounter::Next
     }
-}
\ No newline at end of file
+
+    fn op_load_audio(&mut self) -> Result<ProgramCounter, VmError> {
+        let addr = self.check_ram(self.i as usize, AUDIO_PATTERN_SIZE)?;
+        let mut pattern = [0; AUDIO_PATTERN_SIZE];
+        pattern.copy_from_slice(&self.ram[addr..addr + AUDIO_PATTERN_SIZE]);
+        self.audio_pattern = Some(pattern);
+        Ok(ProgramCounter::Next)
+    }
+
+    fn op_pitch(&mut self, x: usize) -> ProgramCounter {
+        self.pitch = self.v[x];
+        ProgramCounter::Next
+    }
+
+    fn op_store_flags(&mut self, x: usize) -> ProgramCounter {
+        self.flags[..x + 1].copy_from_slice(&self.v[..x + 1]);
+        ProgramCounter::Next
+    }
+
+    fn op_load_flags(&mut self, x: usize) -> ProgramCounter {
+        self.v[..x + 1].copy_from_slice(&self.flags[..x + 1]);
+        ProgramCounter::Next
+    }
+}
+
+// Keypad state as a bitmask, bit `n` set while key `n` is down.
+pub fn keypad_bits(keypad: &[bool; 16]) -> u16 {
+    keypad.iter().enumerate().fold(0, |bits, (key, &down)| bits | (down as u16) << key)
+}
+
+// Registers `Vx` to `Vy` inclusive, counting down when `x` is greater than `y`.
+fn register_range(x: usize, y: usize) -> Box<dyn Iterator<Item = usize>> {
+    if x <= y {
+        Box::new(x..=y)
+    } else {
+        Box::new((y..=x).rev())
+    }
+}
+
+#[cfg(test)]
+#[path = "./vm_test.rs"]
+mod vm_test;
@@ -0,0 +1,483 @@
+use super::*;
+use super::super::watch::WatchKind;
+
+
+#[test]
+fn test_shift_quirk() {
+    let mut vm = VM::with_quirks(Quirks::COSMAC_VIP);
+    vm.v[1] = 0b0000_0011;
+    vm.v[2] = 0b1000_0100;
+    vm.run_opcode(0x8126).unwrap();
+    assert_eq!(vm.v[1], 0b0100_0010);
+    assert_eq!(vm.v[0xf], 0);
+
+    let mut vm = VM::with_quirks(Quirks::SCHIP);
+    vm.v[1] = 0b0000_0011;
+    vm.v[2] = 0b1000_0100;
+    vm.run_opcode(0x8126).unwrap();
+    assert_eq!(vm.v[1], 0b0000_0001);
+    assert_eq!(vm.v[0xf], 1);
+}
+
+#[test]
+fn test_load_store_quirk() {
+    let mut vm = VM::with_quirks(Quirks::COSMAC_VIP);
+    vm.i = 0x300;
+    vm.run_opcode(0xF255).unwrap();
+    assert_eq!(vm.i, 0x303);
+
+    let mut vm = VM::with_quirks(Quirks::SCHIP);
+    vm.i = 0x300;
+    vm.run_opcode(0xF265).unwrap();
+    assert_eq!(vm.i, 0x300);
+}
+
+#[test]
+fn test_jump_quirk() {
+    let mut vm = VM::with_quirks(Quirks::COSMAC_VIP);
+    vm.v[0] = 0x10;
+    vm.v[3] = 0x20;
+    vm.run_opcode(0xB300).unwrap();
+    assert_eq!(vm.pc, 0x310);
+
+    let mut vm = VM::with_quirks(Quirks::SCHIP);
+    vm.v[0] = 0x10;
+    vm.v[3] = 0x20;
+    vm.run_opcode(0xB300).unwrap();
+    assert_eq!(vm.pc, 0x320);
+}
+
+#[test]
+fn test_clip_quirk() {
+    let mut vm = VM::new();
+    vm.i = 0x300;
+    vm.ram[0x300] = 0xFF;
+    vm.v[0] = 60;
+    vm.run_opcode(0xD011).unwrap();
+    assert_eq!(vm.vram[0][63], 1);
+    assert_eq!(vm.vram[0][0], 1);
+
+    let mut vm = VM::with_quirks(Quirks::COSMAC_VIP);
+    vm.i = 0x300;
+    vm.ram[0x300] = 0xFF;
+    vm.v[0] = 60;
+    vm.run_opcode(0xD011).unwrap();
+    assert_eq!(vm.vram[0][63], 1);
+    assert_eq!(vm.vram[0][0], 0);
+}
+
+#[test]
+fn test_vf_reset_quirk() {
+    let mut vm = VM::with_quirks(Quirks::COSMAC_VIP);
+    vm.v[0xf] = 1;
+    vm.run_opcode(0x8011).unwrap();
+    assert_eq!(vm.v[0xf], 0);
+
+    let mut vm = VM::with_quirks(Quirks::SCHIP);
+    vm.v[0xf] = 1;
+    vm.run_opcode(0x8011).unwrap();
+    assert_eq!(vm.v[0xf], 1);
+}
+
+#[test]
+fn test_add_i_overflow_quirk() {
+    let mut vm = VM::new();
+    vm.i = 0x0F00;
+    vm.v[0] = 1;
+    vm.run_opcode(0xF01E).unwrap();
+    assert_eq!(vm.v[0xf], 1);
+    vm.i = 0x0EFF;
+    vm.run_opcode(0xF01E).unwrap();
+    assert_eq!(vm.v[0xf], 0);
+
+    let mut vm = VM::with_quirks(Quirks::COSMAC_VIP);
+    vm.i = 0x0FFF;
+    vm.v[0] = 1;
+    vm.v[0xf] = 7;
+    vm.run_opcode(0xF01E).unwrap();
+    assert_eq!(vm.v[0xf], 7);
+}
+
+#[test]
+fn test_stack_overflow() {
+    let mut vm = VM::new();
+    vm.pc = 0x300;
+    for _ in 0..16 {
+        vm.run_opcode(0x2300).unwrap();
+    }
+    let err = vm.run_opcode(0x2300).err();
+    assert_eq!(err, Some(VmError::StackOverflow { pc: 0x300, opcode: 0x2300 }));
+}
+
+#[test]
+fn test_stack_underflow() {
+    let mut vm = VM::new();
+    let err = vm.run_opcode(0x00EE).err();
+    assert_eq!(err, Some(VmError::StackUnderflow { pc: 0x200, opcode: 0x00EE }));
+}
+
+#[test]
+fn test_pc_out_of_bounds() {
+    let mut vm = VM::new();
+    vm.ram[0x200] = 0x1F;
+    vm.ram[0x201] = 0xFF;
+    vm.step([false; 16]).unwrap();
+    let err = vm.step([false; 16]).err();
+    assert_eq!(err, Some(VmError::PcOutOfBounds { pc: 0xFFF, opcode: 0 }));
+}
+
+#[test]
+fn test_memory_out_of_bounds() {
+    let mut vm = VM::new();
+    vm.i = 0xFFE;
+    let err = vm.run_opcode(0xF033).err();
+    assert_eq!(err, Some(VmError::MemoryOutOfBounds { pc: 0x200, opcode: 0xF033, addr: 0x1000 }));
+
+    vm.i = 0xFF1;
+    let err = vm.run_opcode(0xFF65).err();
+    assert_eq!(err, Some(VmError::MemoryOutOfBounds { pc: 0x200, opcode: 0xFF65, addr: 0x1000 }));
+}
+
+#[test]
+fn test_unknown_opcode() {
+    let mut vm = VM::new();
+    let err = vm.run_opcode(0x5121).err();
+    assert_eq!(err, Some(VmError::UnknownOpcode { pc: 0x200, opcode: 0x5121 }));
+}
+
+#[test]
+fn test_timers_tick_per_frame() {
+    let mut vm = VM::new();
+    vm.delay_timer = 10;
+    vm.sound_timer = 1;
+    vm.ram[0x200] = 0x12;
+    for _ in 0..8 {
+        vm.step([false; 16]).unwrap();
+    }
+    assert_eq!(vm.delay_timer, 10);
+
+    vm.run_frame([false; 16], 8).unwrap();
+    assert_eq!(vm.delay_timer, 9);
+    assert_eq!(vm.sound_timer, 0);
+}
+
+#[test]
+fn test_wait_key_press_and_release() {
+    let mut held = [false; 16];
+    held[3] = true;
+    let mut vm = VM::new();
+    vm.ram[0x200] = 0xF5;
+    vm.ram[0x201] = 0x0A;
+    vm.delay_timer = 5;
+
+    assert!(vm.step(held).unwrap().waiting);
+    // a key held since before the wait doesn't count
+    assert!(vm.step(held).unwrap().waiting);
+    assert!(vm.run_frame(held, 1).unwrap().waiting);
+    assert_eq!(vm.delay_timer, 4);
+
+    let mut pressed = held;
+    pressed[7] = true;
+    assert!(vm.step(pressed).unwrap().waiting);
+    assert!(vm.step(pressed).unwrap().waiting);
+    assert!(!vm.step(held).unwrap().waiting);
+    assert_eq!(vm.v[5], 7);
+    assert_eq!(vm.pc, 0x202);
+
+    // Single stepping doesn't read keys, so it can't end the wait
+    let mut vm = VM::new();
+    vm.ram[0x200] = 0xF5;
+    vm.ram[0x201] = 0x0A;
+    vm.step_instruction().unwrap();
+    assert!(vm.output().waiting);
+    let mut seven = [false; 16];
+    seven[7] = true;
+    vm.step(seven).unwrap();
+    vm.step_instruction().unwrap();
+    assert!(vm.output().waiting);
+    assert_eq!(vm.pc, 0x202);
+    vm.step([false; 16]).unwrap();
+    assert!(!vm.output().waiting);
+    assert_eq!(vm.v[5], 7);
+}
+
+#[test]
+fn test_schip_requires_mode() {
+    let mut vm = VM::new();
+    let err = vm.run_opcode(0x00FF).err();
+    assert_eq!(err, Some(VmError::UnknownOpcode { pc: 0x200, opcode: 0x00FF }));
+
+    let mut vm = VM::with_mode(Mode::SuperChip, Quirks::SCHIP);
+    let output = vm.run_opcode(0x00FF).unwrap();
+    assert_eq!((output.width, output.height), (SCHIP_WIDTH, SCHIP_HEIGHT));
+    let output = vm.run_opcode(0x00FE).unwrap();
+    assert_eq!((output.width, output.height), (CHIP8_WIDTH, CHIP8_HEIGHT));
+}
+
+#[test]
+fn test_schip_large_sprite() {
+    let mut vm = VM::with_mode(Mode::SuperChip, Quirks::SCHIP);
+    vm.run_opcode(0x00FF).unwrap();
+    vm.i = 0x300;
+    for i in 0..32 {
+        vm.ram[0x300 + i] = 0xFF;
+    }
+    vm.v[0] = 100;
+    vm.v[1] = 40;
+    vm.run_opcode(0xD010).unwrap();
+    assert_eq!(vm.vram[40][100], 1);
+    assert_eq!(vm.vram[55][115], 1);
+    assert_eq!(vm.vram[56][116], 0);
+    assert_eq!(vm.v[0xf], 0);
+}
+
+#[test]
+fn test_schip_scroll() {
+    let mut vm = VM::with_mode(Mode::SuperChip, Quirks::SCHIP);
+    vm.vram[0][10] = 1;
+    vm.run_opcode(0x00C3).unwrap();
+    assert_eq!(vm.vram[0][10], 0);
+    assert_eq!(vm.vram[3][10], 1);
+    vm.run_opcode(0x00FB).unwrap();
+    assert_eq!(vm.vram[3][14], 1);
+    vm.run_opcode(0x00FC).unwrap();
+    vm.run_opcode(0x00FC).unwrap();
+    assert_eq!(vm.vram[3][6], 1);
+    assert_eq!(vm.vram[3][14], 0);
+}
+
+#[test]
+fn test_schip_big_font_and_flags() {
+    let mut vm = VM::with_mode(Mode::SuperChip, Quirks::SCHIP);
+    vm.v[2] = 3;
+    vm.run_opcode(0xF230).unwrap();
+    assert_eq!(vm.i as usize, BIG_FONT_ADDR + 30);
+
+    vm.v[0] = 1;
+    vm.v[1] = 2;
+    vm.run_opcode(0xF175).unwrap();
+    vm.v[0] = 0;
+    vm.v[1] = 0;
+    vm.run_opcode(0xF185).unwrap();
+    assert_eq!(&vm.v[..2], &[1, 2]);
+}
+
+#[test]
+fn test_schip_exit() {
+    let mut vm = VM::with_mode(Mode::SuperChip, Quirks::SCHIP);
+    vm.ram[0x200] = 0x00;
+    vm.ram[0x201] = 0xFD;
+    assert!(vm.step([false; 16]).unwrap().halted);
+    assert!(vm.step([false; 16]).unwrap().halted);
+    assert_eq!(vm.pc, 0x200);
+}
+
+#[test]
+fn test_xo_long_load_i() {
+    let mut vm = VM::with_mode(Mode::XoChip, Quirks::XO_CHIP);
+    vm.ram[0x200..0x208].copy_from_slice(&[0x30, 0x00, 0xF0, 0x00, 0xBE, 0xEF, 0xF0, 0x00]);
+    vm.ram[0x208..0x20A].copy_from_slice(&[0xC0, 0x00]);
+    // 3000 skips over the whole 4 byte instruction
+    vm.step([false; 16]).unwrap();
+    assert_eq!(vm.pc, 0x206);
+    vm.step([false; 16]).unwrap();
+    assert_eq!(vm.i, 0xC000);
+    assert_eq!(vm.pc, 0x20A);
+
+    let mut vm = VM::new();
+    let err = vm.run_opcode(0xF000).err();
+    assert_eq!(err, Some(VmError::UnknownOpcode { pc: 0x200, opcode: 0xF000 }));
+}
+
+#[test]
+fn test_xo_memory() {
+    let mut vm = VM::with_mode(Mode::XoChip, Quirks::XO_CHIP);
+    vm.i = 0xFFF0;
+    vm.v[0] = 123;
+    vm.run_opcode(0xF033).unwrap();
+    assert_eq!(&vm.ram[0xFFF0..0xFFF3], &[1, 2, 3]);
+
+    let mut vm = VM::with_mode(Mode::SuperChip, Quirks::SCHIP);
+    vm.i = 0xFFF0;
+    assert!(vm.run_opcode(0xF033).is_err());
+}
+
+#[test]
+fn test_xo_register_ranges() {
+    let mut vm = VM::with_mode(Mode::XoChip, Quirks::XO_CHIP);
+    vm.i = 0x300;
+    vm.v[2] = 2;
+    vm.v[3] = 3;
+    vm.v[4] = 4;
+    vm.run_opcode(0x5242).unwrap();
+    assert_eq!(&vm.ram[0x300..0x303], &[2, 3, 4]);
+    vm.run_opcode(0x5422).unwrap();
+    assert_eq!(&vm.ram[0x300..0x303], &[4, 3, 2]);
+    vm.run_opcode(0x5683).unwrap();
+    assert_eq!(&vm.v[6..9], &[4, 3, 2]);
+    assert_eq!(vm.i, 0x300);
+}
+
+#[test]
+fn test_xo_planes() {
+    let mut vm = VM::with_mode(Mode::XoChip, Quirks::XO_CHIP);
+    vm.i = 0x300;
+    vm.ram[0x300] = 0x80;
+    vm.ram[0x301] = 0xC0;
+    vm.run_opcode(0xF301).unwrap();
+    vm.run_opcode(0xD011).unwrap();
+    assert_eq!(vm.vram[0][0], 0b11);
+    assert_eq!(vm.vram[0][1], 0b10);
+
+    vm.run_opcode(0xF101).unwrap();
+    vm.run_opcode(0x00E0).unwrap();
+    assert_eq!(vm.vram[0][0], 0b10);
+    assert_eq!(vm.vram[0][1], 0b10);
+}
+
+#[test]
+fn test_xo_audio() {
+    let mut vm = VM::with_mode(Mode::XoChip, Quirks::XO_CHIP);
+    vm.i = 0x300;
+    vm.ram[0x300] = 0xAA;
+    vm.v[0] = 112;
+    vm.run_opcode(0xF03A).unwrap();
+    let audio = vm.run_opcode(0xF002).unwrap().audio.unwrap();
+    assert_eq!(audio.pattern[0], 0xAA);
+    assert!(audio.bit(0));
+    assert!(!audio.bit(1));
+    assert_eq!(audio.rate(), 8000.0);
+}
+
+#[test]
+fn test_mega_requires_mode() {
+    let mut vm = VM::with_mode(Mode::SuperChip, Quirks::SCHIP);
+    let err = vm.run_opcode(0x0011).err();
+    assert_eq!(err, Some(VmError::UnknownOpcode { pc: 0x200, opcode: 0x0011 }));
+
+    let mut vm = VM::with_mode(Mode::MegaChip, Quirks::SCHIP);
+    assert!(vm.run_opcode(0x0011).unwrap().mega.is_some());
+    assert!(vm.run_opcode(0x0010).unwrap().mega.is_none());
+}
+
+#[test]
+fn test_mega_long_load_i() {
+    let mut vm = VM::with_mode(Mode::MegaChip, Quirks::SCHIP);
+    vm.ram[0x200..0x204].copy_from_slice(&[0x01, 0x12, 0x34, 0x56]);
+    vm.step([false; 16]).unwrap();
+    assert_eq!(vm.i, 0x123456);
+    assert_eq!(vm.pc, 0x204);
+}
+
+#[test]
+fn test_mega_draw() {
+    let mut vm = VM::with_mode(Mode::MegaChip, Quirks::SCHIP);
+    vm.run_opcode(0x0011).unwrap();
+    vm.i = 0x300;
+    vm.ram[0x300..0x308].copy_from_slice(&[0xFF, 0x10, 0x20, 0x30, 0xFF, 0x40, 0x50, 0x60]);
+    vm.run_opcode(0x0202).unwrap();
+    vm.run_opcode(0x0302).unwrap();
+    vm.run_opcode(0x0401).unwrap();
+    vm.run_opcode(0x0901).unwrap();
+    vm.i = 0x310;
+    vm.ram[0x310..0x312].copy_from_slice(&[1, 2]);
+    vm.v[0] = 10;
+    vm.v[1] = 20;
+    vm.run_opcode(0xD011).unwrap();
+    assert_eq!(vm.v[0xf], 0);
+    vm.run_opcode(0xD011).unwrap();
+    assert_eq!(vm.v[0xf], 1);
+
+    // nothing is shown until 00E0 presents the back buffer
+    assert_eq!(vm.output().mega.unwrap().pixels[20 * 256 + 10], 0);
+    let output = vm.run_opcode(0x00E0).unwrap();
+    let pixels = output.mega.unwrap().pixels;
+    assert_eq!(pixels[20 * 256 + 10], 0xFF102030);
+    assert_eq!(pixels[20 * 256 + 11], 0xFF405060);
+}
+
+#[test]
+fn test_mega_sound() {
+    let mut vm = VM::with_mode(Mode::MegaChip, Quirks::SCHIP);
+    vm.run_opcode(0x0011).unwrap();
+    vm.i = 0x300;
+    vm.ram[0x300..0x309].copy_from_slice(&[0x1F, 0x40, 0x00, 0x00, 0x03, 0x00, 1, 2, 3]);
+    let output = vm.run_opcode(0x0600).unwrap();
+    let sound = output.mega.unwrap().sound.unwrap();
+    assert_eq!(sound.rate, 8000);
+    assert_eq!(sound.samples, vec![1, 2, 3]);
+    assert!(sound.looping);
+    assert!(vm.run_opcode(0x0700).unwrap().mega.unwrap().sound.is_none());
+}
+
+#[test]
+fn test_seeded_rand() {
+    let rand = |vm: &mut VM| (0..8).map(|_| {
+        vm.run_opcode(0xC0FF).unwrap();
+        vm.v[0]
+    }).collect::<Vec<u8>>();
+
+    let mut a = VM::with_seed(Mode::Chip8, Quirks::default(), 1);
+    let mut b = VM::with_seed(Mode::Chip8, Quirks::default(), 1);
+    assert_eq!(rand(&mut a), rand(&mut b));
+
+    let snapshot = a.snapshot();
+    let expected = rand(&mut a);
+    a.restore(&snapshot).unwrap();
+    assert_eq!(rand(&mut a), expected);
+}
+
+#[test]
+fn test_watchpoints() {
+    // LD V0, 123; LD I, 0x300; LD B, V0; DRW V1, V1, 3
+    let mut vm = VM::new();
+    vm.load(&[0x60, 0x7B, 0xA3, 0x00, 0xF0, 0x33, 0xD1, 0x13]);
+    vm.set_watchpoints(vec![
+        Watchpoint { start: 0x202, end: 0x202, kind: WatchKind::Read },
+        Watchpoint { start: 0x301, end: 0x301, kind: WatchKind::Write },
+        Watchpoint { start: 0x300, end: 0x300, kind: WatchKind::Read },
+    ]);
+    for _ in 0..4 {
+        vm.step([false; 16]).unwrap();
+    }
+    let hits: Vec<String> = vm.take_watch_hits().iter().map(|hit| hit.to_string()).collect();
+    assert_eq!(hits, vec![
+        "0x0202 LD I, 0x300 read 0x0202: a3 00",
+        "0x0204 LD B, V0 wrote 0x0300: 00 00 00 -> 01 02 03",
+        "0x0206 DRW V1, V1, 3 read 0x0300: 01 02 03",
+    ]);
+    assert!(vm.take_watch_hits().is_empty());
+}
+
+#[test]
+fn test_self_modifying_code() {
+    // LD I, 0x203; LD V3, 1; ADD V0, 1; LD [I], V0; JP 0x202, rewriting the second instruction
+    // to load V0 each time around the loop
+    let mut vm = VM::new();
+    vm.load(&[0xA2, 0x03, 0x63, 0x01, 0x70, 0x01, 0xF0, 0x55, 0x12, 0x02]);
+    for _ in 0..6 {
+        vm.step([false; 16]).unwrap();
+    }
+    assert_eq!(vm.v[3], 1);
+    for _ in 0..4 {
+        vm.step([false; 16]).unwrap();
+    }
+    assert_eq!(vm.v[3], 2);
+
+    vm.memory_mut()[0x203] = 0x09;
+    vm.pc = 0x202;
+    vm.step([false; 16]).unwrap();
+    assert_eq!(vm.v[3], 9);
+
+    // Writing the second word of a 4 byte instruction
+    let mut vm = VM::with_mode(Mode::XoChip, Quirks::XO_CHIP);
+    vm.load(&[0xF0, 0x00, 0x12, 0x34]);
+    vm.step([false; 16]).unwrap();
+    assert_eq!(vm.i, 0x1234);
+    vm.i = 0x203;
+    vm.v[0] = 0x56;
+    vm.run_opcode(0xF055).unwrap();
+    vm.pc = 0x200;
+    vm.step([false; 16]).unwrap();
+    assert_eq!(vm.i, 0x1256);
+}
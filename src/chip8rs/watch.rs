@@ -0,0 +1,80 @@
+use std::fmt;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Access {
+    Read,
+    Write,
+}
+
+// Which accesses a watchpoint reports.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum WatchKind {
+    Read,
+    Write,
+    ReadWrite,
+}
+
+impl WatchKind {
+    pub fn from_name(name: &str) -> Option<WatchKind> {
+        match name {
+            "r" | "read" => Some(WatchKind::Read),
+            "w" | "write" => Some(WatchKind::Write),
+            "rw" | "access" => Some(WatchKind::ReadWrite),
+            _ => None,
+        }
+    }
+
+    pub fn matches(self, access: Access) -> bool {
+        match self {
+            WatchKind::Read => access == Access::Read,
+            WatchKind::Write => access == Access::Write,
+            WatchKind::ReadWrite => true,
+        }
+    }
+}
+
+// Memory from `start` to `end` inclusive, watched by `VM::set_watchpoints`. Reads are instruction
+// fetches, sprites drawn by DRW and registers loaded by Fx65 or 5xy3; writes are Fx33, Fx55 and
+// 5xy2.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Watchpoint {
+    pub start: usize,
+    pub end: usize,
+    pub kind: WatchKind,
+}
+
+impl Watchpoint {
+    // Whether an access of `len` bytes from `addr` touches the watched memory.
+    pub fn overlaps(&self, addr: usize, len: usize) -> bool {
+        len > 0 && addr <= self.end && addr + len > self.start
+    }
+}
+
+// One access to watched memory: the whole access, not just the watched part of it. `old` and `new`
+// are the same for reads.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct WatchHit {
+    pub pc: usize,
+    pub opcode: u16,
+    pub instruction: String,
+    pub access: Access,
+    pub addr: usize,
+    pub old: Vec<u8>,
+    pub new: Vec<u8>,
+}
+
+// "0x0206 LD B, V0 wrote 0x0300: 00 00 00 -> 01 02 03"
+impl fmt::Display for WatchHit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let hex = |bytes: &[u8]| bytes.iter().map(|b| format!("{:02x}", b)).collect::<Vec<_>>().join(" ");
+        write!(f, "0x{:04x} {} ", self.pc, self.instruction)?;
+        match self.access {
+            Access::Read => write!(f, "read 0x{:04x}: {}", self.addr, hex(&self.new)),
+            Access::Write => write!(f, "wrote 0x{:04x}: {} -> {}", self.addr, hex(&self.old), hex(&self.new)),
+        }
+    }
+}
+
+#[cfg(test)]
+#[path = "./watch_test.rs"]
+mod watch_test;
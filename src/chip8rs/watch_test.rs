@@ -0,0 +1,22 @@
+use super::*;
+
+
+#[test]
+fn test_overlaps() {
+    let watchpoint = Watchpoint { start: 0x300, end: 0x302, kind: WatchKind::Write };
+    assert!(watchpoint.overlaps(0x2FE, 3));
+    assert!(watchpoint.overlaps(0x302, 16));
+    assert!(!watchpoint.overlaps(0x2FE, 2));
+    assert!(!watchpoint.overlaps(0x303, 1));
+    assert!(!watchpoint.overlaps(0x300, 0));
+}
+
+#[test]
+fn test_kinds() {
+    assert_eq!(WatchKind::from_name("rw"), Some(WatchKind::ReadWrite));
+    assert_eq!(WatchKind::from_name("write"), Some(WatchKind::Write));
+    assert_eq!(WatchKind::from_name("x"), None);
+    assert!(WatchKind::Read.matches(Access::Read));
+    assert!(!WatchKind::Read.matches(Access::Write));
+    assert!(WatchKind::ReadWrite.matches(Access::Write));
+}
@@ -0,0 +1,214 @@
+use std::path::PathBuf;
+use chip8rs::args::{positive, Rng};
+use chip8rs::audio::{Tone, Waveform};
+use chip8rs::quirks::Quirks;
+use chip8rs::rewind::{DEFAULT_BUDGET, DEFAULT_LENGTH};
+use chip8rs::scheduler::{DEFAULT_IPS, FRAMES_PER_SECOND};
+use chip8rs::ui::DEFAULT_SCALE;
+
+pub const USAGE: &str = "\
+usage: chip8rs [options] <rom>
+       chip8rs disasm [--dot] <rom>
+       chip8rs asm <source> [-o <rom>]
+       chip8rs dap [--port <n>]
+
+<rom> is a ROM file, a .gz or .zip holding one, or - to read it from stdin. disasm prints a
+listing of the ROM instead of running it, or with --dot its control flow graph for Graphviz. asm
+assembles a source file into a ROM, by default named after the source with a .ch8 extension. dap
+serves the Debug Adapter Protocol for editors on stdin and stdout, or on a TCP port of localhost.
+
+options:
+  --ips <n>             instructions per second (default 500)
+  --scale <n>           window pixels per CHIP-8 pixel (default 5)
+  --quirks <preset>     chip8rs, vip, chip48, schip or xo-chip (default: per mode and roms.json)
+  --seed <n>            seed for the random number generator (default: random)
+  --rng <rng>           splitmix, or vip:<file> for the COSMAC VIP's algorithm, reading the
+                        512 byte interpreter image in <file> (default splitmix)
+  --fullscreen          start in fullscreen
+  --paused              start paused, P toggles pause
+  --load-state <file>   restore a save state after loading the ROM
+  --debug               start in the debugger, reading commands from stdin (try help)
+  --rewind-seconds <n>  how far back Backspace can rewind (default 30)
+  --rewind-budget <mb>  memory for rewinding, cutting it short when full (default 16)
+  --waveform <wave>     beep waveform: square, triangle or sine (default square)
+  --frequency <hz>      beep frequency (default 440)
+  --volume <n>          volume from 0 to 1 (default 0.25)
+  -h, --help            print this help
+
+keys:
+  1234/QWER/ASDF/ZXCV   CHIP-8 keypad
+  F1-F9                 save state to slot, Shift loads it
+  F10, F11              record a movie from the current state, play it back
+  Backspace             rewind
+  P                     pause
+
+exit status: 0 on quit, 1 when the ROM faults, 64 on bad arguments, 65 when the ROM, save state
+or source can't be used, 66 when the ROM or source is missing, 74 when it can't be read or written.
+";
+
+pub const MIB: usize = 1024 * 1024;
+
+#[derive(PartialEq, Debug)]
+pub struct Options {
+    pub rom: PathBuf,
+    pub ips: u32,
+    pub scale: u32,
+    pub quirks: Option<Quirks>,  // `None` picks them from the mode and roms.json
+    pub seed: Option<u64>,
+    pub rng: Rng,
+    pub fullscreen: bool,
+    pub paused: bool,
+    pub load_state: Option<PathBuf>,
+    pub debug: bool,
+    pub rewind_seconds: u32,
+    pub rewind_budget: u32,  // MiB
+    pub tone: Tone,
+}
+
+#[derive(PartialEq, Debug)]
+pub enum Action {
+    Run(Options),
+    Disasm { rom: PathBuf, dot: bool },
+    Asm { source: PathBuf, output: PathBuf },
+    Dap { port: Option<u16> },
+    Help,
+}
+
+// Parses the arguments following the program name.
+pub fn parse<I: IntoIterator<Item = String>>(args: I) -> Result<Action, String> {
+    let mut args = args.into_iter().peekable();
+    if args.peek().map(String::as_str) == Some("disasm") {
+        args.next();
+        return parse_disasm(args);
+    }
+    if args.peek().map(String::as_str) == Some("asm") {
+        args.next();
+        return parse_asm(args);
+    }
+    if args.peek().map(String::as_str) == Some("dap") {
+        args.next();
+        return parse_dap(args);
+    }
+
+    let mut rom = None;
+    let mut options = Options {
+        rom: PathBuf::new(),
+        ips: DEFAULT_IPS,
+        scale: DEFAULT_SCALE,
+        quirks: None,
+        seed: None,
+        rng: Rng::SplitMix64,
+        fullscreen: false,
+        paused: false,
+        load_state: None,
+        debug: false,
+        rewind_seconds: (DEFAULT_LENGTH / FRAMES_PER_SECOND as usize) as u32,
+        rewind_budget: (DEFAULT_BUDGET / MIB) as u32,
+        tone: Tone::default(),
+    };
+
+    while let Some(arg) = args.next() {
+        // Both "--ips 700" and "--ips=700"
+        let (name, inline) = match arg.find('=') {
+            Some(n) if arg.starts_with("--") => (arg[..n].to_string(), Some(arg[n + 1..].to_string())),
+            _ => (arg.clone(), None),
+        };
+        let mut value = || inline.clone().or_else(|| args.next()).ok_or(format!("{} needs a value", name));
+        match name.as_str() {
+            "-h" | "--help" => return Ok(Action::Help),
+            "--ips" => options.ips = positive(&name, &value()?)?,
+            "--scale" => options.scale = positive(&name, &value()?)?,
+            "--quirks" => {
+                let preset = value()?;
+                let quirks = Quirks::preset(&preset).ok_or_else(|| {
+                    format!("unknown quirks preset {:?}, expected one of {}", preset, Quirks::PRESETS.join(", "))
+                })?;
+                options.quirks = Some(quirks);
+            }
+            "--seed" => {
+                let seed = value()?;
+                options.seed = Some(seed.parse().map_err(|_| format!("--seed: invalid number {:?}", seed))?);
+            }
+            "--rng" => options.rng = Rng::parse(&value()?)?,
+            "--fullscreen" => options.fullscreen = true,
+            "--paused" => options.paused = true,
+            "--debug" => options.debug = true,
+            "--load-state" => options.load_state = Some(PathBuf::from(value()?)),
+            "--rewind-seconds" => options.rewind_seconds = positive(&name, &value()?)?,
+            "--rewind-budget" => options.rewind_budget = positive(&name, &value()?)?,
+            "--waveform" => {
+                let name = value()?;
+                options.tone.waveform = Waveform::from_name(&name).ok_or_else(|| {
+                    format!("unknown waveform {:?}, expected one of {}", name, Waveform::NAMES.join(", "))
+                })?;
+            }
+            "--frequency" => options.tone.frequency = ranged(&name, &value()?, 20.0, 20000.0)?,
+            "--volume" => options.tone.volume = ranged(&name, &value()?, 0.0, 1.0)? as f32,
+            _ if name.starts_with('-') && name != "-" => return Err(format!("unknown option {}", name)),
+            _ if rom.is_none() => rom = Some(PathBuf::from(arg)),
+            _ => return Err(format!("unexpected argument {:?}", arg)),
+        }
+    }
+
+    options.rom = rom.ok_or("no ROM given")?;
+    Ok(Action::Run(options))
+}
+
+fn parse_disasm<I: Iterator<Item = String>>(args: I) -> Result<Action, String> {
+    let mut rom = None;
+    let mut dot = false;
+    for arg in args {
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(Action::Help),
+            "--dot" => dot = true,
+            _ if arg.starts_with('-') && arg != "-" => return Err(format!("unknown option {}", arg)),
+            _ if rom.is_none() => rom = Some(PathBuf::from(arg)),
+            _ => return Err(format!("unexpected argument {:?}", arg)),
+        }
+    }
+    Ok(Action::Disasm { rom: rom.ok_or("no ROM given")?, dot })
+}
+
+fn parse_asm<I: Iterator<Item = String>>(mut args: I) -> Result<Action, String> {
+    let mut source = None;
+    let mut output = None;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(Action::Help),
+            "-o" => output = Some(PathBuf::from(args.next().ok_or("-o needs a value")?)),
+            _ if arg.starts_with('-') => return Err(format!("unknown option {}", arg)),
+            _ if source.is_none() => source = Some(PathBuf::from(arg)),
+            _ => return Err(format!("unexpected argument {:?}", arg)),
+        }
+    }
+    let source: PathBuf = source.ok_or("no source given")?;
+    let output = output.unwrap_or_else(|| source.with_extension("ch8"));
+    Ok(Action::Asm { source, output })
+}
+
+fn parse_dap<I: Iterator<Item = String>>(mut args: I) -> Result<Action, String> {
+    let mut port = None;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(Action::Help),
+            "--port" => {
+                let value = args.next().ok_or("--port needs a value")?;
+                port = Some(value.parse().map_err(|_| format!("--port: invalid port {:?}", value))?);
+            }
+            _ if arg.starts_with('-') => return Err(format!("unknown option {}", arg)),
+            _ => return Err(format!("unexpected argument {:?}", arg)),
+        }
+    }
+    Ok(Action::Dap { port })
+}
+
+fn ranged(name: &str, value: &str, min: f64, max: f64) -> Result<f64, String> {
+    match value.parse() {
+        Ok(n) if n >= min && n <= max => Ok(n),
+        _ => Err(format!("{}: expected a number from {} to {}, got {:?}", name, min, max, value)),
+    }
+}
+
+#[cfg(test)]
+#[path = "./cli_test.rs"]
+mod cli_test;
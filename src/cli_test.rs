@@ -0,0 +1,88 @@
+use super::*;
+
+
+fn args(line: &str) -> Vec<String> {
+    line.split_whitespace().map(String::from).collect()
+}
+
+#[test]
+fn test_defaults() {
+    let action = parse(args("games/Pong.ch8")).unwrap();
+    assert_eq!(action, Action::Run(Options {
+        rom: PathBuf::from("games/Pong.ch8"),
+        ips: DEFAULT_IPS,
+        scale: DEFAULT_SCALE,
+        quirks: None,
+        seed: None,
+        rng: Rng::SplitMix64,
+        fullscreen: false,
+        paused: false,
+        load_state: None,
+        debug: false,
+        rewind_seconds: 30,
+        rewind_budget: 16,
+        tone: Tone::default(),
+    }));
+}
+
+#[test]
+fn test_options() {
+    let line = "--ips 700 --scale=4 --quirks vip --seed 42 --rng vip:vip.bin --fullscreen --paused --load-state s --debug \
+                --rewind-seconds 60 --rewind-budget=64 --waveform sine --frequency 880 --volume 0.5 rom.ch8";
+    let action = parse(args(line)).unwrap();
+    assert_eq!(action, Action::Run(Options {
+        rom: PathBuf::from("rom.ch8"),
+        ips: 700,
+        scale: 4,
+        quirks: Some(Quirks::COSMAC_VIP),
+        seed: Some(42),
+        rng: Rng::CosmacVip(PathBuf::from("vip.bin")),
+        fullscreen: true,
+        paused: true,
+        load_state: Some(PathBuf::from("s")),
+        debug: true,
+        rewind_seconds: 60,
+        rewind_budget: 64,
+        tone: Tone { waveform: Waveform::Sine, frequency: 880.0, volume: 0.5 },
+    }));
+    assert_eq!(parse(args("rom.ch8 --help")).unwrap(), Action::Help);
+}
+
+#[test]
+fn test_disasm() {
+    let disasm = |rom: &str, dot: bool| Action::Disasm { rom: PathBuf::from(rom), dot };
+    assert_eq!(parse(args("disasm rom.ch8")).unwrap(), disasm("rom.ch8", false));
+    assert_eq!(parse(args("disasm -")).unwrap(), disasm("-", false));
+    assert_eq!(parse(args("disasm --dot rom.ch8")).unwrap(), disasm("rom.ch8", true));
+    assert!(parse(args("disasm")).is_err());
+    assert!(parse(args("disasm --ips 5 rom.ch8")).is_err());
+}
+
+#[test]
+fn test_asm() {
+    let asm = |source: &str, output: &str| Action::Asm { source: PathBuf::from(source), output: PathBuf::from(output) };
+    assert_eq!(parse(args("asm in.s -o out.ch8")).unwrap(), asm("in.s", "out.ch8"));
+    assert_eq!(parse(args("asm tests/in.s")).unwrap(), asm("tests/in.s", "tests/in.ch8"));
+    assert!(parse(args("asm")).is_err());
+    assert!(parse(args("asm in.s -o")).is_err());
+}
+
+#[test]
+fn test_dap() {
+    assert_eq!(parse(args("dap")).unwrap(), Action::Dap { port: None });
+    assert_eq!(parse(args("dap --port 4711")).unwrap(), Action::Dap { port: Some(4711) });
+    assert!(parse(args("dap --port 70000")).is_err());
+    assert!(parse(args("dap rom.ch8")).is_err());
+}
+
+#[test]
+fn test_errors() {
+    assert!(parse(args("")).is_err());
+    assert!(parse(args("a.ch8 b.ch8")).is_err());
+    assert!(parse(args("--ips 0 a.ch8")).is_err());
+    assert!(parse(args("--ips")).is_err());
+    assert!(parse(args("--quirks nope a.ch8")).is_err());
+    assert!(parse(args("--frobnicate a.ch8")).is_err());
+    assert!(parse(args("--volume 2 a.ch8")).is_err());
+    assert!(parse(args("--waveform saw a.ch8")).is_err());
+}
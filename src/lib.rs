@@ -0,0 +1,3 @@
+mod chip8rs;
+
+pub use crate::chip8rs::*;
@@ -1,35 +1,134 @@
 extern crate rand;
 
-use std::fs::File;
-use std::io::prelude::*;
-use std::thread;
-use std::time::Duration;
-mod chip8rs;
-
-
-fn load_rom(path: &str, buf: &mut [u8; 4096]) -> usize {
-    let mut f = File::open(path).expect("file not found");
-    match f.read(buf) {
-        Err(_) => 0,
-        Ok(n) => n,
+use std::env;
+use std::fs;
+use std::io;
+use std::net::TcpListener;
+use std::path::Path;
+use std::process;
+mod cli;
+
+use chip8rs::args::{rom_exit_status, EXIT_DATA, EXIT_FAULT, EXIT_IO, EXIT_NO_INPUT, EXIT_USAGE};
+use chip8rs::asm::AsmError;
+use chip8rs::debugger::Debugger;
+use chip8rs::rewind::Rewind;
+use chip8rs::rom::Rom;
+use chip8rs::scheduler::FRAMES_PER_SECOND;
+
+
+// Exit status for a source which can't be assembled.
+fn asm_exit_status(err: &AsmError) -> i32 {
+    match err {
+        AsmError::Io(_, err) if err.kind() == io::ErrorKind::NotFound => EXIT_NO_INPUT,
+        AsmError::Io(..) => EXIT_IO,
+        AsmError::Syntax { .. } => EXIT_DATA,
+    }
+}
+
+// Loads the ROM, exiting with the matching status if it can't be used.
+fn load_rom(path: &Path) -> Rom {
+    let loaded = Rom::load(path).and_then(|rom| {
+        rom.check_size(rom.mode())?;
+        Ok(rom)
+    });
+    loaded.unwrap_or_else(|err| {
+        eprintln!("{}: {}", path.display(), err);
+        process::exit(rom_exit_status(&err));
+    })
+}
+
+// Serves one debugging session on stdio, or on the first connection to the port.
+fn serve_dap(port: Option<u16>) -> io::Result<()> {
+    match port {
+        None => chip8rs::dap::serve(io::stdin(), io::stdout()),
+        Some(port) => {
+            let listener = TcpListener::bind(("127.0.0.1", port))?;
+            eprintln!("listening on {}", listener.local_addr()?);
+            let (stream, _) = listener.accept()?;
+            chip8rs::dap::serve(stream.try_clone()?, stream)
+        }
     }
 }
 
 fn main() {
-    let mut buf = [0u8; 4096];
-    let sdl_context = sdl2::init().unwrap();
-    let mut vm = chip8rs::VM::new();
-    let mut ui = chip8rs::UI::new(sdl_context);
+    let options = match cli::parse(env::args().skip(1)) {
+        Ok(cli::Action::Run(options)) => options,
+        Ok(cli::Action::Disasm { rom, dot }) => {
+            let rom = load_rom(&rom);
+            if dot {
+                print!("{}", chip8rs::flow::analyze(&rom.bytes, rom.mode()).to_dot());
+            } else {
+                print!("{}", chip8rs::disasm::disassemble(&rom.bytes, rom.mode()));
+            }
+            return;
+        }
+        Ok(cli::Action::Asm { source, output }) => {
+            let rom = chip8rs::asm::assemble_file(&source).unwrap_or_else(|err| {
+                eprintln!("{}", err);
+                process::exit(asm_exit_status(&err));
+            });
+            if let Err(err) = fs::write(&output, rom) {
+                eprintln!("{}: {}", output.display(), err);
+                process::exit(EXIT_IO);
+            }
+            return;
+        }
+        Ok(cli::Action::Dap { port }) => {
+            if let Err(err) = serve_dap(port) {
+                eprintln!("chip8rs: {}", err);
+                process::exit(EXIT_IO);
+            }
+            return;
+        }
+        Ok(cli::Action::Help) => {
+            print!("{}", cli::USAGE);
+            return;
+        }
+        Err(err) => {
+            eprintln!("chip8rs: {}\n\n{}", err, cli::USAGE);
+            process::exit(EXIT_USAGE);
+        }
+    };
+    let rom_path = options.rom.as_path();
+    let rom = load_rom(rom_path);
+    let mode = rom.mode();
+    // roms.json lists the ROM under its own name, even when it comes from an archive
+    let listed_path = rom_path.with_file_name(rom.name.file_name().unwrap_or_default());
+    let quirks = options.quirks.unwrap_or_else(|| chip8rs::roms::quirks_for(&listed_path, mode.default_quirks()));
 
-    load_rom("games/Landing.ch8", &mut buf);
-    vm.load(&buf);
+    let title = rom.name.file_name().unwrap_or_default().to_string_lossy().into_owned();
 
-    while let Ok(keypad) = ui.poll() {
-        let output = vm.step(keypad);
-        if output.vram_changed {
-            ui.draw(output.vram);
+    let rng = options.rng.open(options.seed.unwrap_or_else(rand::random)).unwrap_or_else(|(err, status)| {
+        eprintln!("--rng: {}", err);
+        process::exit(status);
+    });
+    let vm = chip8rs::VM::with_rng(mode, quirks, rng);
+    let mut emulator = chip8rs::Emulator::new(vm, rom);
+    emulator.set_paused(options.paused);
+    let rewind_length = (options.rewind_seconds as usize).saturating_mul(FRAMES_PER_SECOND as usize);
+    emulator.set_rewind(Rewind::new(rewind_length, (options.rewind_budget as usize).saturating_mul(cli::MIB)));
+    if let Some(ref state_path) = options.load_state {
+        let restored = chip8rs::snapshot::Snapshot::load(state_path).and_then(|s| emulator.vm_mut().restore(&s));
+        if let Err(err) = restored {
+            eprintln!("{}: {}", state_path.display(), err);
+            process::exit(EXIT_DATA);
         }
+    }
 
-        thread::sleep(Duration::from_micros(2000));
+    let sdl_context = sdl2::init().unwrap();
+    let mut ui = chip8rs::UI::new(sdl_context, &title, options.scale, options.fullscreen, options.tone);
+    let mut scheduler = chip8rs::Scheduler::new(options.ips);
+    if options.debug {
+        let mut debugger = Debugger::new(emulator.into_vm());
+        let stdin = io::stdin();
+        if let Err(err) = debugger.repl(stdin.lock(), &mut io::stdout(), &mut ui, &mut scheduler) {
+            eprintln!("chip8rs: {}", err);
+            process::exit(EXIT_IO);
+        }
+        return;
+    }
+    if let Err(err) = emulator.run(&mut ui, &mut scheduler) {
+        eprintln!("{}: {}", rom_path.display(), err);
+        process::exit(EXIT_FAULT);
     }
 }